@@ -1,8 +1,81 @@
+use std::fmt;
+
+use crate::span::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Const {
+    Int(i32),
+    Long(i64),
+    UInt(u32),
+    ULong(u64),
+    Double(f64),
+    Char(i8),
+    UChar(u8),
+}
+
+impl fmt::Display for Const {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Const::Int(v) => write!(f, "{}", v),
+            Const::Long(v) => write!(f, "{}", v),
+            Const::UInt(v) => write!(f, "{}", v),
+            Const::ULong(v) => write!(f, "{}", v),
+            Const::Double(v) => write!(f, "{}", v),
+            Const::Char(v) => write!(f, "{}", v),
+            Const::UChar(v) => write!(f, "{}", v),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq)]
-pub enum Expression {
-    Constant(i32),
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub ty: Option<Type>, // filled in by the type checker
+    pub span: Span,
+}
+
+impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Self {
+            kind,
+            ty: None,
+            span,
+        }
+    }
+
+    pub fn typed(kind: ExpressionKind, ty: Type, span: Span) -> Self {
+        Self {
+            kind,
+            ty: Some(ty),
+            span,
+        }
+    }
+
+    /// The type assigned by the type checker.
+    pub fn ty(&self) -> &Type {
+        self.ty.as_ref().expect("expression has not been type checked")
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ExpressionKind {
+    Constant(Const),
+    Var(String),
+    String(Vec<u8>), // string literal, without the terminating null
+    Cast(Type, Box<Expression>), // target type, expr
+    Assignment(Box<Expression>, Box<Expression>), // lvalue, value
+    CompoundAssignment(BinOp, Box<Expression>, Box<Expression>), // op, lvalue, value
+    PostfixIncrement(Box<Expression>), // lvalue++
+    PostfixDecrement(Box<Expression>), // lvalue--
+    Conditional(Box<Expression>, Box<Expression>, Box<Expression>), // cond ? then : else
     UnaryOp((UnOp,Box<Expression>)),    
     BinaryOp(BinOp, Box<Expression>, Box<Expression>),
+    FunctionCall(String, Vec<Expression>), // name, args
+    Dereference(Box<Expression>),          // *expr
+    AddressOf(Box<Expression>),            // &expr
+    Subscript(Box<Expression>, Box<Expression>), // expr[index]
+    Member(Box<Expression>, String),             // expr.member
+    PointerMember(Box<Expression>, String),      // expr->member
 }
 
 #[derive(Debug, PartialEq)]
@@ -36,17 +109,246 @@ pub enum BinOp {
 }
 
 #[derive(Debug, PartialEq)]
-pub enum Statement {
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+impl Statement {
+    pub fn new(kind: StatementKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StatementKind {
     Return(Expression),
+    Expression(Expression),
+    If(Expression, Box<Statement>, Option<Box<Statement>>), // cond, then, else
+    Compound(Block),
+    While(Expression, Box<Statement>, String),   // cond, body, loop label
+    DoWhile(Box<Statement>, Expression, String), // body, cond, loop label
+    For(
+        Box<ForInit>,
+        Option<Expression>, // cond
+        Option<Expression>, // post
+        Box<Statement>,
+        String, // loop label
+    ),
+    Switch(Expression, Box<Statement>, String, Vec<SwitchCase>), // cond, body, label, cases
+    Case(Expression, Box<Statement>, String), // value, statement, label
+    Default(Box<Statement>, String),          // statement, label
+    Labeled(String, Box<Statement>),          // label, statement
+    Goto(String),                             // label
+    Break(String),    // label of the enclosing loop or switch
+    Continue(String), // label of the enclosing loop
+    Null,             // ;
+}
+
+/// A `case` of a switch, or its `default` when there is no value, and the
+/// label it jumps to. Filled in by the type checker, once the values have
+/// been converted to the type of the controlling expression.
+#[derive(Debug, PartialEq)]
+pub struct SwitchCase {
+    pub value: Option<Const>,
+    pub label: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ForInit {
+    Declaration(VariableDeclaration),
+    Expression(Option<Expression>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Initializer {
+    Single(Expression),
+    Compound(Vec<Initializer>), // { a, b, ... }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct VariableDeclaration {
+    pub name: String,
+    pub ty: Type,
+    pub init: Option<Initializer>,
+    pub storage_class: Option<StorageClass>,
+    pub span: Span, // the declared name
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageClass {
+    Static,
+    Extern,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StructKind {
+    Struct,
+    Union,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MemberDeclaration {
+    pub name: String,
+    pub ty: Type,
+    pub span: Span, // the member name
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StructDeclaration {
+    pub kind: StructKind,
+    pub tag: String,
+    pub members: Option<Vec<MemberDeclaration>>, // None for a forward declaration
+    pub span: Span, // the `struct tag` specifier
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Declaration {
+    Variable(VariableDeclaration),
+    Function(FunctionDeclaration),
+    Struct(StructDeclaration),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BlockItem {
+    Statement(Statement),
+    Declaration(Declaration),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Block {
+    pub items: Vec<BlockItem>,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct FunctionDeclaration {
     pub name: String,
-    pub body: Statement,
+    pub params: Vec<String>,
+    pub fun_type: FunctionType,
+    pub body: Option<Block>, // None for a forward declaration
+    pub storage_class: Option<StorageClass>,
+    pub span: Span, // the declared name
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Char,
+    SChar,
+    UChar,
+    Int,
+    Long,
+    UInt,
+    ULong,
+    Double,
+    Pointer(Box<Type>), // referenced type
+    Array(Box<Type>, usize), // element type, length
+    Structure(StructKind, String), // struct or union, tag
+    Function(FunctionType),
+}
+
+impl Type {
+    /// Size in bytes of a value of this type. Structure layouts are only
+    /// known to the symbol table, see `SymbolTable::size_of`.
+    pub fn size(&self) -> usize {
+        match self {
+            Type::Char | Type::SChar | Type::UChar => 1,
+            Type::Int | Type::UInt => 4,
+            Type::Long | Type::ULong | Type::Double | Type::Pointer(_) => 8,
+            Type::Array(element, len) => element.size() * len,
+            Type::Structure(..) => unreachable!("structure sizes live in the symbol table"),
+            Type::Function(_) => unreachable!("functions have no size"),
+        }
+    }
+
+    /// Signedness of an integer type; false for anything else.
+    pub fn is_signed(&self) -> bool {
+        // Plain char is signed on x86-64
+        matches!(self, Type::Char | Type::SChar | Type::Int | Type::Long)
+    }
+
+    pub fn is_character(&self) -> bool {
+        matches!(self, Type::Char | Type::SChar | Type::UChar)
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.is_character() || matches!(self, Type::Int | Type::Long | Type::UInt | Type::ULong)
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        self.is_integer() || matches!(self, Type::Double)
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, Type::Pointer(_))
+    }
+
+    pub fn is_array(&self) -> bool {
+        matches!(self, Type::Array(..))
+    }
+
+    pub fn is_structure(&self) -> bool {
+        matches!(self, Type::Structure(..))
+    }
+
+    /// Arithmetic types and pointers, the only types that can be tested
+    /// against zero.
+    pub fn is_scalar(&self) -> bool {
+        self.is_arithmetic() || self.is_pointer()
+    }
+}
+
+/// Formats the type the way it is spelled in C, e.g. `unsigned long *`.
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Char => write!(f, "char"),
+            Type::SChar => write!(f, "signed char"),
+            Type::UChar => write!(f, "unsigned char"),
+            Type::Int => write!(f, "int"),
+            Type::Long => write!(f, "long"),
+            Type::UInt => write!(f, "unsigned int"),
+            Type::ULong => write!(f, "unsigned long"),
+            Type::Double => write!(f, "double"),
+            Type::Pointer(inner) if inner.is_pointer() => write!(f, "{}*", inner),
+            Type::Pointer(inner) => write!(f, "{} *", inner),
+            Type::Array(..) => {
+                // The outermost length is written first: int[2][3]
+                let mut element = self;
+                let mut lengths = String::new();
+                while let Type::Array(inner, len) = element {
+                    lengths.push_str(&format!("[{}]", len));
+                    element = inner;
+                }
+                write!(f, "{}{}", element, lengths)
+            }
+            // Tags are uniquely renamed during semantic analysis: s.3 -> s
+            Type::Structure(kind, tag) => {
+                let keyword = match kind {
+                    StructKind::Struct => "struct",
+                    StructKind::Union => "union",
+                };
+                let name = tag.split_once('.').map_or(tag.as_str(), |(name, _)| name);
+                write!(f, "{} {}", keyword, name)
+            }
+            Type::Function(fun_type) => {
+                let params: Vec<String> = fun_type.params.iter().map(Type::to_string).collect();
+                if params.is_empty() {
+                    write!(f, "{} (void)", fun_type.ret)
+                } else {
+                    write!(f, "{} ({})", fun_type.ret, params.join(", "))
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionType {
+    pub params: Vec<Type>,
+    pub ret: Box<Type>,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Program {
-    pub function: FunctionDeclaration,
+    pub declarations: Vec<Declaration>,
 }
@@ -1,56 +1,168 @@
 use std::{collections::HashMap, fmt};
 
+use crate::ast::{Const, Type};
 use crate::ir::{self};
+use crate::typecheck::{StaticInit, SymbolTable};
+
+/// Operand size of an instruction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AsmType {
+    Byte,
+    Longword, // 32-bit (int)
+    Quadword, // 64-bit (long)
+    Double,   // 64-bit in an xmm register
+    ByteArray { size: i32, alignment: i32 }, // aggregates, only ever in memory
+}
+
+impl AsmType {
+    fn from_type(ty: &Type, symbols: &SymbolTable) -> AsmType {
+        match ty {
+            Type::Char | Type::SChar | Type::UChar => AsmType::Byte,
+            Type::Int | Type::UInt => AsmType::Longword,
+            Type::Long | Type::ULong | Type::Pointer(_) => AsmType::Quadword,
+            Type::Double => AsmType::Double,
+            // System V aligns arrays of 16 bytes or more to 16
+            Type::Array(element, _) => {
+                let size = symbols.size_of(ty) as i32;
+                let alignment = if size >= 16 {
+                    16
+                } else {
+                    AsmType::from_type(element, symbols).alignment()
+                };
+                AsmType::ByteArray { size, alignment }
+            }
+            Type::Structure(..) => AsmType::ByteArray {
+                size: symbols.size_of(ty) as i32,
+                alignment: symbols.alignment_of(ty) as i32,
+            },
+            Type::Function(_) => unreachable!("functions have no operand size"),
+        }
+    }
+
+    fn size(&self) -> i32 {
+        match self {
+            AsmType::Byte => 1,
+            AsmType::Longword => 4,
+            AsmType::Quadword | AsmType::Double => 8,
+            AsmType::ByteArray { size, .. } => *size,
+        }
+    }
+
+    fn alignment(&self) -> i32 {
+        match self {
+            AsmType::ByteArray { alignment, .. } => *alignment,
+            _ => self.size(),
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Reg {
     Rax,
     Rcx,
     Rdx,
+    Rdi,
+    Rsi,
+    R8,
+    R9,
     R10,
     R11,
     Rsp,
-    Rbp, // 64-bit
-    Al,
-    Cl,
-    Dl,
-    R10b,
-    R11b,
-    Spl,
-    Bpl, // 8-bit (Low Byte)
+    Rbp,
+    Xmm0,
+    Xmm1,
+    Xmm2,
+    Xmm3,
+    Xmm4,
+    Xmm5,
+    Xmm6,
+    Xmm7,
+    Xmm14,
+    Xmm15,
 }
 impl Reg {
-    pub fn to_byte_reg(&self) -> Reg {
-        match self {
-            // 64-bit -> 8-bit (Low Byte)
-            Reg::Rax => Reg::Al,
-            Reg::Rcx => Reg::Cl,
-            Reg::Rdx => Reg::Dl,
-            Reg::R10 => Reg::R10b,
-            Reg::R11 => Reg::R11b,
-            Reg::Rsp => Reg::Spl,
-            Reg::Rbp => Reg::Bpl,
-
-            // Already 8-bit (Identity)
-            Reg::Al => Reg::Al,
-            Reg::Cl => Reg::Cl,
-            Reg::Dl => Reg::Dl,
-            Reg::R10b => Reg::R10b,
-            Reg::R11b => Reg::R11b,
-            Reg::Spl => Reg::Spl,
-            Reg::Bpl => Reg::Bpl,
+    /// Name of the register when accessed with the given size.
+    fn name(&self, ty: AsmType) -> &'static str {
+        let names = match self {
+            Reg::Xmm0 => return "xmm0",
+            Reg::Xmm1 => return "xmm1",
+            Reg::Xmm2 => return "xmm2",
+            Reg::Xmm3 => return "xmm3",
+            Reg::Xmm4 => return "xmm4",
+            Reg::Xmm5 => return "xmm5",
+            Reg::Xmm6 => return "xmm6",
+            Reg::Xmm7 => return "xmm7",
+            Reg::Xmm14 => return "xmm14",
+            Reg::Xmm15 => return "xmm15",
+            Reg::Rax => ["rax", "eax", "al"],
+            Reg::Rcx => ["rcx", "ecx", "cl"],
+            Reg::Rdx => ["rdx", "edx", "dl"],
+            Reg::Rdi => ["rdi", "edi", "dil"],
+            Reg::Rsi => ["rsi", "esi", "sil"],
+            Reg::R8 => ["r8", "r8d", "r8b"],
+            Reg::R9 => ["r9", "r9d", "r9b"],
+            Reg::R10 => ["r10", "r10d", "r10b"],
+            Reg::R11 => ["r11", "r11d", "r11b"],
+            Reg::Rsp => ["rsp", "esp", "spl"],
+            Reg::Rbp => ["rbp", "ebp", "bpl"],
+        };
+        match ty {
+            AsmType::Quadword | AsmType::Double => names[0],
+            AsmType::Longword => names[1],
+            AsmType::Byte => names[2],
+            AsmType::ByteArray { .. } => unreachable!("aggregates don't fit in a register"),
         }
     }
 }
+// System V integer argument registers, in order
+const ARG_REGISTERS: [Reg; 6] = [Reg::Rdi, Reg::Rsi, Reg::Rdx, Reg::Rcx, Reg::R8, Reg::R9];
+// ... and the ones for doubles
+const XMM_ARG_REGISTERS: [Reg; 8] = [
+    Reg::Xmm0,
+    Reg::Xmm1,
+    Reg::Xmm2,
+    Reg::Xmm3,
+    Reg::Xmm4,
+    Reg::Xmm5,
+    Reg::Xmm6,
+    Reg::Xmm7,
+];
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Operand {
-    Imm(i32),
+    Imm(i64),
     Reg(Reg),
     Pseudo(String),
-    StackQWord(i32),
-    StackByte(i32),
+    PseudoMem(String, i32), // byte offset into an aggregate pseudo
+    Stack(i32),             // offset from rbp
+    Memory(Reg, i32),       // offset from the address in a register
+    Indexed(Reg, Reg, i32), // base + index * scale
+    Data(String, i32),      // RIP-relative static storage, byte offset
+}
+
+impl Operand {
+    fn is_memory(&self) -> bool {
+        matches!(
+            self,
+            Operand::Stack(_) | Operand::Memory(..) | Operand::Indexed(..) | Operand::Data(..)
+        )
+    }
+
+    /// Immediates that don't fit the sign-extended 32-bit field most
+    /// instructions take.
+    fn is_large_imm(&self) -> bool {
+        matches!(self, Operand::Imm(val) if i32::try_from(*val).is_err())
+    }
+
+    /// Wraps the operand for printing at the given size.
+    fn sized(&self, ty: AsmType) -> Sized<'_> {
+        Sized(self, ty)
+    }
 }
 
+/// An operand together with the size it is accessed with.
+pub struct Sized<'a>(&'a Operand, AsmType);
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum CondCode {
     MP, //uncond
@@ -60,24 +172,40 @@ pub enum CondCode {
     GE,
     L,
     LE,
+    A, // unsigned comparisons
+    AE,
+    B,
+    BE,
+    P, // parity, set when comparing against NaN
+    NP,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
-    Mov(Operand, Operand),  // mov dst, src
-    Add(Operand, Operand),  // add dst, src
-    Sub(Operand, Operand),  // sub dst, src
-    Imul(Operand, Operand), // imul dst, src
-    Idiv(Operand),          // idiv src (implicit rax/rdx)
-    And(Operand, Operand),  // add dst, src
-    Or(Operand, Operand),   // add dst, src
-    Xor(Operand, Operand),  // add dst, src
-    Sal(Operand, Operand),
-    Sar(Operand, Operand),
+    Mov(AsmType, Operand, Operand),  // mov dst, src
+    Movsx(AsmType, AsmType, Operand, Operand), // src type, dst type, dst, src
+    MovZeroExtend(AsmType, AsmType, Operand, Operand), // src type, dst type, dst, src
+    Lea(Operand, Operand),           // lea dst, src (address of src)
+    Add(AsmType, Operand, Operand),  // add dst, src
+    Sub(AsmType, Operand, Operand),  // sub dst, src
+    Imul(AsmType, Operand, Operand), // imul dst, src
+    Idiv(AsmType, Operand),          // idiv src (implicit rax/rdx)
+    Div(AsmType, Operand),           // div src (unsigned idiv)
+    And(AsmType, Operand, Operand),  // and dst, src
+    Or(AsmType, Operand, Operand),   // or dst, src
+    Xor(AsmType, Operand, Operand),  // xor dst, src
+    Sal(AsmType, Operand, Operand),  // sal dst, count
+    Sar(AsmType, Operand, Operand),  // sar dst, count
+    Shr(AsmType, Operand, Operand),  // shr dst, count
+    DivDouble(Operand, Operand),     // divsd dst, src
+
+    // Conversions between doubles and integers of the given size
+    Cvttsd2si(AsmType, Operand, Operand), // dst, src
+    Cvtsi2sd(AsmType, Operand, Operand),  // dst, src
 
     // Unary
-    Neg(Operand), // neg dst
-    Not(Operand), // not dst
+    Neg(AsmType, Operand), // neg dst
+    Not(AsmType, Operand), // not dst
 
     // Stack
     Push(Operand),
@@ -85,56 +213,126 @@ pub enum Instruction {
 
     // Control / Comparison
     Ret,
-    Cmp(Operand, Operand),
+    Cmp(AsmType, Operand, Operand),
     SetCC(CondCode, Operand),
     JumpCC(CondCode, String),
+    JumpIndirect(Operand), // jmp to the address in a register
     Label(String),
-    Cqo, // Sign extend rax into rdx:rax
+    Call(String, bool), // name, whether to go through the PLT
+    Cdq(AsmType), // Sign extend eax into edx:eax, or rax into rdx:rax (cqo)
 }
 
 #[derive(Debug)]
 pub struct Function {
     pub name: String,
+    pub global: bool,
     pub instructions: Vec<Instruction>,
 }
 
+#[derive(Debug)]
+pub struct StaticVariable {
+    pub name: String,
+    pub global: bool,
+    pub alignment: i32,
+    pub init: Vec<StaticInit>,
+}
+
+/// Read-only data under a local label: a floating-point constant or a
+/// string literal.
+#[derive(Debug)]
+pub struct StaticConstant {
+    pub name: String,
+    pub alignment: i32,
+    pub init: StaticInit,
+}
+
+/// The targets of a switch, each stored as its offset from the table so the
+/// code stays position independent.
+#[derive(Debug)]
+pub struct JumpTable {
+    pub name: String,
+    pub targets: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum TopLevel {
+    Function(Function),
+    StaticVariable(StaticVariable),
+    StaticConstant(StaticConstant),
+    JumpTable(JumpTable),
+}
+
 #[derive(Debug)]
 pub struct Program {
-    pub function: Function,
+    pub top_level: Vec<TopLevel>,
 }
 
-impl fmt::Display for Reg {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl Instruction {
+    /// The operands the instruction reads or writes.
+    fn operands(&self) -> Vec<&Operand> {
         match self {
-            // 64-bit registers
-            Reg::Rax => write!(f, "rax"),
-            Reg::Rcx => write!(f, "rcx"),
-            Reg::Rdx => write!(f, "rdx"),
-            Reg::R10 => write!(f, "r10"),
-            Reg::R11 => write!(f, "r11"),
-            Reg::Rsp => write!(f, "rsp"),
-            Reg::Rbp => write!(f, "rbp"),
-
-            // 8-bit registers (Low Byte)
-            Reg::Al => write!(f, "al"),
-            Reg::Cl => write!(f, "cl"),
-            Reg::Dl => write!(f, "dl"),
-            Reg::R10b => write!(f, "r10b"),
-            Reg::R11b => write!(f, "r11b"),
-            Reg::Spl => write!(f, "spl"),
-            Reg::Bpl => write!(f, "bpl"),
+            Instruction::Mov(_, dst, src)
+            | Instruction::Movsx(_, _, dst, src)
+            | Instruction::MovZeroExtend(_, _, dst, src)
+            | Instruction::Lea(dst, src)
+            | Instruction::Cvttsd2si(_, dst, src)
+            | Instruction::Cvtsi2sd(_, dst, src)
+            | Instruction::Add(_, dst, src)
+            | Instruction::Sub(_, dst, src)
+            | Instruction::Imul(_, dst, src)
+            | Instruction::DivDouble(dst, src)
+            | Instruction::And(_, dst, src)
+            | Instruction::Or(_, dst, src)
+            | Instruction::Xor(_, dst, src)
+            | Instruction::Sal(_, dst, src)
+            | Instruction::Sar(_, dst, src)
+            | Instruction::Shr(_, dst, src)
+            | Instruction::Cmp(_, dst, src) => vec![dst, src],
+            Instruction::Idiv(_, op)
+            | Instruction::Div(_, op)
+            | Instruction::Neg(_, op)
+            | Instruction::Not(_, op)
+            | Instruction::Push(op)
+            | Instruction::Pop(op)
+            | Instruction::SetCC(_, op)
+            | Instruction::JumpIndirect(op) => vec![op],
+            Instruction::JumpCC(..)
+            | Instruction::Label(_)
+            | Instruction::Call(..)
+            | Instruction::Ret
+            | Instruction::Cdq(_) => vec![],
         }
     }
 }
 
-impl fmt::Display for Operand {
+impl fmt::Display for Sized<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
+        let ptr = match self.1 {
+            AsmType::Byte => "BYTE PTR",
+            AsmType::Longword => "DWORD PTR",
+            AsmType::Quadword | AsmType::Double => "QWORD PTR",
+            AsmType::ByteArray { .. } => unreachable!("aggregates are never accessed whole"),
+        };
+        match self.0 {
             Operand::Imm(val) => write!(f, "{}", val),
-            Operand::Reg(reg) => write!(f, "{}", reg),
-            Operand::StackQWord(offset) => write!(f, "QWORD PTR [rbp{}]", offset),
-            Operand::StackByte(offset) => write!(f, "BYTE PTR [rbp{}]", offset),
-            Operand::Pseudo(_) => unreachable!(),
+            Operand::Reg(reg) => write!(f, "{}", reg.name(self.1)),
+            Operand::Stack(offset) => write!(f, "{} [rbp{:+}]", ptr, offset),
+            Operand::Memory(reg, offset) => {
+                write!(f, "{} [{}{:+}]", ptr, reg.name(AsmType::Quadword), offset)
+            }
+            Operand::Indexed(base, index, scale) => write!(
+                f,
+                "{} [{}+{}*{}]",
+                ptr,
+                base.name(AsmType::Quadword),
+                index.name(AsmType::Quadword),
+                scale
+            ),
+            Operand::Data(name, 0) => write!(f, "{} {}[rip]", ptr, symbol_ref(name)),
+            Operand::Data(name, offset) => {
+                write!(f, "{} {}{:+}[rip]", ptr, symbol_ref(name), offset)
+            }
+            Operand::Pseudo(_) | Operand::PseudoMem(..) => unreachable!(),
         }
     }
 }
@@ -149,42 +347,131 @@ impl fmt::Display for CondCode {
             CondCode::GE => write! {f,"ge"},
             CondCode::L => write! {f,"l"},
             CondCode::LE => write! {f,"le"},
+            CondCode::A => write! {f,"a"},
+            CondCode::AE => write! {f,"ae"},
+            CondCode::B => write! {f,"b"},
+            CondCode::BE => write! {f,"be"},
+            CondCode::P => write! {f,"p"},
+            CondCode::NP => write! {f,"np"},
         }
     }
 }
 
 impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use AsmType::{Byte, Double, Longword, Quadword};
         match self {
-            Instruction::Mov(dst, src) => write!(f, "    mov {}, {}", dst, src),
-            Instruction::Add(dst, src) => write!(f, "    add {}, {}", dst, src),
-            Instruction::And(dst, src) => write!(f, "    and {}, {}", dst, src),
-            Instruction::Or(dst, src) => write!(f, "    or {}, {}", dst, src),
-            Instruction::Xor(dst, src) => write!(f, "    xor {}, {}", dst, src),
-            Instruction::Sub(dst, src) => write!(f, "    sub {}, {}", dst, src),
-            Instruction::Imul(dst, src) => write!(f, "    imul {}, {}", dst, src),
-
-            Instruction::Sal(dst, src) => write!(f, "    sal {}, {}", dst, src),
-            Instruction::Sar(dst, src) => write!(f, "    sar {}, {}", dst, src),
-            Instruction::Cmp(dst, src) => write!(f, "    cmp {}, {}", dst, src),
-
-            Instruction::Idiv(op) => write!(f, "    idiv {}", op),
-            Instruction::Neg(op) => write!(f, "    neg {}", op),
-            Instruction::Not(op) => write!(f, "    not {}", op),
-            Instruction::Push(op) => write!(f, "    push {}", op),
-            Instruction::Pop(op) => write!(f, "    pop {}", op),
-            Instruction::SetCC(cc, op) => write!(f, "    set{} {}", cc, op),
+            Instruction::Mov(Double, dst, src) => {
+                write!(f, "    movsd {}, {}", dst.sized(Double), src.sized(Double))
+            }
+            Instruction::Add(Double, dst, src) => {
+                write!(f, "    addsd {}, {}", dst.sized(Double), src.sized(Double))
+            }
+            Instruction::Sub(Double, dst, src) => {
+                write!(f, "    subsd {}, {}", dst.sized(Double), src.sized(Double))
+            }
+            Instruction::Imul(Double, dst, src) => {
+                write!(f, "    mulsd {}, {}", dst.sized(Double), src.sized(Double))
+            }
+            Instruction::DivDouble(dst, src) => {
+                write!(f, "    divsd {}, {}", dst.sized(Double), src.sized(Double))
+            }
+            Instruction::Xor(Double, dst, src) => {
+                write!(f, "    xorpd {}, {}", dst.sized(Double), src.sized(Double))
+            }
+            Instruction::Cmp(Double, dst, src) => {
+                write!(f, "    comisd {}, {}", dst.sized(Double), src.sized(Double))
+            }
+            Instruction::Cvttsd2si(t, dst, src) => {
+                write!(f, "    cvttsd2si {}, {}", dst.sized(*t), src.sized(Double))
+            }
+            Instruction::Cvtsi2sd(t, dst, src) => {
+                write!(f, "    cvtsi2sd {}, {}", dst.sized(Double), src.sized(*t))
+            }
+
+            Instruction::Mov(t, dst, src) => {
+                write!(f, "    mov {}, {}", dst.sized(*t), src.sized(*t))
+            }
+            Instruction::Movsx(Longword, Quadword, dst, src) => write!(
+                f,
+                "    movsxd {}, {}",
+                dst.sized(Quadword),
+                src.sized(Longword)
+            ),
+            Instruction::Movsx(src_t, dst_t, dst, src) => {
+                write!(f, "    movsx {}, {}", dst.sized(*dst_t), src.sized(*src_t))
+            }
+            Instruction::MovZeroExtend(Byte, dst_t, dst, src) => {
+                write!(f, "    movzx {}, {}", dst.sized(*dst_t), src.sized(Byte))
+            }
+            Instruction::Lea(dst, src) => write!(
+                f,
+                "    lea {}, {}",
+                dst.sized(Quadword),
+                src.sized(Quadword)
+            ),
+            Instruction::Add(t, dst, src) => {
+                write!(f, "    add {}, {}", dst.sized(*t), src.sized(*t))
+            }
+            Instruction::And(t, dst, src) => {
+                write!(f, "    and {}, {}", dst.sized(*t), src.sized(*t))
+            }
+            Instruction::Or(t, dst, src) => {
+                write!(f, "    or {}, {}", dst.sized(*t), src.sized(*t))
+            }
+            Instruction::Xor(t, dst, src) => {
+                write!(f, "    xor {}, {}", dst.sized(*t), src.sized(*t))
+            }
+            Instruction::Sub(t, dst, src) => {
+                write!(f, "    sub {}, {}", dst.sized(*t), src.sized(*t))
+            }
+            Instruction::Imul(t, dst, src) => {
+                write!(f, "    imul {}, {}", dst.sized(*t), src.sized(*t))
+            }
+
+            // Shift counts are an immediate or cl
+            Instruction::Sal(t, dst, src) => {
+                write!(f, "    sal {}, {}", dst.sized(*t), src.sized(Byte))
+            }
+            Instruction::Sar(t, dst, src) => {
+                write!(f, "    sar {}, {}", dst.sized(*t), src.sized(Byte))
+            }
+            Instruction::Shr(t, dst, src) => {
+                write!(f, "    shr {}, {}", dst.sized(*t), src.sized(Byte))
+            }
+            Instruction::Cmp(t, dst, src) => {
+                write!(f, "    cmp {}, {}", dst.sized(*t), src.sized(*t))
+            }
+
+            Instruction::Idiv(t, op) => write!(f, "    idiv {}", op.sized(*t)),
+            Instruction::Div(t, op) => write!(f, "    div {}", op.sized(*t)),
+            Instruction::Neg(t, op) => write!(f, "    neg {}", op.sized(*t)),
+            Instruction::Not(t, op) => write!(f, "    not {}", op.sized(*t)),
+            Instruction::Push(op) => write!(f, "    push {}", op.sized(Quadword)),
+            Instruction::Pop(op) => write!(f, "    pop {}", op.sized(Quadword)),
+            Instruction::SetCC(cc, op) => write!(f, "    set{} {}", cc, op.sized(Byte)),
             Instruction::JumpCC(cc, op) => write!(f, "    j{} {}", cc, op),
+            Instruction::JumpIndirect(op) => write!(f, "    jmp {}", op.sized(Quadword)),
+
+            // Zero extending a longword is removed by fix_instructions
+            Instruction::MovZeroExtend(..) => unreachable!(),
 
             Instruction::Label(label) => write!(f, "{}:", label),
             Instruction::Ret => write!(f, "    ret"),
-            Instruction::Cqo => write!(f, "    cqo"),
+            Instruction::Call(name, false) => write!(f, "    call {}", symbol_ref(name)),
+            Instruction::Call(name, true) => write!(f, "    call {}@PLT", symbol_ref(name)),
+            Instruction::Cdq(Quadword) => write!(f, "    cqo"),
+            Instruction::Cdq(_) => write!(f, "    cdq"),
         }
     }
 }
 impl fmt::Display for Function {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        writeln!(f, "{}:", self.name)?;
+        if self.global {
+            writeln!(f, "    .globl \"{}\"", self.name)?;
+        }
+        writeln!(f, "    .text")?;
+        writeln!(f, "\"{}\":", self.name)?;
 
         for instr in &self.instructions {
             writeln!(f, "{}", instr)?;
@@ -194,138 +481,736 @@ impl fmt::Display for Function {
     }
 }
 
+impl fmt::Display for StaticVariable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.global {
+            writeln!(f, "    .globl \"{}\"", self.name)?;
+        }
+
+        // Zero initialized variables take no space in the object file
+        if self.init.iter().all(StaticInit::is_zero) {
+            let size: usize = self.init.iter().map(StaticInit::size).sum();
+            writeln!(f, "    .bss")?;
+            writeln!(f, "    .align {}", self.alignment)?;
+            writeln!(f, "\"{}\":", self.name)?;
+            writeln!(f, "    .zero {}", size)
+        } else {
+            writeln!(f, "    .data")?;
+            writeln!(f, "    .align {}", self.alignment)?;
+            writeln!(f, "\"{}\":", self.name)?;
+            for init in &self.init {
+                write_static_init(f, init)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+impl fmt::Display for StaticConstant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "    .section .rodata")?;
+        writeln!(f, "    .align {}", self.alignment)?;
+        writeln!(f, "{}:", self.name)?;
+        write_static_init(f, &self.init)
+    }
+}
+
+impl fmt::Display for JumpTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "    .section .rodata")?;
+        writeln!(f, "    .align 4")?;
+        writeln!(f, "{}:", self.name)?;
+        for target in &self.targets {
+            writeln!(f, "    .long {}-{}", target, self.name)?;
+        }
+        Ok(())
+    }
+}
+
+fn write_static_init(f: &mut fmt::Formatter, init: &StaticInit) -> fmt::Result {
+    match init {
+        StaticInit::Char(val) => writeln!(f, "    .byte {}", val),
+        StaticInit::UChar(val) => writeln!(f, "    .byte {}", val),
+        StaticInit::Int(val) => writeln!(f, "    .long {}", val),
+        StaticInit::Long(val) => writeln!(f, "    .quad {}", val),
+        StaticInit::UInt(val) => writeln!(f, "    .long {}", val),
+        StaticInit::ULong(val) => writeln!(f, "    .quad {}", val),
+        // Emitted as raw bits so the value round-trips exactly
+        StaticInit::Double(val) => writeln!(f, "    .quad {}", val.to_bits()),
+        StaticInit::Zero(bytes) => writeln!(f, "    .zero {}", bytes),
+        StaticInit::String(bytes, true) => writeln!(f, "    .asciz \"{}\"", escape(bytes)),
+        StaticInit::String(bytes, false) => writeln!(f, "    .ascii \"{}\"", escape(bytes)),
+        StaticInit::Pointer(name) => writeln!(f, "    .quad {}", constant_label(name)),
+    }
+}
+
+/// Quotes bytes for a string directive; anything but printable ASCII is
+/// written as an octal escape.
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => {
+                escaped.push('\\');
+                escaped.push(byte as char);
+            }
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => escaped.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    escaped
+}
+
+/// Constants from the symbol table (string literals) only need to be visible
+/// within the file, so they get local labels.
+fn constant_label(name: &str) -> String {
+    format!(".L{}", name)
+}
+
+/// How instructions refer to a symbol. In Intel syntax the assembler reads
+/// names like `rax`, `and` or `offset` in an operand as registers and
+/// operators even when quoted, so C symbols are referred to through a
+/// local alias (see `write_symbol_aliases`). Local labels need none.
+fn symbol_ref(name: &str) -> String {
+    if name.starts_with(".L") {
+        name.to_string()
+    } else {
+        // No generated label starts with `.L.`
+        format!(".L.{}", name)
+    }
+}
+
+/// Defines the aliases `symbol_ref` uses for every C symbol the program
+/// refers to. AT&T syntax has no reserved names, so the real symbol can be
+/// named there; the alias resolves to it, relocations included.
+fn write_symbol_aliases(f: &mut fmt::Formatter, functions: &[&Function]) -> fmt::Result {
+    let mut names: Vec<&str> = Vec::new();
+    for instruction in functions.iter().flat_map(|function| &function.instructions) {
+        let referenced = instruction.operands().into_iter().filter_map(|op| match op {
+            Operand::Data(name, _) => Some(name),
+            _ => None,
+        });
+        let called = match instruction {
+            Instruction::Call(name, _) => Some(name),
+            _ => None,
+        };
+        for name in referenced.chain(called) {
+            if !name.starts_with(".L") && !names.contains(&name.as_str()) {
+                names.push(name);
+            }
+        }
+    }
+
+    if names.is_empty() {
+        return Ok(());
+    }
+    writeln!(f, "    .att_syntax")?;
+    for name in names {
+        writeln!(f, "    .set {}, {}", symbol_ref(name), name)?;
+    }
+    writeln!(f, "    .intel_syntax noprefix")
+}
+
 impl fmt::Display for Program {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         writeln!(f, "    .intel_syntax noprefix")?;
 
-        writeln!(f, "    .globl {}", self.function.name)?;
-
-        write!(f, "{}", self.function)?;
+        let mut functions = Vec::new();
+        for item in &self.top_level {
+            match item {
+                TopLevel::Function(function) => {
+                    write!(f, "{}", function)?;
+                    functions.push(function);
+                }
+                TopLevel::StaticVariable(var) => write!(f, "{}", var)?,
+                TopLevel::StaticConstant(constant) => write!(f, "{}", constant)?,
+                TopLevel::JumpTable(table) => write!(f, "{}", table)?,
+            }
+        }
+        write_symbol_aliases(f, &functions)?;
 
         writeln!(f, "    .section .note.GNU-stack,\"\",@progbits")
     }
 }
 
-fn select_instructions(ir_fn: &ir::Function) -> Vec<Instruction> {
+/// State shared by every function: the floating-point constants and jump
+/// tables they reference and a counter for labels created during
+/// instruction selection.
+#[derive(Default)]
+struct Context {
+    constants: Vec<StaticConstant>,
+    jump_tables: Vec<JumpTable>,
+    label_counter: usize,
+}
+
+impl Context {
+    /// A read-only constant holding `val`, shared between identical values.
+    fn constant(&mut self, val: f64) -> Operand {
+        let existing = self.constants.iter().find(
+            |c| matches!(c.init, StaticInit::Double(init) if init.to_bits() == val.to_bits()),
+        );
+
+        let name = match existing {
+            Some(constant) => constant.name.clone(),
+            None => {
+                let name = format!(".Ldouble.{}", self.constants.len());
+                self.constants.push(StaticConstant {
+                    name: name.clone(),
+                    alignment: 8,
+                    init: StaticInit::Double(val),
+                });
+                name
+            }
+        };
+        Operand::Data(name, 0)
+    }
+
+    fn make_label(&mut self, label: &str) -> String {
+        let name = format!(".L{}.{}", label, self.label_counter);
+        self.label_counter += 1;
+        name
+    }
+
+    fn jump_table(&mut self, targets: Vec<String>) -> Operand {
+        let name = self.make_label("switch_table");
+        self.jump_tables.push(JumpTable {
+            name: name.clone(),
+            targets,
+        });
+        Operand::Data(name, 0)
+    }
+}
+
+/// A function argument or parameter and its size.
+type Arg = (Operand, AsmType);
+
+/// System V class of an eightbyte of a structure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Class {
+    Memory,
+    Sse,
+    Integer,
+}
+
+/// Classifies each eightbyte of a structure: one holding nothing but doubles
+/// is passed in an xmm register and any other in a general purpose one,
+/// while structures over 16 bytes are passed in memory.
+fn classify_structure(ty: &Type, symbols: &SymbolTable) -> Vec<Class> {
+    let size = symbols.size_of(ty);
+    if size > 16 {
+        return vec![Class::Memory; size.div_ceil(8)];
+    }
+
+    let mut scalars = Vec::new();
+    flatten_scalars(ty, 0, symbols, &mut scalars);
+    (0..size.div_ceil(8))
+        .map(|i| {
+            let mut in_eightbyte = scalars.iter().filter(|(offset, _)| offset / 8 == i);
+            if in_eightbyte.all(|(_, ty)| *ty == Type::Double) {
+                Class::Sse
+            } else {
+                Class::Integer
+            }
+        })
+        .collect()
+}
+
+/// Every scalar within an aggregate, with its byte offset.
+fn flatten_scalars(
+    ty: &Type,
+    offset: usize,
+    symbols: &SymbolTable,
+    scalars: &mut Vec<(usize, Type)>,
+) {
+    match ty {
+        Type::Structure(_, tag) => {
+            for member in &symbols.structure(tag).unwrap().members {
+                flatten_scalars(&member.ty, offset + member.offset, symbols, scalars);
+            }
+        }
+        Type::Array(element, len) => {
+            let element_size = symbols.size_of(element);
+            for i in 0..*len {
+                flatten_scalars(element, offset + i * element_size, symbols, scalars);
+            }
+        }
+        _ => scalars.push((offset, ty.clone())),
+    }
+}
+
+fn returns_in_memory(ty: &Type, symbols: &SymbolTable) -> bool {
+    ty.is_structure() && classify_structure(ty, symbols)[0] == Class::Memory
+}
+
+/// Size of the integer register piece holding the last `remaining` bytes of
+/// a structure; odd sizes are moved a byte at a time.
+fn eightbyte_type(remaining: i32) -> AsmType {
+    match remaining {
+        8.. => AsmType::Quadword,
+        4 => AsmType::Longword,
+        1 => AsmType::Byte,
+        size => AsmType::ByteArray { size, alignment: 1 },
+    }
+}
+
+/// Bytes an argument takes up on the stack.
+fn stack_slot_size(t: AsmType) -> i32 {
+    (t.size() + 7) / 8 * 8
+}
+
+/// Assigns each value to the next free integer or xmm argument register as
+/// System V does. A structure is split into eightbytes, each going in a
+/// register of its class, unless it is passed in memory or there aren't
+/// enough registers left for all of it. Whatever doesn't fit goes on the
+/// stack, in order.
+fn classify(
+    vals: Vec<(Operand, Type)>,
+    return_in_memory: bool,
+    symbols: &SymbolTable,
+) -> (Vec<(Arg, Reg)>, Vec<Arg>) {
+    // The address a structure is returned through takes up rdi
+    let mut int_regs = ARG_REGISTERS[usize::from(return_in_memory)..].iter();
+    let mut xmm_regs = XMM_ARG_REGISTERS.iter();
+    let mut in_registers = Vec::new();
+    let mut on_stack = Vec::new();
+
+    for (op, ty) in vals {
+        let t = AsmType::from_type(&ty, symbols);
+        if !ty.is_structure() {
+            let reg = match t {
+                AsmType::Double => xmm_regs.next(),
+                _ => int_regs.next(),
+            };
+            match reg {
+                Some(reg) => in_registers.push(((op, t), reg.clone())),
+                None => on_stack.push((op, t)),
+            }
+            continue;
+        }
+
+        let classes = classify_structure(&ty, symbols);
+        let count = |class| classes.iter().filter(|c| **c == class).count();
+        if classes[0] == Class::Memory
+            || count(Class::Integer) > int_regs.len()
+            || count(Class::Sse) > xmm_regs.len()
+        {
+            on_stack.push((op, t));
+            continue;
+        }
+
+        for (i, class) in classes.iter().enumerate() {
+            let offset = 8 * i as i32;
+            let piece = offset_operand(&op, offset);
+            let (piece_t, reg) = match class {
+                Class::Sse => (AsmType::Double, xmm_regs.next()),
+                _ => (eightbyte_type(t.size() - offset), int_regs.next()),
+            };
+            in_registers.push(((piece, piece_t), reg.unwrap().clone()));
+        }
+    }
+
+    (in_registers, on_stack)
+}
+
+/// The registers a structure that isn't returned in memory comes back in:
+/// each eightbyte goes in the next of rax and rdx, or xmm0 and xmm1.
+fn return_pieces(op: &Operand, ty: &Type, symbols: &SymbolTable) -> Vec<(Arg, Reg)> {
+    let size = symbols.size_of(ty) as i32;
+    let mut int_regs = [Reg::Rax, Reg::Rdx].into_iter();
+    let mut xmm_regs = [Reg::Xmm0, Reg::Xmm1].into_iter();
+
+    classify_structure(ty, symbols)
+        .into_iter()
+        .enumerate()
+        .map(|(i, class)| {
+            let offset = 8 * i as i32;
+            let piece = offset_operand(op, offset);
+            match class {
+                Class::Sse => ((piece, AsmType::Double), xmm_regs.next().unwrap()),
+                _ => ((piece, eightbyte_type(size - offset)), int_regs.next().unwrap()),
+            }
+        })
+        .collect()
+}
+
+/// The same memory operand, `bytes` further along.
+fn offset_operand(op: &Operand, bytes: i32) -> Operand {
+    match op {
+        Operand::Pseudo(name) => Operand::PseudoMem(name.clone(), bytes),
+        Operand::PseudoMem(name, offset) => Operand::PseudoMem(name.clone(), offset + bytes),
+        Operand::Stack(offset) => Operand::Stack(offset + bytes),
+        Operand::Memory(reg, offset) => Operand::Memory(reg.clone(), offset + bytes),
+        Operand::Data(name, offset) => Operand::Data(name.clone(), offset + bytes),
+        _ => unreachable!("only operands in memory have an offset"),
+    }
+}
+
+/// Copies an aggregate between memory operands, eight bytes at a time while
+/// there are that many left.
+fn copy_bytes(insts: &mut Vec<Instruction>, dst: &Operand, src: &Operand, size: i32) {
+    let mut i = 0;
+    while i < size {
+        let t = match size - i {
+            8.. => AsmType::Quadword,
+            4.. => AsmType::Longword,
+            _ => AsmType::Byte,
+        };
+        insts.push(Instruction::Mov(t, offset_operand(dst, i), offset_operand(src, i)));
+        i += t.size();
+    }
+}
+
+/// Moves a value into a register. A piece of a structure with an odd size is
+/// shifted in a byte at a time, starting from its last byte.
+fn move_to_register(insts: &mut Vec<Instruction>, t: AsmType, reg: Reg, src: Operand) {
+    let AsmType::ByteArray { size, .. } = t else {
+        insts.push(Instruction::Mov(t, Operand::Reg(reg), src));
+        return;
+    };
+    let dst = Operand::Reg(reg);
+    for i in (0..size).rev() {
+        if i != size - 1 {
+            insts.push(Instruction::Sal(AsmType::Quadword, dst.clone(), Operand::Imm(8)));
+        }
+        insts.push(Instruction::Mov(AsmType::Byte, dst.clone(), offset_operand(&src, i)));
+    }
+}
+
+/// The reverse of `move_to_register`, which shifts the register out a byte
+/// at a time.
+fn move_from_register(insts: &mut Vec<Instruction>, t: AsmType, dst: Operand, reg: Reg) {
+    let AsmType::ByteArray { size, .. } = t else {
+        insts.push(Instruction::Mov(t, dst, Operand::Reg(reg)));
+        return;
+    };
+    let src = Operand::Reg(reg);
+    for i in 0..size {
+        insts.push(Instruction::Mov(AsmType::Byte, offset_operand(&dst, i), src.clone()));
+        if i != size - 1 {
+            insts.push(Instruction::Shr(AsmType::Quadword, src.clone(), Operand::Imm(8)));
+        }
+    }
+}
+
+/// Moves a scalar, or copies an aggregate byte by byte.
+fn copy_value(insts: &mut Vec<Instruction>, t: AsmType, dst: Operand, src: Operand) {
+    match t {
+        AsmType::ByteArray { size, .. } => copy_bytes(insts, &dst, &src, size),
+        _ => insts.push(Instruction::Mov(t, dst, src)),
+    }
+}
+
+fn select_instructions(
+    ir_fn: &ir::Function,
+    symbols: &SymbolTable,
+    ctx: &mut Context,
+) -> Vec<Instruction> {
     let mut insts = Vec::new();
+    let Type::Function(fun_type) = symbols.type_of(&ir_fn.name) else {
+        unreachable!("functions have function types")
+    };
+    let return_in_memory = returns_in_memory(&fun_type.ret, symbols);
+
+    // The address to return a structure through is saved in the slot
+    // reserved for it by `allocate_stack`
+    if return_in_memory {
+        insts.push(Instruction::Mov(
+            AsmType::Quadword,
+            Operand::Stack(-8),
+            Operand::Reg(Reg::Rdi),
+        ));
+    }
+
+    // Copy incoming parameters out of their registers / stack slots
+    let params = ir_fn
+        .params
+        .iter()
+        .map(|param| (Operand::Pseudo(param.clone()), symbols.type_of(param).clone()))
+        .collect();
+    let (register_params, stack_params) = classify(params, return_in_memory, symbols);
+
+    for ((param, t), reg) in register_params {
+        move_from_register(&mut insts, t, param, reg);
+    }
+    let mut offset = 16;
+    for (param, t) in stack_params {
+        copy_value(&mut insts, t, param, Operand::Stack(offset));
+        offset += stack_slot_size(t);
+    }
 
     for instruction in &ir_fn.instructions {
         match instruction {
             ir::Instruction::Binary(op, src1, src2, dst) => {
-                let s1 = to_operand(src1);
-                let s2 = to_operand(src2);
-                let d = to_operand(dst);
+                // Operands share a type; a comparison's result is always int
+                let t = asm_type(src1, symbols);
+                let signed = is_signed(src1, symbols);
+                let s1 = to_operand(src1, ctx);
+                let s2 = to_operand(src2, ctx);
+                let d = to_operand(dst, ctx);
+
+                if t == AsmType::Double {
+                    select_double_binary(&mut insts, op, s1, s2, d);
+                    continue;
+                }
 
                 match op {
                     ir::BinaryOp::Add => {
-                        insts.push(Instruction::Mov(d.clone(), s1));
-                        insts.push(Instruction::Add(d, s2));
+                        insts.push(Instruction::Mov(t, d.clone(), s1));
+                        insts.push(Instruction::Add(t, d, s2));
                     }
                     ir::BinaryOp::BitwiseAnd => {
-                        insts.push(Instruction::Mov(d.clone(), s1));
-                        insts.push(Instruction::And(d, s2));
+                        insts.push(Instruction::Mov(t, d.clone(), s1));
+                        insts.push(Instruction::And(t, d, s2));
                     }
                     ir::BinaryOp::BitwiseOr => {
-                        insts.push(Instruction::Mov(d.clone(), s1));
-                        insts.push(Instruction::Or(d, s2));
+                        insts.push(Instruction::Mov(t, d.clone(), s1));
+                        insts.push(Instruction::Or(t, d, s2));
                     }
                     ir::BinaryOp::BitwiseXor => {
-                        insts.push(Instruction::Mov(d.clone(), s1));
-                        insts.push(Instruction::Xor(d, s2));
+                        insts.push(Instruction::Mov(t, d.clone(), s1));
+                        insts.push(Instruction::Xor(t, d, s2));
                     }
                     ir::BinaryOp::Subtract => {
-                        insts.push(Instruction::Mov(d.clone(), s1));
-                        insts.push(Instruction::Sub(d, s2));
+                        insts.push(Instruction::Mov(t, d.clone(), s1));
+                        insts.push(Instruction::Sub(t, d, s2));
                     }
                     ir::BinaryOp::Multiply => {
-                        insts.push(Instruction::Mov(d.clone(), s1));
-                        insts.push(Instruction::Imul(d, s2));
+                        insts.push(Instruction::Mov(t, d.clone(), s1));
+                        insts.push(Instruction::Imul(t, d, s2));
                     }
                     ir::BinaryOp::LeftShift => {
-                        insts.push(Instruction::Mov(d.clone(), s1));
-                        insts.push(Instruction::Sal(d, s2));
+                        insts.push(Instruction::Mov(t, d.clone(), s1));
+                        insts.push(Instruction::Sal(t, d, s2));
                     }
                     ir::BinaryOp::RightShift => {
-                        insts.push(Instruction::Mov(d.clone(), s1));
-                        insts.push(Instruction::Sar(d, s2));
-                    }
-                    ir::BinaryOp::NotEqual => {
-                        insts.push(Instruction::Mov(d.clone(), s1));
-                        insts.push(Instruction::Cmp(d.clone(), s2));
-                        insts.push(Instruction::Mov(d.clone(), Operand::Imm(0)));
-                        insts.push(Instruction::SetCC(CondCode::NE, d));
-                    }
-                    ir::BinaryOp::Equal => {
-                        insts.push(Instruction::Mov(d.clone(), s1));
-                        insts.push(Instruction::Cmp(d.clone(), s2));
-                        insts.push(Instruction::Mov(d.clone(), Operand::Imm(0)));
-                        insts.push(Instruction::SetCC(CondCode::E, d));
-                    }
-                    ir::BinaryOp::LessThan => {
-                        insts.push(Instruction::Mov(d.clone(), s1));
-                        insts.push(Instruction::Cmp(d.clone(), s2));
-                        insts.push(Instruction::Mov(d.clone(), Operand::Imm(0)));
-                        insts.push(Instruction::SetCC(CondCode::L, d));
-                    }
-
-                    ir::BinaryOp::LessThanEqual => {
-                        insts.push(Instruction::Mov(d.clone(), s1));
-                        insts.push(Instruction::Cmp(d.clone(), s2));
-                        insts.push(Instruction::Mov(d.clone(), Operand::Imm(0)));
-                        insts.push(Instruction::SetCC(CondCode::LE, d));
-                    }
-                    ir::BinaryOp::GreaterThan => {
-                        insts.push(Instruction::Mov(d.clone(), s1));
-                        insts.push(Instruction::Cmp(d.clone(), s2));
-                        insts.push(Instruction::Mov(d.clone(), Operand::Imm(0)));
-                        insts.push(Instruction::SetCC(CondCode::G, d));
-                    }
-                    ir::BinaryOp::GreaterThanEqual => {
-                        insts.push(Instruction::Mov(d.clone(), s1));
-                        insts.push(Instruction::Cmp(d.clone(), s2));
-                        insts.push(Instruction::Mov(d.clone(), Operand::Imm(0)));
-                        insts.push(Instruction::SetCC(CondCode::GE, d));
+                        insts.push(Instruction::Mov(t, d.clone(), s1));
+                        if signed {
+                            insts.push(Instruction::Sar(t, d, s2));
+                        } else {
+                            insts.push(Instruction::Shr(t, d, s2));
+                        }
                     }
 
                     // Division / Remainder
                     ir::BinaryOp::Divide | ir::BinaryOp::Remainder => {
-                        insts.push(Instruction::Mov(Operand::Reg(Reg::Rax), s1));
-                        insts.push(Instruction::Cqo); // Sign extend RAX -> RDX:RAX
-                        insts.push(Instruction::Idiv(s2));
+                        insts.push(Instruction::Mov(t, Operand::Reg(Reg::Rax), s1));
+                        if signed {
+                            insts.push(Instruction::Cdq(t)); // Sign extend into RDX
+                            insts.push(Instruction::Idiv(t, s2));
+                        } else {
+                            insts.push(Instruction::Mov(t, Operand::Reg(Reg::Rdx), Operand::Imm(0)));
+                            insts.push(Instruction::Div(t, s2));
+                        }
 
                         let result_reg = match op {
                             ir::BinaryOp::Divide => Reg::Rax,
                             ir::BinaryOp::Remainder => Reg::Rdx,
                             _ => unreachable!(),
                         };
-                        insts.push(Instruction::Mov(d, Operand::Reg(result_reg)));
+                        insts.push(Instruction::Mov(t, d, Operand::Reg(result_reg)));
+                    }
+
+                    // Relational
+                    ir::BinaryOp::Equal
+                    | ir::BinaryOp::NotEqual
+                    | ir::BinaryOp::LessThan
+                    | ir::BinaryOp::LessThanEqual
+                    | ir::BinaryOp::GreaterThan
+                    | ir::BinaryOp::GreaterThanEqual => {
+                        let cond = match (op, signed) {
+                            (ir::BinaryOp::Equal, _) => CondCode::E,
+                            (ir::BinaryOp::NotEqual, _) => CondCode::NE,
+                            (ir::BinaryOp::LessThan, true) => CondCode::L,
+                            (ir::BinaryOp::LessThanEqual, true) => CondCode::LE,
+                            (ir::BinaryOp::GreaterThan, true) => CondCode::G,
+                            (ir::BinaryOp::GreaterThanEqual, true) => CondCode::GE,
+                            (ir::BinaryOp::LessThan, false) => CondCode::B,
+                            (ir::BinaryOp::LessThanEqual, false) => CondCode::BE,
+                            (ir::BinaryOp::GreaterThan, false) => CondCode::A,
+                            (ir::BinaryOp::GreaterThanEqual, false) => CondCode::AE,
+                            _ => unreachable!(),
+                        };
+
+                        insts.push(Instruction::Cmp(t, s1, s2));
+                        insts.push(Instruction::Mov(
+                            asm_type(dst, symbols),
+                            d.clone(),
+                            Operand::Imm(0),
+                        ));
+                        insts.push(Instruction::SetCC(cond, d));
                     }
                 }
             }
 
             ir::Instruction::Unary(op, src, dst) => {
-                let s = to_operand(src);
-                let d = to_operand(dst);
+                let t = asm_type(src, symbols);
+                let s = to_operand(src, ctx);
+                let d = to_operand(dst, ctx);
 
                 match op {
+                    // -x is computed as -0.0 - x, which flips the sign of
+                    // zeros too
+                    ir::UnaryOp::Negation if t == AsmType::Double => {
+                        insts.push(Instruction::Mov(t, d.clone(), ctx.constant(-0.0)));
+                        insts.push(Instruction::Sub(t, d, s));
+                    }
                     ir::UnaryOp::Negation => {
-                        insts.push(Instruction::Mov(d.clone(), s));
-                        insts.push(Instruction::Neg(d));
+                        insts.push(Instruction::Mov(t, d.clone(), s));
+                        insts.push(Instruction::Neg(t, d));
                     }
                     ir::UnaryOp::BitwiseComplement => {
-                        insts.push(Instruction::Mov(d.clone(), s));
-                        insts.push(Instruction::Not(d));
+                        insts.push(Instruction::Mov(t, d.clone(), s));
+                        insts.push(Instruction::Not(t, d));
+                    }
+                    ir::UnaryOp::LogicalNegation if t == AsmType::Double => {
+                        let zero = Operand::Reg(Reg::Xmm14);
+                        insts.push(Instruction::Xor(t, zero.clone(), zero.clone()));
+                        select_double_binary(&mut insts, &ir::BinaryOp::Equal, s, zero, d);
                     }
                     ir::UnaryOp::LogicalNegation => {
-                        insts.push(Instruction::Cmp(s, Operand::Imm(0)));
-                        insts.push(Instruction::Mov(d.clone(), Operand::Imm(0)));
+                        insts.push(Instruction::Cmp(t, s, Operand::Imm(0)));
+                        insts.push(Instruction::Mov(
+                            asm_type(dst, symbols),
+                            d.clone(),
+                            Operand::Imm(0),
+                        ));
                         insts.push(Instruction::SetCC(CondCode::E, d));
                     }
                 }
             }
 
             ir::Instruction::Copy(dst, src) => {
-                insts.push(Instruction::Mov(to_operand(dst), to_operand(src)));
+                let (d, s) = (to_operand(dst, ctx), to_operand(src, ctx));
+                copy_value(&mut insts, asm_type(src, symbols), d, s);
+            }
+
+            ir::Instruction::SignExtend(src, dst) => {
+                insts.push(Instruction::Movsx(
+                    asm_type(src, symbols),
+                    asm_type(dst, symbols),
+                    to_operand(dst, ctx),
+                    to_operand(src, ctx),
+                ));
+            }
+
+            ir::Instruction::ZeroExtend(src, dst) => {
+                insts.push(Instruction::MovZeroExtend(
+                    asm_type(src, symbols),
+                    asm_type(dst, symbols),
+                    to_operand(dst, ctx),
+                    to_operand(src, ctx),
+                ));
+            }
+
+            // Truncating is just reading the low bytes
+            ir::Instruction::Truncate(src, dst) => {
+                insts.push(Instruction::Mov(
+                    asm_type(dst, symbols),
+                    to_operand(dst, ctx),
+                    to_operand(src, ctx),
+                ));
+            }
+
+            // There are no byte-sized conversions, so chars go through eax
+            ir::Instruction::DoubleToInt(src, dst) => {
+                let t = asm_type(dst, symbols);
+                let s = to_operand(src, ctx);
+                let d = to_operand(dst, ctx);
+
+                if t == AsmType::Byte {
+                    let rax = Operand::Reg(Reg::Rax);
+                    insts.push(Instruction::Cvttsd2si(AsmType::Longword, rax.clone(), s));
+                    insts.push(Instruction::Mov(t, d, rax));
+                } else {
+                    insts.push(Instruction::Cvttsd2si(t, d, s));
+                }
+            }
+
+            ir::Instruction::IntToDouble(src, dst) => {
+                let t = asm_type(src, symbols);
+                let s = to_operand(src, ctx);
+                let d = to_operand(dst, ctx);
+
+                if t == AsmType::Byte {
+                    let rax = Operand::Reg(Reg::Rax);
+                    let l = AsmType::Longword;
+                    insts.push(Instruction::Movsx(t, l, rax.clone(), s));
+                    insts.push(Instruction::Cvtsi2sd(l, d, rax));
+                } else {
+                    insts.push(Instruction::Cvtsi2sd(t, d, s));
+                }
+            }
+
+            ir::Instruction::DoubleToUInt(src, dst) => {
+                let t = asm_type(dst, symbols);
+                let s = to_operand(src, ctx);
+                let d = to_operand(dst, ctx);
+
+                if t != AsmType::Quadword {
+                    // Every unsigned int or unsigned char fits in a long
+                    insts.push(Instruction::Cvttsd2si(AsmType::Quadword, Operand::Reg(Reg::Rax), s));
+                    insts.push(Instruction::Mov(t, d, Operand::Reg(Reg::Rax)));
+                    continue;
+                }
+
+                // Values past the long range are shifted down by 2^63 before
+                // converting and the top bit is added back afterwards
+                let upper = ctx.constant(9223372036854775808.0);
+                let big_label = ctx.make_label("d2ul_big");
+                let end_label = ctx.make_label("d2ul_end");
+                let scratch = Operand::Reg(Reg::Xmm1);
+
+                insts.push(Instruction::Cmp(AsmType::Double, s.clone(), upper.clone()));
+                insts.push(Instruction::JumpCC(CondCode::AE, big_label.clone()));
+                insts.push(Instruction::Cvttsd2si(AsmType::Quadword, d.clone(), s.clone()));
+                insts.push(Instruction::JumpCC(CondCode::MP, end_label.clone()));
+
+                insts.push(Instruction::Label(big_label));
+                insts.push(Instruction::Mov(AsmType::Double, scratch.clone(), s));
+                insts.push(Instruction::Sub(AsmType::Double, scratch.clone(), upper));
+                insts.push(Instruction::Cvttsd2si(AsmType::Quadword, d.clone(), scratch));
+                insts.push(Instruction::Mov(
+                    AsmType::Quadword,
+                    Operand::Reg(Reg::Rdx),
+                    Operand::Imm(i64::MIN),
+                ));
+                insts.push(Instruction::Add(AsmType::Quadword, d, Operand::Reg(Reg::Rdx)));
+                insts.push(Instruction::Label(end_label));
+            }
+
+            ir::Instruction::UIntToDouble(src, dst) => {
+                let s = to_operand(src, ctx);
+                let d = to_operand(dst, ctx);
+
+                let t = asm_type(src, symbols);
+                if t != AsmType::Quadword {
+                    let q = AsmType::Quadword;
+                    insts.push(Instruction::MovZeroExtend(t, q, Operand::Reg(Reg::Rax), s));
+                    insts.push(Instruction::Cvtsi2sd(q, d, Operand::Reg(Reg::Rax)));
+                    continue;
+                }
+
+                // Values with the top bit set are halved (keeping the low bit
+                // so rounding is unaffected), converted, then doubled
+                let big_label = ctx.make_label("ul2d_big");
+                let end_label = ctx.make_label("ul2d_end");
+                let rax = Operand::Reg(Reg::Rax);
+                let rdx = Operand::Reg(Reg::Rdx);
+
+                insts.push(Instruction::Cmp(AsmType::Quadword, s.clone(), Operand::Imm(0)));
+                insts.push(Instruction::JumpCC(CondCode::L, big_label.clone()));
+                insts.push(Instruction::Cvtsi2sd(AsmType::Quadword, d.clone(), s.clone()));
+                insts.push(Instruction::JumpCC(CondCode::MP, end_label.clone()));
+
+                insts.push(Instruction::Label(big_label));
+                insts.push(Instruction::Mov(AsmType::Quadword, rax.clone(), s));
+                insts.push(Instruction::Mov(AsmType::Quadword, rdx.clone(), rax.clone()));
+                insts.push(Instruction::Shr(AsmType::Quadword, rdx.clone(), Operand::Imm(1)));
+                insts.push(Instruction::And(AsmType::Quadword, rax.clone(), Operand::Imm(1)));
+                insts.push(Instruction::Or(AsmType::Quadword, rdx.clone(), rax));
+                insts.push(Instruction::Cvtsi2sd(AsmType::Quadword, d.clone(), rdx));
+                insts.push(Instruction::Add(AsmType::Double, d.clone(), d));
+                insts.push(Instruction::Label(end_label));
             }
 
             ir::Instruction::Jump(label) => {
@@ -333,12 +1218,35 @@ fn select_instructions(ir_fn: &ir::Function) -> Vec<Instruction> {
             }
 
             ir::Instruction::JumpIfZero(op, label) => {
-                insts.push(Instruction::Cmp(to_operand(op), Operand::Imm(0)));
+                let t = asm_type(op, symbols);
+                if t == AsmType::Double {
+                    // NaN compares unordered, which also sets ZF
+                    let zero = Operand::Reg(Reg::Xmm14);
+                    let skip_label = ctx.make_label("nan");
+                    insts.push(Instruction::Xor(t, zero.clone(), zero.clone()));
+                    insts.push(Instruction::Cmp(t, to_operand(op, ctx), zero));
+                    insts.push(Instruction::JumpCC(CondCode::P, skip_label.clone()));
+                    insts.push(Instruction::JumpCC(CondCode::E, label.clone()));
+                    insts.push(Instruction::Label(skip_label));
+                    continue;
+                }
+
+                insts.push(Instruction::Cmp(t, to_operand(op, ctx), Operand::Imm(0)));
                 insts.push(Instruction::JumpCC(CondCode::E, label.clone()));
             }
 
             ir::Instruction::JumpIfNotZero(op, label) => {
-                insts.push(Instruction::Cmp(to_operand(op), Operand::Imm(0)));
+                let t = asm_type(op, symbols);
+                if t == AsmType::Double {
+                    let zero = Operand::Reg(Reg::Xmm14);
+                    insts.push(Instruction::Xor(t, zero.clone(), zero.clone()));
+                    insts.push(Instruction::Cmp(t, to_operand(op, ctx), zero));
+                    insts.push(Instruction::JumpCC(CondCode::NE, label.clone()));
+                    insts.push(Instruction::JumpCC(CondCode::P, label.clone()));
+                    continue;
+                }
+
+                insts.push(Instruction::Cmp(t, to_operand(op, ctx), Operand::Imm(0)));
                 insts.push(Instruction::JumpCC(CondCode::NE, label.clone()));
             }
 
@@ -346,79 +1254,410 @@ fn select_instructions(ir_fn: &ir::Function) -> Vec<Instruction> {
                 insts.push(Instruction::Label(label.clone()));
             }
 
+            // The index is never negative, so a 32-bit one needs no
+            // extending beyond what mov does
+            ir::Instruction::JumpTable(index, targets) => {
+                let rax = Operand::Reg(Reg::Rax);
+                let rdx = Operand::Reg(Reg::Rdx);
+                let t = asm_type(index, symbols);
+                insts.push(Instruction::Mov(t, rax.clone(), to_operand(index, ctx)));
+                insts.push(Instruction::Lea(rdx.clone(), ctx.jump_table(targets.clone())));
+                insts.push(Instruction::Movsx(
+                    AsmType::Longword,
+                    AsmType::Quadword,
+                    rax.clone(),
+                    Operand::Indexed(Reg::Rdx, Reg::Rax, 4),
+                ));
+                insts.push(Instruction::Add(AsmType::Quadword, rax.clone(), rdx));
+                insts.push(Instruction::JumpIndirect(rax));
+            }
+
             ir::Instruction::Return(val) => {
-                insts.push(Instruction::Mov(Operand::Reg(Reg::Rax), to_operand(val)));
+                let op = to_operand(val, ctx);
+                if return_in_memory {
+                    let size = AsmType::from_type(&fun_type.ret, symbols).size();
+                    let rax = Operand::Reg(Reg::Rax);
+                    insts.push(Instruction::Mov(AsmType::Quadword, rax, Operand::Stack(-8)));
+                    copy_bytes(&mut insts, &Operand::Memory(Reg::Rax, 0), &op, size);
+                } else if fun_type.ret.is_structure() {
+                    for ((piece, t), reg) in return_pieces(&op, &fun_type.ret, symbols) {
+                        move_to_register(&mut insts, t, reg, piece);
+                    }
+                } else {
+                    let t = asm_type(val, symbols);
+                    insts.push(Instruction::Mov(t, return_register(t), op));
+                }
                 insts.push(Instruction::Ret);
             }
+
+            ir::Instruction::FunCall(name, args, dst) => {
+                select_call(&mut insts, name, args, dst, symbols, ctx);
+            }
+
+            ir::Instruction::GetAddress(src, dst) => {
+                insts.push(Instruction::Lea(to_operand(dst, ctx), to_operand(src, ctx)));
+            }
+
+            // Pointers are dereferenced through rax
+            ir::Instruction::Load(ptr, dst) => {
+                insts.push(Instruction::Mov(
+                    AsmType::Quadword,
+                    Operand::Reg(Reg::Rax),
+                    to_operand(ptr, ctx),
+                ));
+                copy_value(
+                    &mut insts,
+                    asm_type(dst, symbols),
+                    to_operand(dst, ctx),
+                    Operand::Memory(Reg::Rax, 0),
+                );
+            }
+
+            ir::Instruction::AddPtr(ptr, index, scale, dst) => {
+                let rax = Operand::Reg(Reg::Rax);
+                let rdx = Operand::Reg(Reg::Rdx);
+                let d = to_operand(dst, ctx);
+                insts.push(Instruction::Mov(AsmType::Quadword, rax, to_operand(ptr, ctx)));
+
+                // A constant index folds into the displacement
+                let displacement = match index {
+                    ir::Val::Constant(Const::Long(i)) => {
+                        i.checked_mul(*scale).and_then(|d| i32::try_from(d).ok())
+                    }
+                    _ => None,
+                };
+                if let Some(displacement) = displacement {
+                    insts.push(Instruction::Lea(d, Operand::Memory(Reg::Rax, displacement)));
+                    continue;
+                }
+
+                let index = to_operand(index, ctx);
+                insts.push(Instruction::Mov(AsmType::Quadword, rdx.clone(), index));
+                let scale = match scale {
+                    1 | 2 | 4 | 8 => *scale as i32,
+                    _ => {
+                        insts.push(Instruction::Imul(AsmType::Quadword, rdx, Operand::Imm(*scale)));
+                        1
+                    }
+                };
+                insts.push(Instruction::Lea(d, Operand::Indexed(Reg::Rax, Reg::Rdx, scale)));
+            }
+
+            ir::Instruction::CopyToOffset(src, dst, offset) => {
+                copy_value(
+                    &mut insts,
+                    asm_type(src, symbols),
+                    Operand::PseudoMem(dst.clone(), *offset as i32),
+                    to_operand(src, ctx),
+                );
+            }
+
+            ir::Instruction::CopyFromOffset(src, offset, dst) => {
+                copy_value(
+                    &mut insts,
+                    asm_type(dst, symbols),
+                    to_operand(dst, ctx),
+                    Operand::PseudoMem(src.clone(), *offset as i32),
+                );
+            }
+
+            ir::Instruction::Store(src, ptr) => {
+                insts.push(Instruction::Mov(
+                    AsmType::Quadword,
+                    Operand::Reg(Reg::Rax),
+                    to_operand(ptr, ctx),
+                ));
+                copy_value(
+                    &mut insts,
+                    asm_type(src, symbols),
+                    Operand::Memory(Reg::Rax, 0),
+                    to_operand(src, ctx),
+                );
+            }
         }
     }
     insts
 }
 
-fn allocate_stack(insts: Vec<Instruction>) -> (Vec<Instruction>, i32) {
+/// Arithmetic and comparisons on doubles.
+fn select_double_binary(
+    insts: &mut Vec<Instruction>,
+    op: &ir::BinaryOp,
+    s1: Operand,
+    s2: Operand,
+    d: Operand,
+) {
+    let t = AsmType::Double;
+
+    // comisd sets CF both for "below" and for unordered operands, so < and <=
+    // test > and >= with the operands swapped to make NaN compare false
+    let (cond, s1, s2) = match op {
+        ir::BinaryOp::Add => {
+            insts.push(Instruction::Mov(t, d.clone(), s1));
+            insts.push(Instruction::Add(t, d, s2));
+            return;
+        }
+        ir::BinaryOp::Subtract => {
+            insts.push(Instruction::Mov(t, d.clone(), s1));
+            insts.push(Instruction::Sub(t, d, s2));
+            return;
+        }
+        ir::BinaryOp::Multiply => {
+            insts.push(Instruction::Mov(t, d.clone(), s1));
+            insts.push(Instruction::Imul(t, d, s2));
+            return;
+        }
+        ir::BinaryOp::Divide => {
+            insts.push(Instruction::Mov(t, d.clone(), s1));
+            insts.push(Instruction::DivDouble(d, s2));
+            return;
+        }
+        ir::BinaryOp::Equal => (CondCode::E, s1, s2),
+        ir::BinaryOp::NotEqual => (CondCode::NE, s1, s2),
+        ir::BinaryOp::GreaterThan => (CondCode::A, s1, s2),
+        ir::BinaryOp::GreaterThanEqual => (CondCode::AE, s1, s2),
+        ir::BinaryOp::LessThan => (CondCode::A, s2, s1),
+        ir::BinaryOp::LessThanEqual => (CondCode::AE, s2, s1),
+        _ => unreachable!("rejected by the type checker"),
+    };
+
+    insts.push(Instruction::Cmp(t, s1, s2));
+    insts.push(Instruction::Mov(AsmType::Longword, d.clone(), Operand::Imm(0)));
+    insts.push(Instruction::SetCC(cond.clone(), d.clone()));
+
+    // Unordered operands also set ZF; PF tells them apart
+    let r11 = Operand::Reg(Reg::R11);
+    match cond {
+        CondCode::E => {
+            insts.push(Instruction::SetCC(CondCode::NP, r11.clone()));
+            insts.push(Instruction::And(AsmType::Byte, d, r11));
+        }
+        CondCode::NE => {
+            insts.push(Instruction::SetCC(CondCode::P, r11.clone()));
+            insts.push(Instruction::Or(AsmType::Byte, d, r11));
+        }
+        _ => {}
+    }
+}
+
+fn return_register(t: AsmType) -> Operand {
+    match t {
+        AsmType::Double => Operand::Reg(Reg::Xmm0),
+        _ => Operand::Reg(Reg::Rax),
+    }
+}
+
+fn select_call(
+    insts: &mut Vec<Instruction>,
+    name: &str,
+    args: &[ir::Val],
+    dst: &ir::Val,
+    symbols: &SymbolTable,
+    ctx: &mut Context,
+) {
+    let Type::Function(fun_type) = symbols.type_of(name) else {
+        unreachable!("only functions are called")
+    };
+    let d = to_operand(dst, ctx);
+    let return_in_memory = returns_in_memory(&fun_type.ret, symbols);
+
+    // Arguments have already been converted to the parameter types
+    let args = args
+        .iter()
+        .zip(&fun_type.params)
+        .map(|(arg, ty)| (to_operand(arg, ctx), ty.clone()))
+        .collect();
+    let (register_args, stack_args) = classify(args, return_in_memory, symbols);
+
+    // Keep rsp 16-byte aligned at the call instruction
+    let stack_bytes: i32 = stack_args.iter().map(|(_, t)| stack_slot_size(*t)).sum();
+    let padding = if stack_bytes % 16 != 0 { 8 } else { 0 };
+    if padding != 0 {
+        insts.push(Instruction::Sub(
+            AsmType::Quadword,
+            Operand::Reg(Reg::Rsp),
+            Operand::Imm(padding),
+        ));
+    }
+
+    // The callee writes a structure returned in memory straight to dst
+    if return_in_memory {
+        insts.push(Instruction::Lea(Operand::Reg(Reg::Rdi), d.clone()));
+    }
+    for ((arg, t), reg) in register_args {
+        move_to_register(insts, t, reg, arg);
+    }
+
+    // Remaining arguments are pushed right to left. Pushing a value narrower
+    // than 8 bytes straight from memory would read past it, so those go
+    // through rax, and the end of a structure is copied into place.
+    for (arg, t) in stack_args.into_iter().rev() {
+        if let AsmType::ByteArray { size, .. } = t {
+            push_bytes(insts, &arg, size);
+            continue;
+        }
+        match arg {
+            op @ Operand::Imm(_) => insts.push(Instruction::Push(op)),
+            op if matches!(t, AsmType::Quadword | AsmType::Double) => {
+                insts.push(Instruction::Push(op))
+            }
+            op => {
+                insts.push(Instruction::Mov(t, Operand::Reg(Reg::Rax), op));
+                insts.push(Instruction::Push(Operand::Reg(Reg::Rax)));
+            }
+        }
+    }
+
+    // Functions defined elsewhere (e.g. libc) are called through the PLT
+    let plt = !symbols.is_defined_function(name);
+    insts.push(Instruction::Call(name.to_string(), plt));
+
+    let bytes_to_remove = stack_bytes as i64 + padding;
+    if bytes_to_remove != 0 {
+        insts.push(Instruction::Add(
+            AsmType::Quadword,
+            Operand::Reg(Reg::Rsp),
+            Operand::Imm(bytes_to_remove),
+        ));
+    }
+
+    if return_in_memory {
+        return;
+    }
+    if fun_type.ret.is_structure() {
+        for ((piece, t), reg) in return_pieces(&d, &fun_type.ret, symbols) {
+            move_from_register(insts, t, piece, reg);
+        }
+    } else {
+        let t = asm_type(dst, symbols);
+        insts.push(Instruction::Mov(t, d, return_register(t)));
+    }
+}
+
+/// Pushes a structure one eightbyte at a time, last first.
+fn push_bytes(insts: &mut Vec<Instruction>, src: &Operand, size: i32) {
+    for i in (0..(size + 7) / 8).rev() {
+        let offset = 8 * i;
+        if size - offset >= 8 {
+            insts.push(Instruction::Push(offset_operand(src, offset)));
+        } else {
+            let rsp = Operand::Reg(Reg::Rsp);
+            insts.push(Instruction::Sub(AsmType::Quadword, rsp, Operand::Imm(8)));
+            let piece = offset_operand(src, offset);
+            copy_bytes(insts, &Operand::Memory(Reg::Rsp, 0), &piece, size - offset);
+        }
+    }
+}
+
+/// Assigns every pseudo a stack slot below the first `reserved` bytes of the
+/// frame, or its static storage.
+fn allocate_stack(
+    insts: Vec<Instruction>,
+    symbols: &SymbolTable,
+    reserved: i32,
+) -> (Vec<Instruction>, i32) {
     let mut map = HashMap::new();
-    let mut stack_size = 0;
+    let mut stack_size = -reserved;
     let mut new_insts = Vec::new();
 
     let mut replace_operand = |op: &Operand| -> Operand {
-        if let Operand::Pseudo(name) = op {
-            if !map.contains_key(name) {
-                stack_size -= 8; // Allocate 8 bytes
-                map.insert(name.clone(), stack_size);
+        let (name, offset) = match op {
+            Operand::Pseudo(name) if symbols.is_constant(name) => {
+                return Operand::Data(constant_label(name), 0);
             }
-            Operand::StackQWord(map[name])
-        } else {
-            op.clone()
+            Operand::Pseudo(name) if symbols.is_static(name) => {
+                return Operand::Data(name.clone(), 0);
+            }
+            Operand::PseudoMem(name, offset) if symbols.is_static(name) => {
+                return Operand::Data(name.clone(), *offset);
+            }
+            Operand::Pseudo(name) => (name, 0),
+            Operand::PseudoMem(name, offset) => (name, *offset),
+            _ => return op.clone(),
+        };
+
+        if !map.contains_key(name) {
+            // Each slot is sized and aligned for its type
+            let t = AsmType::from_type(symbols.type_of(name), symbols);
+            stack_size -= t.size();
+            stack_size -= stack_size.rem_euclid(t.alignment());
+            map.insert(name.clone(), stack_size);
         }
+        Operand::Stack(map[name] + offset)
     };
 
     for inst in insts {
         // Reconstruct instruction with replaced operands
         let new_inst = match inst {
-            Instruction::Mov(dst, src) => {
-                Instruction::Mov(replace_operand(&dst), replace_operand(&src))
+            Instruction::Mov(t, dst, src) => {
+                Instruction::Mov(t, replace_operand(&dst), replace_operand(&src))
+            }
+            Instruction::Movsx(src_t, dst_t, dst, src) => {
+                Instruction::Movsx(src_t, dst_t, replace_operand(&dst), replace_operand(&src))
             }
-            Instruction::Add(dst, src) => {
-                Instruction::Add(replace_operand(&dst), replace_operand(&src))
+            Instruction::MovZeroExtend(src_t, dst_t, dst, src) => {
+                let (dst, src) = (replace_operand(&dst), replace_operand(&src));
+                Instruction::MovZeroExtend(src_t, dst_t, dst, src)
             }
-            Instruction::And(dst, src) => {
-                Instruction::And(replace_operand(&dst), replace_operand(&src))
+            Instruction::Lea(dst, src) => {
+                Instruction::Lea(replace_operand(&dst), replace_operand(&src))
             }
-            Instruction::Or(dst, src) => {
-                Instruction::Or(replace_operand(&dst), replace_operand(&src))
+            Instruction::Add(t, dst, src) => {
+                Instruction::Add(t, replace_operand(&dst), replace_operand(&src))
             }
-            Instruction::Xor(dst, src) => {
-                Instruction::Xor(replace_operand(&dst), replace_operand(&src))
+            Instruction::And(t, dst, src) => {
+                Instruction::And(t, replace_operand(&dst), replace_operand(&src))
             }
-            Instruction::Sub(dst, src) => {
-                Instruction::Sub(replace_operand(&dst), replace_operand(&src))
+            Instruction::Or(t, dst, src) => {
+                Instruction::Or(t, replace_operand(&dst), replace_operand(&src))
             }
-            Instruction::Imul(dst, src) => {
-                Instruction::Imul(replace_operand(&dst), replace_operand(&src))
+            Instruction::Xor(t, dst, src) => {
+                Instruction::Xor(t, replace_operand(&dst), replace_operand(&src))
+            }
+            Instruction::Sub(t, dst, src) => {
+                Instruction::Sub(t, replace_operand(&dst), replace_operand(&src))
+            }
+            Instruction::Imul(t, dst, src) => {
+                Instruction::Imul(t, replace_operand(&dst), replace_operand(&src))
             }
 
-            Instruction::Sal(dst, src) => {
-                Instruction::Sal(replace_operand(&dst), replace_operand(&src))
+            Instruction::Sal(t, dst, src) => {
+                Instruction::Sal(t, replace_operand(&dst), replace_operand(&src))
             }
 
-            Instruction::Sar(dst, src) => {
-                Instruction::Imul(replace_operand(&dst), replace_operand(&src))
+            Instruction::Sar(t, dst, src) => {
+                Instruction::Sar(t, replace_operand(&dst), replace_operand(&src))
+            }
+            Instruction::Shr(t, dst, src) => {
+                Instruction::Shr(t, replace_operand(&dst), replace_operand(&src))
             }
-            Instruction::Cmp(dst, src) => {
-                Instruction::Cmp(replace_operand(&dst), replace_operand(&src))
+            Instruction::Cmp(t, dst, src) => {
+                Instruction::Cmp(t, replace_operand(&dst), replace_operand(&src))
+            }
+            Instruction::DivDouble(dst, src) => {
+                Instruction::DivDouble(replace_operand(&dst), replace_operand(&src))
+            }
+            Instruction::Cvttsd2si(t, dst, src) => {
+                Instruction::Cvttsd2si(t, replace_operand(&dst), replace_operand(&src))
+            }
+            Instruction::Cvtsi2sd(t, dst, src) => {
+                Instruction::Cvtsi2sd(t, replace_operand(&dst), replace_operand(&src))
             }
 
-            Instruction::Idiv(op) => Instruction::Idiv(replace_operand(&op)),
-            Instruction::Neg(op) => Instruction::Neg(replace_operand(&op)),
-            Instruction::Not(op) => Instruction::Not(replace_operand(&op)),
+            Instruction::Idiv(t, op) => Instruction::Idiv(t, replace_operand(&op)),
+            Instruction::Div(t, op) => Instruction::Div(t, replace_operand(&op)),
+            Instruction::Neg(t, op) => Instruction::Neg(t, replace_operand(&op)),
+            Instruction::Not(t, op) => Instruction::Not(t, replace_operand(&op)),
             Instruction::Push(op) => Instruction::Push(replace_operand(&op)),
             Instruction::Pop(op) => Instruction::Pop(replace_operand(&op)),
             Instruction::SetCC(cc, op) => Instruction::SetCC(cc, replace_operand(&op)),
             Instruction::JumpCC(cc, op) => Instruction::JumpCC(cc, op),
+            Instruction::JumpIndirect(op) => Instruction::JumpIndirect(replace_operand(&op)),
 
             Instruction::Label(label) => Instruction::Label(label),
             Instruction::Ret => Instruction::Ret,
-            Instruction::Cqo => Instruction::Cqo,
+            Instruction::Call(name, plt) => Instruction::Call(name, plt),
+            Instruction::Cdq(t) => Instruction::Cdq(t),
         };
         new_insts.push(new_inst);
     }
@@ -430,118 +1669,252 @@ fn fix_instructions(insts: Vec<Instruction>) -> Vec<Instruction> {
 
     for inst in insts {
         match inst {
-            Instruction::Add(ref dst, ref src)
-            | Instruction::Sub(ref dst, ref src)
-            | Instruction::Cmp(ref dst, ref src)
-            | Instruction::And(ref dst, ref src)
-            | Instruction::Or(ref dst, ref src)
-            | Instruction::Xor(ref dst, ref src) => {
-                // Check if BOTH are stack locations
-                if let (Operand::StackQWord(_), Operand::StackQWord(_)) = (&dst, &src) {
-                    // Rewrite: add [mem], [mem]  ->  mov r10, [mem]; add r10, [mem]; mov [mem], r10
-
-                    // Load dst into scratch register R10
-                    clean_insts.push(Instruction::Mov(Operand::Reg(Reg::R10), src.clone()));
-
-                    //  Perform operation on R10
-                    // (Construct the same instruction type but with R10)
-                    let new_op = match inst {
-                        Instruction::Add(_, _) => {
-                            Instruction::Add(dst.clone(), Operand::Reg(Reg::R10))
-                        }
-                        Instruction::Sub(_, _) => {
-                            Instruction::Sub(dst.clone(), Operand::Reg(Reg::R10))
-                        }
-                        Instruction::And(_, _) => {
-                            Instruction::And(dst.clone(), Operand::Reg(Reg::R10))
-                        }
-                        Instruction::Or(_, _) => {
-                            Instruction::Or(dst.clone(), Operand::Reg(Reg::R10))
-                        }
-                        Instruction::Xor(_, _) => {
-                            Instruction::Xor(dst.clone(), Operand::Reg(Reg::R10))
-                        }
+            // SSE arithmetic needs its destination in an xmm register
+            Instruction::Add(AsmType::Double, ref dst, ref src)
+            | Instruction::Sub(AsmType::Double, ref dst, ref src)
+            | Instruction::Imul(AsmType::Double, ref dst, ref src)
+            | Instruction::Xor(AsmType::Double, ref dst, ref src)
+            | Instruction::DivDouble(ref dst, ref src)
+                if !matches!(dst, Operand::Reg(_)) =>
+            {
+                let xmm15 = Operand::Reg(Reg::Xmm15);
+                let t = AsmType::Double;
+                clean_insts.push(Instruction::Mov(t, xmm15.clone(), dst.clone()));
+                let new_op = match inst {
+                    Instruction::Add(..) => Instruction::Add(t, xmm15.clone(), src.clone()),
+                    Instruction::Sub(..) => Instruction::Sub(t, xmm15.clone(), src.clone()),
+                    Instruction::Imul(..) => Instruction::Imul(t, xmm15.clone(), src.clone()),
+                    Instruction::Xor(..) => Instruction::Xor(t, xmm15.clone(), src.clone()),
+                    Instruction::DivDouble(..) => Instruction::DivDouble(xmm15.clone(), src.clone()),
+                    _ => unreachable!(),
+                };
+                clean_insts.push(new_op);
+                clean_insts.push(Instruction::Mov(t, dst.clone(), xmm15));
+            }
 
-                        Instruction::Cmp(_, _) => {
-                            Instruction::Cmp(dst.clone(), Operand::Reg(Reg::R10))
-                        }
-                        _ => unreachable!(),
-                    };
-                    clean_insts.push(new_op);
-                } else if let (Operand::Imm(_), Operand::Imm(_)) = (&dst, &src) {
-                    let new_op = match inst {
-                        Instruction::Cmp(_, _) => {
-                            clean_insts.push(Instruction::Mov(Operand::Reg(Reg::R11), dst.clone()));
-                            Instruction::Cmp(Operand::Reg(Reg::R11), src.clone())
-                        }
-                        _ => inst,
-                    };
-                    clean_insts.push(new_op);
+            // The first operand of comisd must be a register
+            Instruction::Cmp(AsmType::Double, ref dst, ref src) if !matches!(dst, Operand::Reg(_)) => {
+                let xmm15 = Operand::Reg(Reg::Xmm15);
+                clean_insts.push(Instruction::Mov(AsmType::Double, xmm15.clone(), dst.clone()));
+                clean_insts.push(Instruction::Cmp(AsmType::Double, xmm15, src.clone()));
+            }
+
+            // Conversions can't target memory, and cvtsi2sd takes no immediate
+            Instruction::Cvttsd2si(t, dst, src) => {
+                if let Operand::Reg(_) = dst {
+                    clean_insts.push(Instruction::Cvttsd2si(t, dst, src));
                 } else {
-                    clean_insts.push(inst);
+                    clean_insts.push(Instruction::Cvttsd2si(t, Operand::Reg(Reg::R11), src));
+                    clean_insts.push(Instruction::Mov(t, dst, Operand::Reg(Reg::R11)));
                 }
             }
+            Instruction::Cvtsi2sd(t, dst, src) => {
+                let src = if let Operand::Imm(_) = src {
+                    clean_insts.push(Instruction::Mov(t, Operand::Reg(Reg::R10), src));
+                    Operand::Reg(Reg::R10)
+                } else {
+                    src
+                };
+
+                if let Operand::Reg(_) = dst {
+                    clean_insts.push(Instruction::Cvtsi2sd(t, dst, src));
+                } else {
+                    clean_insts.push(Instruction::Cvtsi2sd(t, Operand::Reg(Reg::Xmm15), src));
+                    clean_insts.push(Instruction::Mov(
+                        AsmType::Double,
+                        dst,
+                        Operand::Reg(Reg::Xmm15),
+                    ));
+                }
+            }
+
+            Instruction::Add(t, ref dst, ref src)
+            | Instruction::Sub(t, ref dst, ref src)
+            | Instruction::Cmp(t, ref dst, ref src)
+            | Instruction::And(t, ref dst, ref src)
+            | Instruction::Or(t, ref dst, ref src)
+            | Instruction::Xor(t, ref dst, ref src) => {
+                let mut dst = dst.clone();
+                let mut src = src.clone();
+
+                // Only mov can take a 64-bit immediate
+                if src.is_large_imm() {
+                    clean_insts.push(Instruction::Mov(t, Operand::Reg(Reg::R10), src));
+                    src = Operand::Reg(Reg::R10);
+                }
+
+                // Check if BOTH are memory locations
+                if dst.is_memory() && src.is_memory() {
+                    // Rewrite: add [mem], [mem]  ->  mov r10, [mem]; add [mem], r10
+                    clean_insts.push(Instruction::Mov(t, Operand::Reg(Reg::R10), src));
+                    src = Operand::Reg(Reg::R10);
+                }
+
+                // The first operand of cmp can't be an immediate
+                if let Operand::Imm(_) = dst {
+                    clean_insts.push(Instruction::Mov(t, Operand::Reg(Reg::R11), dst));
+                    dst = Operand::Reg(Reg::R11);
+                }
+
+                // Construct the same instruction type with the fixed operands
+                let new_op = match inst {
+                    Instruction::Add(..) => Instruction::Add(t, dst, src),
+                    Instruction::Sub(..) => Instruction::Sub(t, dst, src),
+                    Instruction::And(..) => Instruction::And(t, dst, src),
+                    Instruction::Or(..) => Instruction::Or(t, dst, src),
+                    Instruction::Xor(..) => Instruction::Xor(t, dst, src),
+                    Instruction::Cmp(..) => Instruction::Cmp(t, dst, src),
+                    _ => unreachable!(),
+                };
+                clean_insts.push(new_op);
+            }
 
             // Case: Mov (Cannot move Mem to Mem)
-            Instruction::Mov(ref dst, ref src) => {
-                if let (Operand::StackQWord(_), Operand::StackQWord(_)) = (&dst, &src) {
+            Instruction::Mov(t, ref dst, ref src) => {
+                if let (AsmType::Longword, Operand::Imm(val)) = (t, src) {
+                    // Only the low 32 bits are kept anyway
+                    clean_insts.push(Instruction::Mov(
+                        t,
+                        dst.clone(),
+                        Operand::Imm(*val as i32 as i64),
+                    ));
+                } else if let (AsmType::Byte, Operand::Imm(val)) = (t, src) {
+                    // ... or the low 8
+                    clean_insts.push(Instruction::Mov(
+                        t,
+                        dst.clone(),
+                        Operand::Imm(*val as i8 as i64),
+                    ));
+                } else if dst.is_memory() && (src.is_memory() || src.is_large_imm()) {
                     // Rewrite: mov [dst], [src] -> mov r10, [src]; mov [dst], r10
-                    clean_insts.push(Instruction::Mov(Operand::Reg(Reg::R10), src.clone()));
-                    clean_insts.push(Instruction::Mov(dst.clone(), Operand::Reg(Reg::R10)));
+                    let scratch = match t {
+                        AsmType::Double => Operand::Reg(Reg::Xmm15),
+                        _ => Operand::Reg(Reg::R10),
+                    };
+                    clean_insts.push(Instruction::Mov(t, scratch.clone(), src.clone()));
+                    clean_insts.push(Instruction::Mov(t, dst.clone(), scratch));
                 } else {
                     clean_insts.push(inst);
                 }
             }
 
-            Instruction::SetCC(ref cc, ref dst) => {
-                let inst = match dst {
-                    Operand::Reg(reg) => {
-                        Instruction::SetCC(cc.clone(), Operand::Reg(reg.to_byte_reg()))
-                    }
-                    Operand::StackQWord(offset) => {
-                        Instruction::SetCC(cc.clone(), Operand::StackByte(*offset))
-                    }
-                    _ => unreachable!(),
+            Instruction::Movsx(src_t, dst_t, dst, src) => {
+                // movsx takes neither an immediate source nor a memory destination
+                let src = if let Operand::Imm(_) = src {
+                    clean_insts.push(Instruction::Mov(src_t, Operand::Reg(Reg::R10), src));
+                    Operand::Reg(Reg::R10)
+                } else {
+                    src
                 };
-                clean_insts.push(inst);
+
+                if dst.is_memory() {
+                    clean_insts.push(Instruction::Movsx(src_t, dst_t, Operand::Reg(Reg::R11), src));
+                    clean_insts.push(Instruction::Mov(dst_t, dst, Operand::Reg(Reg::R11)));
+                } else {
+                    clean_insts.push(Instruction::Movsx(src_t, dst_t, dst, src));
+                }
             }
-            Instruction::Sal(ref dst, ref src) => {
-                clean_insts.push(Instruction::Mov(Operand::Reg(Reg::Rcx), src.clone()));
-                clean_insts.push(Instruction::Sal(dst.clone(), Operand::Reg(Reg::Cl)));
+
+            // ... and neither does movzx
+            Instruction::MovZeroExtend(AsmType::Byte, dst_t, dst, src) => {
+                let src = if let Operand::Imm(_) = src {
+                    clean_insts.push(Instruction::Mov(AsmType::Byte, Operand::Reg(Reg::R10), src));
+                    Operand::Reg(Reg::R10)
+                } else {
+                    src
+                };
+
+                let byte = AsmType::Byte;
+                if dst.is_memory() {
+                    let r11 = Operand::Reg(Reg::R11);
+                    clean_insts.push(Instruction::MovZeroExtend(byte, dst_t, r11.clone(), src));
+                    clean_insts.push(Instruction::Mov(dst_t, dst, r11));
+                } else {
+                    clean_insts.push(Instruction::MovZeroExtend(byte, dst_t, dst, src));
+                }
+            }
+
+            Instruction::Sal(t, dst, src) if !matches!(src, Operand::Imm(_)) => {
+                clean_insts.push(Instruction::Mov(t, Operand::Reg(Reg::Rcx), src));
+                clean_insts.push(Instruction::Sal(t, dst, Operand::Reg(Reg::Rcx)));
+            }
+
+            Instruction::Sar(t, dst, src) if !matches!(src, Operand::Imm(_)) => {
+                clean_insts.push(Instruction::Mov(t, Operand::Reg(Reg::Rcx), src));
+                clean_insts.push(Instruction::Sar(t, dst, Operand::Reg(Reg::Rcx)));
+            }
+
+            Instruction::Shr(t, dst, src) if !matches!(src, Operand::Imm(_)) => {
+                clean_insts.push(Instruction::Mov(t, Operand::Reg(Reg::Rcx), src));
+                clean_insts.push(Instruction::Shr(t, dst, Operand::Reg(Reg::Rcx)));
+            }
+
+            // lea can only write to a register
+            Instruction::Lea(dst, src) if dst.is_memory() => {
+                clean_insts.push(Instruction::Lea(Operand::Reg(Reg::R11), src));
+                clean_insts.push(Instruction::Mov(
+                    AsmType::Quadword,
+                    dst,
+                    Operand::Reg(Reg::R11),
+                ));
             }
 
-            Instruction::Sar(ref dst, ref src) => {
-                clean_insts.push(Instruction::Mov(Operand::Reg(Reg::Rcx), src.clone()));
-                clean_insts.push(Instruction::Sar(dst.clone(), Operand::Reg(Reg::Cl)));
+            // A 32-bit mov clears the upper half of a register
+            Instruction::MovZeroExtend(_, _, dst, src) => {
+                if let Operand::Reg(_) = dst {
+                    clean_insts.push(Instruction::Mov(AsmType::Longword, dst, src));
+                } else {
+                    clean_insts.push(Instruction::Mov(
+                        AsmType::Longword,
+                        Operand::Reg(Reg::R11),
+                        src,
+                    ));
+                    clean_insts.push(Instruction::Mov(
+                        AsmType::Quadword,
+                        dst,
+                        Operand::Reg(Reg::R11),
+                    ));
+                }
             }
 
-            Instruction::Idiv(ref src) => {
+            Instruction::Idiv(t, ref src) | Instruction::Div(t, ref src) => {
                 if let Operand::Imm(val) = src {
-                    clean_insts.push(Instruction::Mov(Operand::Reg(Reg::R10), Operand::Imm(*val)));
-                    clean_insts.push(Instruction::Idiv(Operand::Reg(Reg::R10)));
+                    clean_insts.push(Instruction::Mov(t, Operand::Reg(Reg::R10), Operand::Imm(*val)));
+                    let new_op = match inst {
+                        Instruction::Idiv(..) => Instruction::Idiv(t, Operand::Reg(Reg::R10)),
+                        _ => Instruction::Div(t, Operand::Reg(Reg::R10)),
+                    };
+                    clean_insts.push(new_op);
                 } else {
                     clean_insts.push(inst);
                 }
             }
-            Instruction::Imul(ref dst, ref src) => {
-                if let Operand::StackQWord(_) = dst {
-                    clean_insts.push(Instruction::Mov(Operand::Reg(Reg::R11), dst.clone()));
+            Instruction::Imul(t, dst, src) => {
+                let src = if src.is_large_imm() {
+                    clean_insts.push(Instruction::Mov(t, Operand::Reg(Reg::R10), src));
+                    Operand::Reg(Reg::R10)
+                } else {
+                    src
+                };
 
-                    clean_insts.push(Instruction::Imul(Operand::Reg(Reg::R11), src.clone()));
+                if dst.is_memory() {
+                    clean_insts.push(Instruction::Mov(t, Operand::Reg(Reg::R11), dst.clone()));
 
-                    clean_insts.push(Instruction::Mov(dst.clone(), Operand::Reg(Reg::R11)));
+                    clean_insts.push(Instruction::Imul(t, Operand::Reg(Reg::R11), src));
+
+                    clean_insts.push(Instruction::Mov(t, dst, Operand::Reg(Reg::R11)));
                 } else {
-                    clean_insts.push(Instruction::Imul(dst.clone(), src.clone()));
+                    clean_insts.push(Instruction::Imul(t, dst, src));
                 }
             }
 
-            Instruction::Cmp(ref dst, ref src) => {
-                if let (Operand::Imm(_), Operand::Imm(_)) = (&dst, &src) {
-                    clean_insts.push(Instruction::Mov(Operand::Reg(Reg::R11), dst.clone()));
-                    clean_insts.push(Instruction::Mov(Operand::Reg(Reg::R11), src.clone()));
-                } else {
-                    clean_insts.push(inst);
-                }
+            Instruction::Push(ref src) if src.is_large_imm() => {
+                clean_insts.push(Instruction::Mov(
+                    AsmType::Quadword,
+                    Operand::Reg(Reg::R10),
+                    src.clone(),
+                ));
+                clean_insts.push(Instruction::Push(Operand::Reg(Reg::R10)));
             }
 
             // Pass through others
@@ -551,48 +1924,117 @@ fn fix_instructions(insts: Vec<Instruction>) -> Vec<Instruction> {
 
     clean_insts
 }
-fn to_operand(v: &ir::Val) -> Operand {
+fn to_operand(v: &ir::Val, ctx: &mut Context) -> Operand {
     match v {
-        ir::Val::Constant(i) => Operand::Imm(*i),
+        ir::Val::Constant(Const::Double(d)) => ctx.constant(*d),
+        ir::Val::Constant(Const::Int(i)) => Operand::Imm(*i as i64),
+        ir::Val::Constant(Const::Long(i)) => Operand::Imm(*i),
+        // Unsigned values keep their bit pattern
+        ir::Val::Constant(Const::UInt(i)) => Operand::Imm(*i as i32 as i64),
+        ir::Val::Constant(Const::ULong(i)) => Operand::Imm(*i as i64),
+        ir::Val::Constant(Const::Char(i)) => Operand::Imm(*i as i64),
+        ir::Val::Constant(Const::UChar(i)) => Operand::Imm(*i as i8 as i64),
         ir::Val::Var(s) => Operand::Pseudo(s.clone()),
     }
 }
 
-pub fn generate(program: &ir::Program) -> Program {
-    let abstract_asm = select_instructions(&program.function);
+fn asm_type(v: &ir::Val, symbols: &SymbolTable) -> AsmType {
+    match v {
+        ir::Val::Constant(Const::Char(_) | Const::UChar(_)) => AsmType::Byte,
+        ir::Val::Constant(Const::Int(_) | Const::UInt(_)) => AsmType::Longword,
+        ir::Val::Constant(Const::Long(_) | Const::ULong(_)) => AsmType::Quadword,
+        ir::Val::Constant(Const::Double(_)) => AsmType::Double,
+        ir::Val::Var(name) => AsmType::from_type(symbols.type_of(name), symbols),
+    }
+}
 
-    let (stack_asm, stack_size) = allocate_stack(abstract_asm);
+fn is_signed(v: &ir::Val, symbols: &SymbolTable) -> bool {
+    match v {
+        ir::Val::Constant(Const::Char(_) | Const::Int(_) | Const::Long(_)) => true,
+        ir::Val::Constant(
+            Const::UChar(_) | Const::UInt(_) | Const::ULong(_) | Const::Double(_),
+        ) => false,
+        ir::Val::Var(name) => symbols.type_of(name).is_signed(),
+    }
+}
+
+fn generate_function(ir_fn: &ir::Function, symbols: &SymbolTable, ctx: &mut Context) -> Function {
+    let abstract_asm = select_instructions(ir_fn, symbols, ctx);
+
+    // Room for the address a structure is returned through
+    let reserved = match symbols.type_of(&ir_fn.name) {
+        Type::Function(fun_type) if returns_in_memory(&fun_type.ret, symbols) => 8,
+        _ => 0,
+    };
+    let (stack_asm, stack_size) = allocate_stack(abstract_asm, symbols, reserved);
 
-    let mut valid_asm = fix_instructions(stack_asm);
+    let valid_asm = fix_instructions(stack_asm);
 
-    // Insert Prologue/Epilogue
-    valid_asm.insert(0, Instruction::Push(Operand::Reg(Reg::Rbp)));
-    valid_asm.insert(
-        1,
-        Instruction::Mov(Operand::Reg(Reg::Rbp), Operand::Reg(Reg::Rsp)),
-    );
+    // Insert Prologue
+    let mut instructions = vec![
+        Instruction::Push(Operand::Reg(Reg::Rbp)),
+        Instruction::Mov(
+            AsmType::Quadword,
+            Operand::Reg(Reg::Rbp),
+            Operand::Reg(Reg::Rsp),
+        ),
+    ];
 
     if stack_size != 0 {
         let alignment = 16;
         let aligned_size = ((-stack_size + alignment - 1) / alignment) * alignment;
-        valid_asm.insert(
-            2,
-            Instruction::Sub(Operand::Reg(Reg::Rsp), Operand::Imm(aligned_size)),
-        );
-        valid_asm.insert(
-            valid_asm.len() - 1,
-            Instruction::Mov(Operand::Reg(Reg::Rsp), Operand::Reg(Reg::Rbp)),
-        );
+        instructions.push(Instruction::Sub(
+            AsmType::Quadword,
+            Operand::Reg(Reg::Rsp),
+            Operand::Imm(aligned_size as i64),
+        ));
     }
-    valid_asm.insert(
-        valid_asm.len() - 1,
-        Instruction::Pop(Operand::Reg(Reg::Rbp)),
-    );
 
-    Program {
-        function: Function {
-            name: program.function.name.clone(),
-            instructions: valid_asm,
-        },
+    // Every return needs its own epilogue
+    for inst in valid_asm {
+        if inst == Instruction::Ret {
+            instructions.push(Instruction::Mov(
+                AsmType::Quadword,
+                Operand::Reg(Reg::Rsp),
+                Operand::Reg(Reg::Rbp),
+            ));
+            instructions.push(Instruction::Pop(Operand::Reg(Reg::Rbp)));
+        }
+        instructions.push(inst);
+    }
+
+    Function {
+        name: ir_fn.name.clone(),
+        global: ir_fn.global,
+        instructions,
     }
 }
+
+pub fn generate(program: &ir::Program, symbols: &SymbolTable) -> Program {
+    let mut ctx = Context::default();
+    let mut top_level: Vec<TopLevel> = program
+        .top_level
+        .iter()
+        .map(|item| match item {
+            ir::TopLevel::Function(f) => {
+                TopLevel::Function(generate_function(f, symbols, &mut ctx))
+            }
+            ir::TopLevel::StaticVariable(var) => TopLevel::StaticVariable(StaticVariable {
+                name: var.name.clone(),
+                global: var.global,
+                alignment: AsmType::from_type(&var.ty, symbols).alignment(),
+                init: var.init.clone(),
+            }),
+            ir::TopLevel::StaticConstant(constant) => TopLevel::StaticConstant(StaticConstant {
+                name: constant_label(&constant.name),
+                alignment: AsmType::from_type(&constant.ty, symbols).alignment(),
+                init: constant.init.clone(),
+            }),
+        })
+        .collect();
+
+    top_level.extend(ctx.constants.into_iter().map(TopLevel::StaticConstant));
+    top_level.extend(ctx.jump_tables.into_iter().map(TopLevel::JumpTable));
+
+    Program { top_level }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::rc::Rc;
+
+use crate::lexer::LexError;
+use crate::parser::ParseError;
+use crate::semantic::SemanticError;
+use crate::span::{Located, Span};
+use crate::typecheck::TypeError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Note,
+}
+
+/// A message about the program being compiled, rendered like
+///
+/// ```text
+/// error[E0201]: use of undeclared variable 'x'
+///  --> main.c:1:21
+///   |
+/// 1 | int main() { return x; }
+///   |                     ^
+/// ```
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Option<&'static str>,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<Diagnostic>, // all with severity Note
+}
+
+impl Diagnostic {
+    pub fn error(code: Option<&'static str>, message: impl ToString, span: Option<Span>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message: message.to_string(),
+            span,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn note(message: impl ToString, span: Option<Span>) -> Self {
+        Self {
+            severity: Severity::Note,
+            code: None,
+            message: message.to_string(),
+            span,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_note(mut self, note: Diagnostic) -> Self {
+        self.notes.push(note);
+        self
+    }
+}
+
+impl From<LexError> for Diagnostic {
+    fn from(e: LexError) -> Self {
+        Diagnostic::error(Some(e.code()), &e, e.span().cloned())
+    }
+}
+
+impl From<Located<ParseError>> for Diagnostic {
+    fn from(e: Located<ParseError>) -> Self {
+        Diagnostic::error(Some(e.error.code()), &e.error, Some(e.span))
+    }
+}
+
+impl From<Located<SemanticError>> for Diagnostic {
+    fn from(e: Located<SemanticError>) -> Self {
+        let diagnostic = Diagnostic::error(Some(e.error.code()), &e.error, Some(e.span));
+        let note = match e.error {
+            SemanticError::DuplicateDeclaration(name, previous) => {
+                let message = format!("previous declaration of '{}' was here", name);
+                Diagnostic::note(message, Some(previous))
+            }
+            SemanticError::DuplicateLabel(label, previous) => {
+                let message = format!("previous definition of '{}' was here", label);
+                Diagnostic::note(message, Some(previous))
+            }
+            _ => return diagnostic,
+        };
+        diagnostic.with_note(note)
+    }
+}
+
+impl From<Located<TypeError>> for Diagnostic {
+    fn from(e: Located<TypeError>) -> Self {
+        Diagnostic::error(Some(e.error.code()), &e.error, Some(e.span))
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const GREEN: &str = "\x1b[1;32m";
+const BLUE: &str = "\x1b[1;34m";
+
+/// Renders diagnostics to stderr, quoting the source lines they point at.
+/// Each source file is read the first time a diagnostic points into it,
+/// unless its text was handed over with [`Emitter::add_source`].
+pub struct Emitter {
+    color: bool,
+    sources: HashMap<Rc<str>, Option<String>>, // None if the file can't be read
+}
+
+impl Emitter {
+    pub fn new(color: bool) -> Self {
+        Self {
+            color,
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Colors the output only when stderr is a terminal and `NO_COLOR`
+    /// isn't set, so logs and pipes get plain text.
+    pub fn stderr() -> Self {
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        Self::new(io::stderr().is_terminal() && !no_color)
+    }
+
+    /// Supplies the text of a source that isn't a file on disk, such as
+    /// `<stdin>`, so diagnostics pointing into it can quote it.
+    pub fn add_source(&mut self, name: &str, text: String) {
+        self.sources.insert(name.into(), Some(text));
+    }
+
+    pub fn emit(&mut self, diagnostic: &Diagnostic) {
+        eprint!("{}", self.render(diagnostic));
+    }
+
+    pub fn render(&mut self, diagnostic: &Diagnostic) -> String {
+        // Line numbers of the diagnostic and its notes share one gutter
+        let widest_line = std::iter::once(diagnostic)
+            .chain(&diagnostic.notes)
+            .filter_map(|d| d.span.as_ref().map(|span| span.start.line))
+            .max()
+            .unwrap_or(0);
+        let gutter = widest_line.to_string().len();
+
+        let mut out = String::new();
+        self.render_one(&mut out, diagnostic, gutter);
+        for note in diagnostic.notes.iter().filter(|note| note.span.is_some()) {
+            self.render_one(&mut out, note, gutter);
+        }
+        for note in diagnostic.notes.iter().filter(|note| note.span.is_none()) {
+            let blue = self.paint(BLUE);
+            let bold = self.paint(BOLD);
+            let reset = self.paint(RESET);
+            writeln!(out, "{:gutter$} {blue}={reset} {bold}note{reset}: {}", "", note.message)
+                .unwrap();
+        }
+        out.push('\n');
+        out
+    }
+
+    fn render_one(&mut self, out: &mut String, diagnostic: &Diagnostic, gutter: usize) {
+        let (label, color, marker) = match diagnostic.severity {
+            Severity::Error => ("error", RED, '^'),
+            Severity::Note => ("note", GREEN, '-'),
+        };
+        let (color, blue, bold, reset) =
+            (self.paint(color), self.paint(BLUE), self.paint(BOLD), self.paint(RESET));
+
+        match diagnostic.code {
+            Some(code) => write!(out, "{color}{label}[{code}]{reset}").unwrap(),
+            None => write!(out, "{color}{label}{reset}").unwrap(),
+        }
+        writeln!(out, "{bold}: {}{reset}", diagnostic.message).unwrap();
+
+        let Some(span) = &diagnostic.span else {
+            return;
+        };
+        writeln!(out, "{:gutter$}{blue}-->{reset} {}", "", span).unwrap();
+
+        let Some(line) = self.source_line(span) else {
+            return;
+        };
+
+        // Spans over several lines are underlined to the end of the first
+        let start = span.start.column as usize - 1;
+        let end = if span.end.line == span.start.line {
+            span.end.column as usize - 1
+        } else {
+            line.chars().count()
+        };
+        // Tabs are kept so the underline lines up however they are displayed
+        let indent: String = line
+            .chars()
+            .take(start)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let underline = marker.to_string().repeat(end.saturating_sub(start).max(1));
+
+        writeln!(out, "{:gutter$} {blue}|{reset}", "").unwrap();
+        writeln!(out, "{blue}{:>gutter$} |{reset} {}", span.start.line, line).unwrap();
+        writeln!(out, "{:gutter$} {blue}|{reset} {}{color}{}{reset}", "", indent, underline)
+            .unwrap();
+    }
+
+    fn source_line(&mut self, span: &Span) -> Option<String> {
+        let text = self
+            .sources
+            .entry(span.file.clone())
+            .or_insert_with(|| fs::read_to_string(&*span.file).ok());
+        let line = text.as_ref()?.lines().nth(span.start.line as usize - 1)?;
+        Some(line.to_string())
+    }
+
+    fn paint(&self, code: &'static str) -> &'static str {
+        if self.color { code } else { "" }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::Position;
+
+    const SOURCE: &str = "int main(void) {\n\tint x = y;\n  return x\n    + 1;\n}\n";
+
+    fn emitter() -> Emitter {
+        let mut emitter = Emitter::new(false);
+        emitter.sources.insert("test.c".into(), Some(SOURCE.to_string()));
+        emitter
+    }
+
+    fn span(start: (u32, u32), end: (u32, u32)) -> Option<Span> {
+        Some(Span {
+            file: "test.c".into(),
+            start: Position {
+                line: start.0,
+                column: start.1,
+            },
+            end: Position {
+                line: end.0,
+                column: end.1,
+            },
+        })
+    }
+
+    #[test]
+    fn underline_keeps_tabs() {
+        let diagnostic = Diagnostic::error(Some("E0201"), "undeclared", span((2, 10), (2, 11)));
+        assert_eq!(
+            emitter().render(&diagnostic),
+            "error[E0201]: undeclared\n \
+             --> test.c:2:10\n  \
+             |\n\
+             2 | \tint x = y;\n  \
+             | \t        ^\n\n"
+        );
+    }
+
+    #[test]
+    fn multiline_span_underlines_rest_of_first_line() {
+        let diagnostic = Diagnostic::error(None, "bad sum", span((3, 10), (4, 8)));
+        assert_eq!(
+            emitter().render(&diagnostic),
+            "error: bad sum\n \
+             --> test.c:3:10\n  \
+             |\n\
+             3 |   return x\n  \
+             |          ^\n\n"
+        );
+    }
+
+    #[test]
+    fn gutter_fits_widest_line_number() {
+        let source = format!("{}int y;\n", "\n".repeat(9));
+        let mut emitter = Emitter::new(false);
+        emitter.sources.insert("test.c".into(), Some(source));
+        let diagnostic = Diagnostic::error(None, "redeclared", span((10, 5), (10, 6)))
+            .with_note(Diagnostic::note("first here", span((2, 1), (2, 1))));
+        assert_eq!(
+            emitter.render(&diagnostic),
+            "error: redeclared\n  \
+             --> test.c:10:5\n   \
+             |\n\
+             10 | int y;\n   \
+             |     ^\n\
+             note: first here\n  \
+             --> test.c:2:1\n   \
+             |\n \
+             2 | \n   \
+             | -\n\n"
+        );
+    }
+
+    #[test]
+    fn notes_without_spans_come_last() {
+        let diagnostic = Diagnostic::error(None, "too many errors", span((5, 1), (5, 2)))
+            .with_note(Diagnostic::note("stopped here", None))
+            .with_note(Diagnostic::note("opened here", span((1, 16), (1, 17))));
+        assert_eq!(
+            emitter().render(&diagnostic),
+            "error: too many errors\n \
+             --> test.c:5:1\n  \
+             |\n\
+             5 | }\n  \
+             | ^\n\
+             note: opened here\n \
+             --> test.c:1:16\n  \
+             |\n\
+             1 | int main(void) {\n  \
+             |                -\n  \
+             = note: stopped here\n\n"
+        );
+    }
+
+    #[test]
+    fn missing_source_shows_location_only() {
+        let diagnostic = Diagnostic::error(None, "oops", span((1, 1), (1, 2)))
+            .with_note(Diagnostic::note("no file", None));
+        let mut emitter = Emitter::new(false);
+        emitter.sources.insert("test.c".into(), None);
+        assert_eq!(
+            emitter.render(&diagnostic),
+            "error: oops\n --> test.c:1:1\n  = note: no file\n\n"
+        );
+    }
+}
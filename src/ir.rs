@@ -1,3 +1,6 @@
+use crate::ast::{Const, Type};
+use crate::typecheck::StaticInit;
+
 #[derive(Debug, Clone)]
 pub enum UnaryOp {
     Negation,
@@ -27,13 +30,20 @@ pub enum BinaryOp {
 
 #[derive(Debug, Clone)]
 pub enum Val {
-    Constant(i32),
+    Constant(Const),
     Var(String), // Represents both variable names ("x") and temporaries ("tmp.0")
 }
 
 #[derive(Debug, Clone)]
 pub enum Instruction {
     Return(Val),
+    SignExtend(Val, Val), // src, dst
+    Truncate(Val, Val),   // src, dst
+    ZeroExtend(Val, Val), // src, dst
+    DoubleToInt(Val, Val),  // src, dst
+    DoubleToUInt(Val, Val), // src, dst
+    IntToDouble(Val, Val),  // src, dst
+    UIntToDouble(Val, Val), // src, dst
     Unary(UnaryOp, Val, Val),       // op src, dst
     Binary(BinaryOp, Val, Val, Val), // op src1, src2, dst
     Copy(Val, Val),                    //dst,src 
@@ -41,15 +51,48 @@ pub enum Instruction {
     JumpIfZero(Val, String),           // condition, target
     JumpIfNotZero(Val, String),        // condition, target
     Label(String),                     // label_name
+    FunCall(String, Vec<Val>, Val),    // name, args, dst
+    GetAddress(Val, Val),              // src, dst
+    Load(Val, Val),                    // src pointer, dst
+    Store(Val, Val),                   // src, dst pointer
+    AddPtr(Val, Val, i64, Val),        // pointer, index, scale, dst
+    CopyToOffset(Val, String, i64),    // src, dst variable, byte offset
+    CopyFromOffset(String, i64, Val),  // src variable, byte offset, dst
+    JumpTable(Val, Vec<String>),       // index, target of each index
 }
 
 #[derive(Debug)]
 pub struct Function {
     pub name: String,
+    pub global: bool,
+    pub params: Vec<String>,
     pub instructions: Vec<Instruction>,
 }
 
+#[derive(Debug)]
+pub struct StaticVariable {
+    pub name: String,
+    pub global: bool,
+    pub ty: Type,
+    pub init: Vec<StaticInit>,
+}
+
+/// Read-only data, such as a string literal.
+#[derive(Debug)]
+pub struct StaticConstant {
+    pub name: String,
+    pub ty: Type,
+    pub init: StaticInit,
+}
+
+#[derive(Debug)]
+pub enum TopLevel {
+    Function(Function),
+    StaticVariable(StaticVariable),
+    StaticConstant(StaticConstant),
+}
+
 #[derive(Debug)]
 pub struct Program {
-    pub function: Function,
+    pub top_level: Vec<TopLevel>,
 }
@@ -1,22 +1,38 @@
 use crate::ast;
 use crate::ir::{self, Instruction, Val};
+use crate::ast::{Const, SwitchCase, Type};
+use crate::typecheck::{IdentifierAttrs, InitialValue, StaticInit, SymbolTable};
 
-struct Generator {
+/// Where an lvalue lives: a variable, part of one, or the object a pointer
+/// refers to.
+enum Lvalue {
+    Plain(Val),
+    SubObject(String, i64), // aggregate variable, byte offset
+    Dereferenced(Val),      // pointer
+}
+
+struct Generator<'a> {
     instructions: Vec<Instruction>,
     temp_counter: usize,
+    symbols: &'a mut SymbolTable,
+    function: String, // name of the function being generated
 }
 
-impl Generator {
-    fn new() -> Self {
+impl<'a> Generator<'a> {
+    fn new(symbols: &'a mut SymbolTable) -> Self {
         Self {
             instructions: Vec::new(),
             temp_counter: 0,
+            symbols,
+            function: String::new(),
         }
     }
 
-    fn make_temporary(&mut self) -> Val {
+    /// Creates a temporary of type `ty`, recording it so codegen can size it.
+    fn make_temporary(&mut self, ty: &Type) -> Val {
         let name = format!("temp.{}", self.temp_counter);
         self.temp_counter += 1;
+        self.symbols.add_local(name.clone(), ty.clone());
         Val::Var(name)
     }
 
@@ -27,13 +43,119 @@ impl Generator {
     }
 
     fn emit_expression(&mut self, expr: &ast::Expression) -> Val {
-        match expr {
-            ast::Expression::Constant(c) => Val::Constant(*c),
+        match &expr.kind {
+            ast::ExpressionKind::Constant(c) => Val::Constant(*c),
+
+            ast::ExpressionKind::Var(name) => Val::Var(name.clone()),
+
+            // Arrays decay during type checking, so a string is only ever
+            // reached through `emit_lvalue` or as an array initializer
+            ast::ExpressionKind::String(_) => unreachable!("string literals are arrays"),
+
+            ast::ExpressionKind::Cast(ty, inner) => {
+                let src = self.emit_expression(inner);
+                self.emit_cast(src, inner.ty(), ty)
+            }
+
+            ast::ExpressionKind::Assignment(lvalue, value) => {
+                let result = self.emit_expression(value);
+
+                match self.emit_lvalue(lvalue) {
+                    Lvalue::Plain(dst) => {
+                        self.instructions.push(Instruction::Copy(dst.clone(), result));
+                        dst
+                    }
+                    target => {
+                        self.emit_store(&target, result.clone());
+                        result
+                    }
+                }
+            }
+
+            ast::ExpressionKind::CompoundAssignment(op, lvalue, value) => {
+                let rhs = self.emit_expression(value);
+                self.emit_update(lvalue, op, rhs, value.ty(), false)
+            }
+
+            // x++ adds one of the type x is promoted to, or a one element
+            // offset for a pointer
+            ast::ExpressionKind::PostfixIncrement(inner)
+            | ast::ExpressionKind::PostfixDecrement(inner) => {
+                let op = match expr.kind {
+                    ast::ExpressionKind::PostfixIncrement(_) => ast::BinOp::Add,
+                    _ => ast::BinOp::Subtract,
+                };
+                let (one, ty) = match inner.ty() {
+                    Type::Double => (Const::Double(1.0), Type::Double),
+                    Type::Long | Type::Pointer(_) => (Const::Long(1), Type::Long),
+                    Type::UInt => (Const::UInt(1), Type::UInt),
+                    Type::ULong => (Const::ULong(1), Type::ULong),
+                    _ => (Const::Int(1), Type::Int),
+                };
+                self.emit_update(inner, &op, Val::Constant(one), &ty, true)
+            }
+
+            ast::ExpressionKind::Dereference(_)
+            | ast::ExpressionKind::Subscript(..)
+            | ast::ExpressionKind::Member(..)
+            | ast::ExpressionKind::PointerMember(..) => {
+                let target = self.emit_lvalue(expr);
+                self.emit_load(&target, expr.ty())
+            }
+
+            // &*p is just p
+            ast::ExpressionKind::AddressOf(inner) => match self.emit_lvalue(inner) {
+                Lvalue::Plain(src) => {
+                    let dst = self.make_temporary(expr.ty());
+                    self.instructions
+                        .push(Instruction::GetAddress(src, dst.clone()));
+                    dst
+                }
+                Lvalue::SubObject(name, offset) => {
+                    let base = self.make_temporary(expr.ty());
+                    self.instructions
+                        .push(Instruction::GetAddress(Val::Var(name), base.clone()));
+                    self.emit_byte_offset(base, offset, expr.ty())
+                }
+                Lvalue::Dereferenced(ptr) => ptr,
+            },
+
+            ast::ExpressionKind::Conditional(cond, then_expr, else_expr) => {
+                let dst = self.make_temporary(expr.ty());
+                let else_label = self.make_label("cond_else");
+                let end_label = self.make_label("cond_end");
 
-            ast::Expression::UnaryOp((op, inner)) => {
+                let c = self.emit_expression(cond);
+                self.instructions
+                    .push(Instruction::JumpIfZero(c, else_label.clone()));
+
+                let v1 = self.emit_expression(then_expr);
+                self.instructions.push(Instruction::Copy(dst.clone(), v1));
+                self.instructions.push(Instruction::Jump(end_label.clone()));
+
+                self.instructions.push(Instruction::Label(else_label));
+                let v2 = self.emit_expression(else_expr);
+                self.instructions.push(Instruction::Copy(dst.clone(), v2));
+
+                self.instructions.push(Instruction::Label(end_label));
+
+                dst
+            }
+
+            ast::ExpressionKind::FunctionCall(name, args) => {
+                let args = args.iter().map(|arg| self.emit_expression(arg)).collect();
+                let dst = self.make_temporary(expr.ty());
+
+                self.instructions
+                    .push(Instruction::FunCall(name.clone(), args, dst.clone()));
+
+                dst
+            }
+
+            ast::ExpressionKind::UnaryOp((op, inner)) => {
                 let src = self.emit_expression(inner);
 
-                let dst = self.make_temporary();
+                let dst = self.make_temporary(expr.ty());
 
                 let tacky_op = match op {
                     ast::UnOp::Negation => ir::UnaryOp::Negation,
@@ -47,9 +169,9 @@ impl Generator {
                 dst
             }
 
-            ast::Expression::BinaryOp(op, left, right) => match op {
+            ast::ExpressionKind::BinaryOp(op, left, right) => match op {
                 ast::BinOp::LogicalAnd => {
-                    let dst = self.make_temporary();
+                    let dst = self.make_temporary(expr.ty());
                     let false_label = self.make_label("and_false");
                     let end_label = self.make_label("and_end");
 
@@ -63,12 +185,12 @@ impl Generator {
                         .push(Instruction::JumpIfZero(v2, false_label.clone()));
 
                     self.instructions
-                        .push(Instruction::Copy(dst.clone(), Val::Constant(1)));
+                        .push(Instruction::Copy(dst.clone(), Val::Constant(Const::Int(1))));
                     self.instructions.push(Instruction::Jump(end_label.clone()));
 
                     self.instructions.push(Instruction::Label(false_label));
                     self.instructions
-                        .push(Instruction::Copy(dst.clone(), Val::Constant(0)));
+                        .push(Instruction::Copy(dst.clone(), Val::Constant(Const::Int(0))));
 
                     self.instructions.push(Instruction::Label(end_label));
 
@@ -76,7 +198,7 @@ impl Generator {
                 }
 
                 ast::BinOp::LogicalOr => {
-                    let dst = self.make_temporary();
+                    let dst = self.make_temporary(expr.ty());
                     let true_label = self.make_label("or_true");
                     let end_label = self.make_label("or_end");
 
@@ -89,68 +211,756 @@ impl Generator {
                         .push(Instruction::JumpIfNotZero(v2, true_label.clone()));
 
                     self.instructions
-                        .push(Instruction::Copy(dst.clone(), Val::Constant(0)));
+                        .push(Instruction::Copy(dst.clone(), Val::Constant(Const::Int(0))));
                     self.instructions.push(Instruction::Jump(end_label.clone()));
 
                     self.instructions.push(Instruction::Label(true_label));
                     self.instructions
-                        .push(Instruction::Copy(dst.clone(), Val::Constant(1)));
+                        .push(Instruction::Copy(dst.clone(), Val::Constant(Const::Int(1))));
 
                     self.instructions.push(Instruction::Label(end_label));
 
                     dst
                 }
 
+                ast::BinOp::Add if expr.ty().is_pointer() => {
+                    if left.ty().is_pointer() {
+                        self.emit_pointer_offset(left, right, false)
+                    } else {
+                        self.emit_pointer_offset(right, left, false)
+                    }
+                }
+
+                ast::BinOp::Subtract if left.ty().is_pointer() && right.ty().is_integer() => {
+                    self.emit_pointer_offset(left, right, true)
+                }
+
+                // The byte distance between the pointers, divided by the
+                // element size
+                ast::BinOp::Subtract if left.ty().is_pointer() => {
+                    let scale = match left.ty() {
+                        Type::Pointer(element) => self.symbols.size_of(element) as i64,
+                        _ => unreachable!(),
+                    };
+
+                    let v1 = self.emit_expression(left);
+                    let v2 = self.emit_expression(right);
+                    let diff = self.make_temporary(&Type::Long);
+                    self.instructions.push(Instruction::Binary(
+                        ir::BinaryOp::Subtract,
+                        v1,
+                        v2,
+                        diff.clone(),
+                    ));
+
+                    let dst = self.make_temporary(&Type::Long);
+                    self.instructions.push(Instruction::Binary(
+                        ir::BinaryOp::Divide,
+                        diff,
+                        Val::Constant(Const::Long(scale)),
+                        dst.clone(),
+                    ));
+                    dst
+                }
+
                 _ => {
                     let v1 = self.emit_expression(left);
                     let v2 = self.emit_expression(right);
-                    let dst = self.make_temporary();
-
-                    let tacky_op = match op {
-                        ast::BinOp::Add => ir::BinaryOp::Add,
-                        ast::BinOp::Subtract => ir::BinaryOp::Subtract,
-                        ast::BinOp::Multiply => ir::BinaryOp::Multiply,
-                        ast::BinOp::Divide => ir::BinaryOp::Divide,
-                        ast::BinOp::Remainder => ir::BinaryOp::Remainder,
-                        ast::BinOp::BitwiseAnd => ir::BinaryOp::BitwiseAnd,
-                        ast::BinOp::BitwiseXor => ir::BinaryOp::BitwiseXor,
-                        ast::BinOp::BitwiseOr => ir::BinaryOp::BitwiseOr,
-                        ast::BinOp::LeftShift => ir::BinaryOp::LeftShift,
-                        ast::BinOp::RightShift => ir::BinaryOp::RightShift,
-                        ast::BinOp::Equal => ir::BinaryOp::Equal,
-                        ast::BinOp::NotEqual => ir::BinaryOp::NotEqual,
-                        ast::BinOp::LessThan => ir::BinaryOp::LessThan,
-                        ast::BinOp::LessThanEqual => ir::BinaryOp::LessThanEqual,
-                        ast::BinOp::GreaterThan => ir::BinaryOp::GreaterThan,
-                        ast::BinOp::GreaterThanEqual => ir::BinaryOp::GreaterThanEqual,
-                        ast::BinOp::LogicalAnd | ast::BinOp::LogicalOr => unreachable!(),
-                    };
+                    let dst = self.make_temporary(expr.ty());
 
                     self.instructions
-                        .push(Instruction::Binary(tacky_op, v1, v2, dst.clone()));
+                        .push(Instruction::Binary(tacky_binary_op(op), v1, v2, dst.clone()));
                     dst
                 }
             },
         }
     }
 
-    fn emit_function(mut self, func: &ast::FunctionDeclaration) -> ir::Function {
-        match &func.body {
-            ast::Statement::Return(expr) => {
+    /// Converts `src` from type `from` to type `to`.
+    fn emit_cast(&mut self, src: Val, from: &Type, to: &Type) -> Val {
+        if from == to {
+            return src;
+        }
+
+        let dst = self.make_temporary(to);
+        // Same-size integer conversions only reinterpret the bits
+        let instruction = if *to == Type::Double {
+            if from.is_signed() {
+                Instruction::IntToDouble(src, dst.clone())
+            } else {
+                Instruction::UIntToDouble(src, dst.clone())
+            }
+        } else if *from == Type::Double {
+            if to.is_signed() {
+                Instruction::DoubleToInt(src, dst.clone())
+            } else {
+                Instruction::DoubleToUInt(src, dst.clone())
+            }
+        } else if to.size() == from.size() {
+            Instruction::Copy(dst.clone(), src)
+        } else if to.size() < from.size() {
+            Instruction::Truncate(src, dst.clone())
+        } else if from.is_signed() {
+            Instruction::SignExtend(src, dst.clone())
+        } else {
+            Instruction::ZeroExtend(src, dst.clone())
+        };
+        self.instructions.push(instruction);
+        dst
+    }
+
+    /// Reads the object an lvalue designates.
+    fn emit_load(&mut self, target: &Lvalue, ty: &Type) -> Val {
+        match target {
+            Lvalue::Plain(val) => val.clone(),
+            Lvalue::SubObject(name, offset) => {
+                let dst = self.make_temporary(ty);
+                self.instructions
+                    .push(Instruction::CopyFromOffset(name.clone(), *offset, dst.clone()));
+                dst
+            }
+            Lvalue::Dereferenced(ptr) => {
+                let dst = self.make_temporary(ty);
+                self.instructions.push(Instruction::Load(ptr.clone(), dst.clone()));
+                dst
+            }
+        }
+    }
+
+    /// Writes `val` to the object an lvalue designates.
+    fn emit_store(&mut self, target: &Lvalue, val: Val) {
+        let instruction = match target {
+            Lvalue::Plain(dst) => Instruction::Copy(dst.clone(), val),
+            Lvalue::SubObject(name, offset) => {
+                Instruction::CopyToOffset(val, name.clone(), *offset)
+            }
+            Lvalue::Dereferenced(ptr) => Instruction::Store(val, ptr.clone()),
+        };
+        self.instructions.push(instruction);
+    }
+
+    /// Computes `lvalue op rhs` in type `ty` and stores it back, evaluating
+    /// the lvalue only once, so `a[i++] += 1` increments `i` once. Gives the
+    /// stored value, or the original one for a postfix operator.
+    fn emit_update(
+        &mut self,
+        lvalue: &ast::Expression,
+        op: &ast::BinOp,
+        rhs: Val,
+        ty: &Type,
+        postfix: bool,
+    ) -> Val {
+        let lvalue_ty = lvalue.ty();
+        let target = self.emit_lvalue(lvalue);
+        let mut current = self.emit_load(&target, lvalue_ty);
+
+        // A variable is read in place, so keep a copy of its old value
+        if postfix && matches!(target, Lvalue::Plain(_)) {
+            let old = self.make_temporary(lvalue_ty);
+            self.instructions.push(Instruction::Copy(old.clone(), current));
+            current = old;
+        }
+
+        let result = if let Type::Pointer(element) = lvalue_ty {
+            let scale = self.symbols.size_of(element) as i64;
+            let index = if *op == ast::BinOp::Subtract {
+                let negated = self.make_temporary(ty);
+                self.instructions
+                    .push(Instruction::Unary(ir::UnaryOp::Negation, rhs, negated.clone()));
+                negated
+            } else {
+                rhs
+            };
+            let dst = self.make_temporary(lvalue_ty);
+            self.instructions
+                .push(Instruction::AddPtr(current.clone(), index, scale, dst.clone()));
+            dst
+        } else {
+            let lhs = self.emit_cast(current.clone(), lvalue_ty, ty);
+            let dst = self.make_temporary(ty);
+            self.instructions
+                .push(Instruction::Binary(tacky_binary_op(op), lhs, rhs, dst.clone()));
+            self.emit_cast(dst, ty, lvalue_ty)
+        };
+
+        self.emit_store(&target, result.clone());
+        if postfix { current } else { result }
+    }
+
+    /// Computes `ptr + index` (or `ptr - index`), scaling the index by the
+    /// size of the element `ptr` points to.
+    fn emit_pointer_offset(
+        &mut self,
+        ptr: &ast::Expression,
+        index: &ast::Expression,
+        negate: bool,
+    ) -> Val {
+        let scale = match ptr.ty() {
+            Type::Pointer(element) => self.symbols.size_of(element) as i64,
+            _ => unreachable!("pointer arithmetic on a non-pointer"),
+        };
+
+        let ptr_val = self.emit_expression(ptr);
+        let mut index_val = self.emit_expression(index);
+        if negate {
+            let negated = self.make_temporary(index.ty());
+            self.instructions.push(Instruction::Unary(
+                ir::UnaryOp::Negation,
+                index_val,
+                negated.clone(),
+            ));
+            index_val = negated;
+        }
+
+        let dst = self.make_temporary(ptr.ty());
+        self.instructions
+            .push(Instruction::AddPtr(ptr_val, index_val, scale, dst.clone()));
+        dst
+    }
+
+    /// Adds a constant number of bytes to a pointer.
+    fn emit_byte_offset(&mut self, ptr: Val, offset: i64, ty: &Type) -> Val {
+        if offset == 0 {
+            return ptr;
+        }
+        let dst = self.make_temporary(ty);
+        self.instructions.push(Instruction::AddPtr(
+            ptr,
+            Val::Constant(Const::Long(offset)),
+            1,
+            dst.clone(),
+        ));
+        dst
+    }
+
+    /// Byte offset of `member` within the structure type `ty`.
+    fn member_offset(&self, ty: &Type, member: &str) -> i64 {
+        let Type::Structure(_, tag) = ty else {
+            unreachable!("member access is checked by the type checker")
+        };
+        let entry = self.symbols.structure(tag).unwrap();
+        entry.member(member).unwrap().offset as i64
+    }
+
+    /// Evaluates an lvalue without reading the object it designates.
+    fn emit_lvalue(&mut self, expr: &ast::Expression) -> Lvalue {
+        match &expr.kind {
+            ast::ExpressionKind::Var(name) => Lvalue::Plain(Val::Var(name.clone())),
+            ast::ExpressionKind::String(bytes) => {
+                Lvalue::Plain(Val::Var(self.symbols.add_string_constant(bytes.clone())))
+            }
+            ast::ExpressionKind::Dereference(inner) => {
+                Lvalue::Dereferenced(self.emit_expression(inner))
+            }
+            ast::ExpressionKind::Subscript(left, right) => {
+                let (ptr, index) = if left.ty().is_pointer() {
+                    (left, right)
+                } else {
+                    (right, left)
+                };
+                Lvalue::Dereferenced(self.emit_pointer_offset(ptr, index, false))
+            }
+            // The structure may also be a temporary, such as a call's result
+            ast::ExpressionKind::Member(inner, member) => {
+                let offset = self.member_offset(inner.ty(), member);
+                let base = match inner.kind {
+                    ast::ExpressionKind::Var(_)
+                    | ast::ExpressionKind::Dereference(_)
+                    | ast::ExpressionKind::Subscript(..)
+                    | ast::ExpressionKind::Member(..)
+                    | ast::ExpressionKind::PointerMember(..) => self.emit_lvalue(inner),
+                    _ => Lvalue::Plain(self.emit_expression(inner)),
+                };
+                match base {
+                    Lvalue::Plain(Val::Var(name)) => Lvalue::SubObject(name, offset),
+                    Lvalue::Plain(Val::Constant(_)) => unreachable!("constants are scalars"),
+                    Lvalue::SubObject(name, base) => Lvalue::SubObject(name, base + offset),
+                    Lvalue::Dereferenced(ptr) => {
+                        let ptr_ty = Type::Pointer(Box::new(expr.ty().clone()));
+                        Lvalue::Dereferenced(self.emit_byte_offset(ptr, offset, &ptr_ty))
+                    }
+                }
+            }
+            ast::ExpressionKind::PointerMember(inner, member) => {
+                let Type::Pointer(structure) = inner.ty() else {
+                    unreachable!("member access is checked by the type checker")
+                };
+                let offset = self.member_offset(structure, member);
+                let ptr = self.emit_expression(inner);
+                let ptr_ty = Type::Pointer(Box::new(expr.ty().clone()));
+                Lvalue::Dereferenced(self.emit_byte_offset(ptr, offset, &ptr_ty))
+            }
+            _ => unreachable!("lvalues are checked during semantic analysis"),
+        }
+    }
+
+    fn emit_statement(&mut self, stmt: &ast::Statement) {
+        match &stmt.kind {
+            ast::StatementKind::Return(expr) => {
                 let val = self.emit_expression(expr);
                 self.instructions.push(Instruction::Return(val));
             }
+            ast::StatementKind::Expression(expr) => {
+                self.emit_expression(expr);
+            }
+            ast::StatementKind::If(cond, then_stmt, else_stmt) => {
+                let end_label = self.make_label("if_end");
+
+                let c = self.emit_expression(cond);
+
+                match else_stmt {
+                    None => {
+                        self.instructions
+                            .push(Instruction::JumpIfZero(c, end_label.clone()));
+                        self.emit_statement(then_stmt);
+                    }
+                    Some(else_stmt) => {
+                        let else_label = self.make_label("if_else");
+                        self.instructions
+                            .push(Instruction::JumpIfZero(c, else_label.clone()));
+                        self.emit_statement(then_stmt);
+                        self.instructions.push(Instruction::Jump(end_label.clone()));
+
+                        self.instructions.push(Instruction::Label(else_label));
+                        self.emit_statement(else_stmt);
+                    }
+                }
+
+                self.instructions.push(Instruction::Label(end_label));
+            }
+            ast::StatementKind::Compound(block) => self.emit_block(block),
+            ast::StatementKind::While(cond, body, label) => {
+                let continue_label = continue_label(label);
+                let break_label = break_label(label);
+
+                self.instructions
+                    .push(Instruction::Label(continue_label.clone()));
+                let c = self.emit_expression(cond);
+                self.instructions
+                    .push(Instruction::JumpIfZero(c, break_label.clone()));
+
+                self.emit_statement(body);
+                self.instructions.push(Instruction::Jump(continue_label));
+
+                self.instructions.push(Instruction::Label(break_label));
+            }
+            ast::StatementKind::DoWhile(body, cond, label) => {
+                let start_label = start_label(label);
+
+                self.instructions.push(Instruction::Label(start_label.clone()));
+                self.emit_statement(body);
+
+                self.instructions
+                    .push(Instruction::Label(continue_label(label)));
+                let c = self.emit_expression(cond);
+                self.instructions
+                    .push(Instruction::JumpIfNotZero(c, start_label));
+
+                self.instructions.push(Instruction::Label(break_label(label)));
+            }
+            ast::StatementKind::For(init, cond, post, body, label) => {
+                let start_label = start_label(label);
+                let break_label = break_label(label);
+
+                match init.as_ref() {
+                    ast::ForInit::Declaration(decl) => self.emit_declaration(decl),
+                    ast::ForInit::Expression(Some(expr)) => {
+                        self.emit_expression(expr);
+                    }
+                    ast::ForInit::Expression(None) => {}
+                }
+
+                self.instructions.push(Instruction::Label(start_label.clone()));
+                if let Some(cond) = cond {
+                    let c = self.emit_expression(cond);
+                    self.instructions
+                        .push(Instruction::JumpIfZero(c, break_label.clone()));
+                }
+
+                self.emit_statement(body);
+
+                self.instructions
+                    .push(Instruction::Label(continue_label(label)));
+                if let Some(post) = post {
+                    self.emit_expression(post);
+                }
+                self.instructions.push(Instruction::Jump(start_label));
+
+                self.instructions.push(Instruction::Label(break_label));
+            }
+            ast::StatementKind::Switch(cond, body, label, cases) => {
+                let value = self.emit_expression(cond);
+                self.emit_switch_dispatch(value, cond.ty(), cases, label);
+                self.emit_statement(body);
+                self.instructions.push(Instruction::Label(break_label(label)));
+            }
+            // Falls through from the statement before
+            ast::StatementKind::Case(_, stmt, label) | ast::StatementKind::Default(stmt, label) => {
+                self.instructions.push(Instruction::Label(case_label(label)));
+                self.emit_statement(stmt);
+            }
+            ast::StatementKind::Labeled(label, stmt) => {
+                let label = goto_label(&self.function, label);
+                self.instructions.push(Instruction::Label(label));
+                self.emit_statement(stmt);
+            }
+            ast::StatementKind::Goto(label) => {
+                let label = goto_label(&self.function, label);
+                self.instructions.push(Instruction::Jump(label));
+            }
+            ast::StatementKind::Break(label) => {
+                self.instructions.push(Instruction::Jump(break_label(label)));
+            }
+            ast::StatementKind::Continue(label) => {
+                self.instructions
+                    .push(Instruction::Jump(continue_label(label)));
+            }
+            ast::StatementKind::Null => {}
+        }
+    }
+
+    /// Jumps to the case matching `value`, or to the default (the end of the
+    /// switch if there is none). Dense cases index a jump table, sparse ones
+    /// are found with a binary search.
+    fn emit_switch_dispatch(&mut self, value: Val, ty: &Type, cases: &[SwitchCase], label: &str) {
+        let default = match cases.iter().find(|case| case.value.is_none()) {
+            Some(case) => case_label(&case.label),
+            None => break_label(label),
+        };
+
+        let mut targets: Vec<(i128, Const, String)> = cases
+            .iter()
+            .filter_map(|case| {
+                let value = case.value?;
+                Some((sort_key(value), value, case_label(&case.label)))
+            })
+            .collect();
+        targets.sort_by_key(|(key, ..)| *key);
+
+        let (Some(first), Some(last)) = (targets.first(), targets.last()) else {
+            self.instructions.push(Instruction::Jump(default));
+            return;
+        };
+        let range = last.0 - first.0 + 1;
+        if targets.len() >= MIN_JUMP_TABLE_CASES && range <= 3 * targets.len() as i128 {
+            self.emit_jump_table(value, ty, &targets, default);
+        } else {
+            self.emit_case_search(&value, &targets, &default);
+        }
+    }
+
+    /// Checks `value` is within the range of the cases, then jumps through a
+    /// table indexed by its distance from the lowest one.
+    fn emit_jump_table(
+        &mut self,
+        value: Val,
+        ty: &Type,
+        targets: &[(i128, Const, String)],
+        default: String,
+    ) {
+        let (min_key, min, _) = &targets[0];
+        let (max_key, max, _) = &targets[targets.len() - 1];
+
+        for (op, bound) in [(ir::BinaryOp::LessThan, min), (ir::BinaryOp::GreaterThan, max)] {
+            let outside = self.make_temporary(&Type::Int);
+            self.instructions.push(Instruction::Binary(
+                op,
+                value.clone(),
+                Val::Constant(*bound),
+                outside.clone(),
+            ));
+            self.instructions
+                .push(Instruction::JumpIfNotZero(outside, default.clone()));
+        }
+
+        let index = self.make_temporary(ty);
+        self.instructions.push(Instruction::Binary(
+            ir::BinaryOp::Subtract,
+            value,
+            Val::Constant(*min),
+            index.clone(),
+        ));
+
+        let mut table = vec![default; (max_key - min_key + 1) as usize];
+        for (key, _, target) in targets {
+            table[(key - min_key) as usize] = target.clone();
+        }
+        self.instructions.push(Instruction::JumpTable(index, table));
+    }
+
+    /// Binary search over the sorted case values, down to a short chain of
+    /// comparisons.
+    fn emit_case_search(&mut self, value: &Val, targets: &[(i128, Const, String)], default: &str) {
+        if targets.len() <= 3 {
+            for (_, case, target) in targets {
+                self.emit_case_comparison(ir::BinaryOp::Equal, value, *case, target);
+            }
+            self.instructions.push(Instruction::Jump(default.to_string()));
+            return;
+        }
+
+        let mid = targets.len() / 2;
+        let (_, case, target) = &targets[mid];
+        let lower_label = self.make_label("switch_lower");
+        self.emit_case_comparison(ir::BinaryOp::Equal, value, *case, target);
+        self.emit_case_comparison(ir::BinaryOp::LessThan, value, *case, &lower_label);
+
+        self.emit_case_search(value, &targets[mid + 1..], default);
+        self.instructions.push(Instruction::Label(lower_label));
+        self.emit_case_search(value, &targets[..mid], default);
+    }
+
+    /// Jumps to `target` if `value op case` holds.
+    fn emit_case_comparison(&mut self, op: ir::BinaryOp, value: &Val, case: Const, target: &str) {
+        let result = self.make_temporary(&Type::Int);
+        self.instructions.push(Instruction::Binary(
+            op,
+            value.clone(),
+            Val::Constant(case),
+            result.clone(),
+        ));
+        self.instructions
+            .push(Instruction::JumpIfNotZero(result, target.to_string()));
+    }
+
+    fn emit_declaration(&mut self, decl: &ast::VariableDeclaration) {
+        // Static and extern locals are not initialized at runtime
+        if decl.storage_class.is_some() {
+            return;
+        }
+
+        match &decl.init {
+            Some(ast::Initializer::Single(init)) if !decl.ty.is_array() => {
+                let val = self.emit_expression(init);
+                self.instructions
+                    .push(Instruction::Copy(Val::Var(decl.name.clone()), val));
+            }
+            Some(init) => {
+                self.emit_compound_initializer(&decl.name, init, &decl.ty, 0);
+            }
+            None => {}
+        }
+    }
+
+    /// Stores each scalar of an aggregate initializer at its byte offset
+    /// within the variable.
+    fn emit_compound_initializer(
+        &mut self,
+        name: &str,
+        init: &ast::Initializer,
+        ty: &Type,
+        offset: i64,
+    ) {
+        match (init, ty) {
+            (
+                ast::Initializer::Single(ast::Expression {
+                    kind: ast::ExpressionKind::String(bytes),
+                    ..
+                }),
+                Type::Array(_, len),
+            ) => self.emit_string_initializer(name, bytes, *len, offset),
+            (ast::Initializer::Single(expr), _) => {
+                let val = self.emit_expression(expr);
+                self.instructions
+                    .push(Instruction::CopyToOffset(val, name.to_string(), offset));
+            }
+            (ast::Initializer::Compound(inits), Type::Array(element, _)) => {
+                let element_size = self.symbols.size_of(element) as i64;
+                for (i, init) in inits.iter().enumerate() {
+                    let offset = offset + i as i64 * element_size;
+                    self.emit_compound_initializer(name, init, element, offset);
+                }
+            }
+            (ast::Initializer::Compound(inits), Type::Structure(_, tag)) => {
+                let members = self.symbols.structure(tag).unwrap().members.clone();
+                for (init, member) in inits.iter().zip(&members) {
+                    let offset = offset + member.offset as i64;
+                    self.emit_compound_initializer(name, init, &member.ty, offset);
+                }
+            }
+            (ast::Initializer::Compound(_), _) => {
+                unreachable!("compound initializers are checked by the type checker")
+            }
         }
+    }
+
+    /// Copies a string into a character array eight bytes at a time where
+    /// possible, padding it with zeros up to the length of the array.
+    fn emit_string_initializer(&mut self, name: &str, bytes: &[u8], len: usize, offset: i64) {
+        let mut padded = bytes.to_vec();
+        padded.resize(len, 0);
+
+        let mut i = 0;
+        while i < len {
+            let (c, size) = match len - i {
+                8.. => {
+                    let chunk = padded[i..i + 8].try_into().unwrap();
+                    (Const::Long(i64::from_le_bytes(chunk)), 8)
+                }
+                4.. => {
+                    let chunk = padded[i..i + 4].try_into().unwrap();
+                    (Const::Int(i32::from_le_bytes(chunk)), 4)
+                }
+                _ => (Const::Char(padded[i] as i8), 1),
+            };
+            self.instructions.push(Instruction::CopyToOffset(
+                Val::Constant(c),
+                name.to_string(),
+                offset + i as i64,
+            ));
+            i += size;
+        }
+    }
+
+    fn emit_block(&mut self, block: &ast::Block) {
+        for item in &block.items {
+            match item {
+                ast::BlockItem::Statement(stmt) => self.emit_statement(stmt),
+                ast::BlockItem::Declaration(ast::Declaration::Variable(decl)) => {
+                    self.emit_declaration(decl)
+                }
+                // Local function and structure declarations generate no code
+                ast::BlockItem::Declaration(
+                    ast::Declaration::Function(_) | ast::Declaration::Struct(_),
+                ) => {}
+            }
+        }
+    }
+
+    fn emit_function(&mut self, func: &ast::FunctionDeclaration) -> Option<ir::Function> {
+        let body = func.body.as_ref()?;
 
-        ir::Function {
+        self.function = func.name.clone();
+        self.emit_block(body);
+
+        // Falling off the end of a function returns 0 (required for main).
+        // The value is undefined anywhere else, so a structure is left as is.
+        let ret = match &*func.fun_type.ret {
+            ty @ Type::Structure(..) => self.make_temporary(ty),
+            _ => Val::Constant(Const::Int(0)),
+        };
+        self.instructions.push(Instruction::Return(ret));
+
+        Some(ir::Function {
             name: func.name.clone(),
-            instructions: self.instructions,
+            global: self.symbols.is_global(&func.name),
+            params: func.params.clone(),
+            instructions: std::mem::take(&mut self.instructions),
+        })
+    }
+}
+
+/// The TACKY operator for an arithmetic, bitwise or comparison operator.
+/// `&&` and `||` short-circuit, so they become jumps instead.
+fn tacky_binary_op(op: &ast::BinOp) -> ir::BinaryOp {
+    match op {
+        ast::BinOp::Add => ir::BinaryOp::Add,
+        ast::BinOp::Subtract => ir::BinaryOp::Subtract,
+        ast::BinOp::Multiply => ir::BinaryOp::Multiply,
+        ast::BinOp::Divide => ir::BinaryOp::Divide,
+        ast::BinOp::Remainder => ir::BinaryOp::Remainder,
+        ast::BinOp::BitwiseAnd => ir::BinaryOp::BitwiseAnd,
+        ast::BinOp::BitwiseXor => ir::BinaryOp::BitwiseXor,
+        ast::BinOp::BitwiseOr => ir::BinaryOp::BitwiseOr,
+        ast::BinOp::LeftShift => ir::BinaryOp::LeftShift,
+        ast::BinOp::RightShift => ir::BinaryOp::RightShift,
+        ast::BinOp::Equal => ir::BinaryOp::Equal,
+        ast::BinOp::NotEqual => ir::BinaryOp::NotEqual,
+        ast::BinOp::LessThan => ir::BinaryOp::LessThan,
+        ast::BinOp::LessThanEqual => ir::BinaryOp::LessThanEqual,
+        ast::BinOp::GreaterThan => ir::BinaryOp::GreaterThan,
+        ast::BinOp::GreaterThanEqual => ir::BinaryOp::GreaterThanEqual,
+        ast::BinOp::LogicalAnd | ast::BinOp::LogicalOr => unreachable!(),
+    }
+}
+
+// Loop labels come from semantic analysis, so they get their own prefixes to
+// stay clear of the labels produced by `Generator::make_label`.
+fn start_label(loop_label: &str) -> String {
+    format!(".Lstart.{}", loop_label)
+}
+
+fn continue_label(loop_label: &str) -> String {
+    format!(".Lcontinue.{}", loop_label)
+}
+
+fn break_label(loop_label: &str) -> String {
+    format!(".Lbreak.{}", loop_label)
+}
+
+// Case labels are already prefixed with "case" or "default"
+fn case_label(label: &str) -> String {
+    format!(".L{}", label)
+}
+
+// User labels are only unique within their function. Every generated label
+// ends in a number, which an identifier can't start with, so these can't
+// collide with one.
+fn goto_label(function: &str, label: &str) -> String {
+    format!(".L{}.{}", function, label)
+}
+
+/// Fewer cases than this are always searched, however dense.
+const MIN_JUMP_TABLE_CASES: usize = 4;
+
+/// Orders case values the way the switch compares them, signed or unsigned.
+fn sort_key(value: Const) -> i128 {
+    match value {
+        Const::Int(v) => v as i128,
+        Const::Long(v) => v as i128,
+        Const::UInt(v) => v as i128,
+        Const::ULong(v) => v as i128,
+        Const::Char(v) => v as i128,
+        Const::UChar(v) => v as i128,
+        Const::Double(_) => unreachable!("case values are integers"),
+    }
+}
+
+/// Every variable with static storage that is defined in this translation
+/// unit, along with the constants (string literals) it refers to.
+fn static_variables(symbols: &SymbolTable) -> Vec<ir::TopLevel> {
+    let mut statics = Vec::new();
+
+    for (name, symbol) in symbols.iter() {
+        if let IdentifierAttrs::Constant(init) = &symbol.attrs {
+            statics.push(ir::TopLevel::StaticConstant(ir::StaticConstant {
+                name: name.clone(),
+                ty: symbol.ty.clone(),
+                init: init.clone(),
+            }));
+        }
+        if let IdentifierAttrs::Static { init, global } = &symbol.attrs {
+            let init = match init {
+                InitialValue::Initial(values) => values.clone(),
+                InitialValue::Tentative => vec![StaticInit::Zero(symbols.size_of(&symbol.ty))],
+                InitialValue::NoInitializer => continue,
+            };
+            statics.push(ir::TopLevel::StaticVariable(ir::StaticVariable {
+                name: name.clone(),
+                global: *global,
+                ty: symbol.ty.clone(),
+                init,
+            }));
         }
     }
+
+    statics
 }
 
-pub fn generate(program: &ast::Program) -> ir::Program {
-    let generator = Generator::new();
-    let function = generator.emit_function(&program.function);
-    ir::Program { function }
+pub fn generate(program: &ast::Program, symbols: &mut SymbolTable) -> ir::Program {
+    // A single generator keeps temporaries and labels unique across functions
+    let mut generator = Generator::new(symbols);
+    let mut top_level: Vec<ir::TopLevel> = program
+        .declarations
+        .iter()
+        .filter_map(|decl| match decl {
+            ast::Declaration::Function(func) => generator.emit_function(func),
+            ast::Declaration::Variable(_) | ast::Declaration::Struct(_) => None,
+        })
+        .map(ir::TopLevel::Function)
+        .collect();
+
+    top_level.extend(static_variables(symbols));
+
+    ir::Program { top_level }
 }
@@ -1,27 +1,65 @@
+use std::fmt;
 use std::fs;
 use std::io;
 use std::iter::Peekable;
 use std::path::Path;
+use std::rc::Rc;
 use std::str::Chars;
 
+use crate::span::{Position, Span};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     OpenBrace,           // {
     CloseBrace,          // }
     OpenParen,           // (
     CloseParen,          // )
+    OpenBracket,         // [
+    CloseBracket,        // ]
     Semicolon,           // ;
+    Comma,               // ,
+    Dot,                 // .
+    Arrow,               // ->
     IntKeyword,          // int
+    CharKeyword,         // char
+    StructKeyword,       // struct
+    UnionKeyword,        // union
+    LongKeyword,         // long
+    SignedKeyword,       // signed
+    UnsignedKeyword,     // unsigned
+    DoubleKeyword,       // double
+    VoidKeyword,         // void
+    StaticKeyword,       // static
+    ExternKeyword,       // extern
     ReturnKeyword,       // return
+    IfKeyword,           // if
+    ElseKeyword,         // else
+    DoKeyword,           // do
+    WhileKeyword,        // while
+    ForKeyword,          // for
+    BreakKeyword,        // break
+    ContinueKeyword,     // continue
+    SwitchKeyword,       // switch
+    CaseKeyword,         // case
+    DefaultKeyword,      // default
+    GotoKeyword,         // goto
     Identifier(String),  // [a-zA-Z]\w*
-    IntegerLiteral(i32), // [0-9]+
+    // Integer literals are typed by the lexer, so each value fits its type
+    IntegerLiteral(i64), // 42, 0x2A, 052, 0b101010
+    LongLiteral(i64),    // 42L, 3000000000
+    UnsignedIntegerLiteral(u64), // 42u, 0xFFFFFFFF
+    UnsignedLongLiteral(u64),    // 42ul, 0xFFFFFFFFFFFFFFFF
+    DoubleLiteral(f64),  // [0-9]*\.[0-9]*([eE][+-]?[0-9]+)?
+    CharLiteral(u8),     // 'a', '\n'
+    StringLiteral(Vec<u8>), // "...", escapes already resolved
     Negation,            // -
     BitwiseComplement,   // ~
     LogicalNegation,     // !
     Addition,            // +
     Multiplication,      // *
     Division,            // /
-    Decrement,
+    Increment,           // ++
+    Decrement,           // --
     Remainder,
     BitwiseXor,
     BitwiseAnd,
@@ -37,12 +75,201 @@ pub enum Token {
     NotEqual,
     Equal,
     Assign,
+    AdditionAssign,       // +=
+    SubtractionAssign,    // -=
+    MultiplicationAssign, // *=
+    DivisionAssign,       // /=
+    RemainderAssign,      // %=
+    BitwiseAndAssign,     // &=
+    BitwiseOrAssign,      // |=
+    BitwiseXorAssign,     // ^=
+    LeftShiftAssign,      // <<=
+    RightShiftAssign,     // >>=
+    QuestionMark, // ?
+    Colon,        // :
+}
+
+impl Token {
+    /// How the token is written in source, for tokens that are always
+    /// written the same way.
+    pub fn spelling(&self) -> Option<&'static str> {
+        let spelling = match self {
+            Token::OpenBrace => "{",
+            Token::CloseBrace => "}",
+            Token::OpenParen => "(",
+            Token::CloseParen => ")",
+            Token::OpenBracket => "[",
+            Token::CloseBracket => "]",
+            Token::Semicolon => ";",
+            Token::Comma => ",",
+            Token::Dot => ".",
+            Token::Arrow => "->",
+            Token::IntKeyword => "int",
+            Token::CharKeyword => "char",
+            Token::StructKeyword => "struct",
+            Token::UnionKeyword => "union",
+            Token::LongKeyword => "long",
+            Token::SignedKeyword => "signed",
+            Token::UnsignedKeyword => "unsigned",
+            Token::DoubleKeyword => "double",
+            Token::VoidKeyword => "void",
+            Token::StaticKeyword => "static",
+            Token::ExternKeyword => "extern",
+            Token::ReturnKeyword => "return",
+            Token::IfKeyword => "if",
+            Token::ElseKeyword => "else",
+            Token::DoKeyword => "do",
+            Token::WhileKeyword => "while",
+            Token::ForKeyword => "for",
+            Token::BreakKeyword => "break",
+            Token::ContinueKeyword => "continue",
+            Token::SwitchKeyword => "switch",
+            Token::CaseKeyword => "case",
+            Token::DefaultKeyword => "default",
+            Token::GotoKeyword => "goto",
+            Token::Negation => "-",
+            Token::BitwiseComplement => "~",
+            Token::LogicalNegation => "!",
+            Token::Addition => "+",
+            Token::Multiplication => "*",
+            Token::Division => "/",
+            Token::Increment => "++",
+            Token::Decrement => "--",
+            Token::Remainder => "%",
+            Token::BitwiseXor => "^",
+            Token::BitwiseAnd => "&",
+            Token::BitwiseOr => "|",
+            Token::LogicalAnd => "&&",
+            Token::LogicalOr => "||",
+            Token::LeftShift => "<<",
+            Token::RightShift => ">>",
+            Token::LessThan => "<",
+            Token::GreaterThan => ">",
+            Token::LessThanEqual => "<=",
+            Token::GreaterThanEqual => ">=",
+            Token::NotEqual => "!=",
+            Token::Equal => "==",
+            Token::Assign => "=",
+            Token::AdditionAssign => "+=",
+            Token::SubtractionAssign => "-=",
+            Token::MultiplicationAssign => "*=",
+            Token::DivisionAssign => "/=",
+            Token::RemainderAssign => "%=",
+            Token::BitwiseAndAssign => "&=",
+            Token::BitwiseOrAssign => "|=",
+            Token::BitwiseXorAssign => "^=",
+            Token::LeftShiftAssign => "<<=",
+            Token::RightShiftAssign => ">>=",
+            Token::QuestionMark => "?",
+            Token::Colon => ":",
+            Token::Identifier(_)
+            | Token::IntegerLiteral(_)
+            | Token::LongLiteral(_)
+            | Token::UnsignedIntegerLiteral(_)
+            | Token::UnsignedLongLiteral(_)
+            | Token::DoubleLiteral(_)
+            | Token::CharLiteral(_)
+            | Token::StringLiteral(_) => return None,
+        };
+        Some(spelling)
+    }
+}
+
+/// Describes the token for error messages: `` `;` ``, `identifier 'x'`.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(spelling) = self.spelling() {
+            return write!(f, "`{}`", spelling);
+        }
+        match self {
+            Token::Identifier(name) => write!(f, "identifier '{}'", name),
+            Token::IntegerLiteral(v) | Token::LongLiteral(v) => {
+                write!(f, "integer literal '{}'", v)
+            }
+            Token::UnsignedIntegerLiteral(v) | Token::UnsignedLongLiteral(v) => {
+                write!(f, "integer literal '{}u'", v)
+            }
+            Token::DoubleLiteral(v) => write!(f, "floating literal '{}'", v),
+            Token::CharLiteral(byte) => {
+                write!(f, "character literal '{}'", std::ascii::escape_default(*byte))
+            }
+            Token::StringLiteral(bytes) => write!(f, "string literal \"{}\"", bytes.escape_ascii()),
+            _ => unreachable!("tokens with a fixed spelling are written above"),
+        }
+    }
+}
+
+/// A token and where it appears in the source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub enum LexError {
     IoError(io::Error),
-    UnknownToken(char, usize), // char, position
+    UnknownToken(char, Span),
+    InvalidNumber(String, Span), // literal text
+    UnterminatedLiteral(Span),   // the opening quote
+    InvalidEscape(String, Span), // escape sequence
+    InvalidCharLiteral(Span),    // the whole literal
+    IntegerTooLarge(String, Span), // literal text
+    UnterminatedComment(Span),     // the opening /*
+}
+
+impl LexError {
+    /// Where the error is, unless the file couldn't be read at all.
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            LexError::IoError(_) => None,
+            LexError::UnknownToken(_, span)
+            | LexError::InvalidNumber(_, span)
+            | LexError::UnterminatedLiteral(span)
+            | LexError::InvalidEscape(_, span)
+            | LexError::InvalidCharLiteral(span)
+            | LexError::IntegerTooLarge(_, span)
+            | LexError::UnterminatedComment(span) => Some(span),
+        }
+    }
+
+    /// Identifies the kind of error in diagnostics, as in `error[E0002]`.
+    /// Lexer codes are E00xx, then each later pass takes the next hundred.
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexError::IoError(_) => "E0001",
+            LexError::UnknownToken(..) => "E0002",
+            LexError::InvalidNumber(..) => "E0003",
+            LexError::UnterminatedLiteral(_) => "E0004",
+            LexError::InvalidEscape(..) => "E0005",
+            LexError::InvalidCharLiteral(_) => "E0006",
+            LexError::IntegerTooLarge(..) => "E0007",
+            LexError::UnterminatedComment(_) => "E0008",
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexError::IoError(e) => write!(f, "{}", e),
+            LexError::UnknownToken(c, _) => write!(f, "unknown token '{}'", c),
+            LexError::InvalidNumber(text, _) => {
+                write!(f, "invalid numeric literal '{}'", text)
+            }
+            LexError::UnterminatedLiteral(_) => write!(f, "unterminated literal"),
+            LexError::InvalidEscape(text, _) => {
+                write!(f, "invalid escape sequence '\\{}'", text)
+            }
+            LexError::InvalidCharLiteral(_) => {
+                write!(f, "character literal must hold exactly one character")
+            }
+            LexError::IntegerTooLarge(text, _) => {
+                write!(f, "integer literal '{}' is too large for its type", text)
+            }
+            LexError::UnterminatedComment(_) => write!(f, "unterminated comment"),
+        }
+    }
 }
 
 impl From<io::Error> for LexError {
@@ -51,220 +278,585 @@ impl From<io::Error> for LexError {
     }
 }
 
-pub fn lex<P: AsRef<Path>>(file_path: P) -> Result<Vec<Token>, LexError> {
-    let content = fs::read_to_string(file_path)?;
+/// The characters of a source file, keeping track of where the next one is.
+/// A backslash at the end of a line joins it to the next, as if neither the
+/// backslash nor the newline were there, so the lexer never sees either.
+#[derive(Clone)]
+struct Cursor<'a> {
+    chars: Peekable<Chars<'a>>,
+    file: Rc<str>,
+    position: Position,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(source: &'a str, file: Rc<str>) -> Self {
+        Self {
+            chars: source.chars().peekable(),
+            file,
+            position: Position { line: 1, column: 1 },
+        }
+    }
+
+    fn peek(&mut self) -> Option<&char> {
+        self.skip_continuations();
+        self.chars.peek()
+    }
+
+    /// Looks `n` characters past the next one.
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        let mut ahead = self.clone();
+        for _ in 0..n {
+            ahead.next();
+        }
+        ahead.peek().copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        self.skip_continuations();
+        self.bump()
+    }
+
+    /// Skips any backslash-newline pairs at the cursor. `\r\n` line endings
+    /// count as a newline too.
+    fn skip_continuations(&mut self) {
+        loop {
+            let mut ahead = self.chars.clone();
+            let len = match (ahead.next(), ahead.next(), ahead.next()) {
+                (Some('\\'), Some('\n'), _) => 2,
+                (Some('\\'), Some('\r'), Some('\n')) => 3,
+                _ => return,
+            };
+            for _ in 0..len {
+                self.bump();
+            }
+        }
+    }
+
+    /// Takes the next character as it is in the source.
+    fn bump(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 1;
+        } else {
+            self.position.column += 1;
+        }
+        Some(c)
+    }
+
+    /// The span from `start` up to the next character.
+    fn span_from(&self, start: Position) -> Span {
+        Span {
+            file: self.file.clone(),
+            start,
+            end: self.position,
+        }
+    }
+}
+
+pub fn lex<P: AsRef<Path>>(file_path: P) -> Result<Vec<SpannedToken>, LexError> {
+    let content = fs::read_to_string(&file_path)?;
+    lex_str(&content, &file_path.as_ref().display().to_string())
+}
 
+/// Lexes source text that is already in memory. `name` identifies the
+/// source in spans, the way a file path would, e.g. `<stdin>`.
+pub fn lex_str(source: &str, name: &str) -> Result<Vec<SpannedToken>, LexError> {
     let mut tokens = Vec::new();
-    let mut chars = content.chars().peekable();
-    let mut pos = 0;
+    let mut chars = Cursor::new(source, name.into());
 
     while let Some(&c) = chars.peek() {
-        match c {
+        let start = chars.position;
+        let token = match c {
             c if c.is_whitespace() => {
                 chars.next();
-                pos += 1;
+                continue;
             }
 
             '{' => {
-                tokens.push(Token::OpenBrace);
                 chars.next();
-                pos += 1;
+                Token::OpenBrace
             }
             '}' => {
-                tokens.push(Token::CloseBrace);
                 chars.next();
-                pos += 1;
+                Token::CloseBrace
             }
             '(' => {
-                tokens.push(Token::OpenParen);
                 chars.next();
-                pos += 1;
+                Token::OpenParen
             }
             ')' => {
-                tokens.push(Token::CloseParen);
                 chars.next();
-                pos += 1;
+                Token::CloseParen
+            }
+            '[' => {
+                chars.next();
+                Token::OpenBracket
+            }
+            ']' => {
+                chars.next();
+                Token::CloseBracket
             }
             ';' => {
-                tokens.push(Token::Semicolon);
                 chars.next();
-                pos += 1;
+                Token::Semicolon
+            }
+            ',' => {
+                chars.next();
+                Token::Comma
             }
             '-' => {
                 chars.next();
-                pos += 1;
 
                 match chars.peek() {
                     Some(&'-') => {
                         chars.next();
-                        pos += 1;
-                        tokens.push(Token::Decrement);
+                        Token::Decrement
                     }
-                    _ => {
-                        tokens.push(Token::Negation);
+                    Some(&'>') => {
+                        chars.next();
+                        Token::Arrow
                     }
+                    Some(&'=') => {
+                        chars.next();
+                        Token::SubtractionAssign
+                    }
+                    _ => Token::Negation,
                 }
             }
             '&' => {
                 chars.next();
-                pos += 1;
 
                 match chars.peek() {
                     Some(&'&') => {
                         chars.next();
-                        pos += 1;
-                        tokens.push(Token::LogicalAnd);
+                        Token::LogicalAnd
                     }
-                    _ => {
-                        tokens.push(Token::BitwiseAnd);
+                    Some(&'=') => {
+                        chars.next();
+                        Token::BitwiseAndAssign
                     }
+                    _ => Token::BitwiseAnd,
                 }
             }
             '<' => {
                 chars.next();
-                pos += 1;
 
                 match chars.peek() {
                     Some(&'<') => {
                         chars.next();
-                        pos += 1;
-                        tokens.push(Token::LeftShift);
+
+                        if chars.peek() == Some(&'=') {
+                            chars.next();
+                            Token::LeftShiftAssign
+                        } else {
+                            Token::LeftShift
+                        }
                     }
                     Some(&'=') => {
                         chars.next();
-                        pos += 1;
-                        tokens.push(Token::LessThanEqual);
-                    }
-                    _ => {
-                        tokens.push(Token::LessThan);
+                        Token::LessThanEqual
                     }
+                    _ => Token::LessThan,
                 }
             }
             '>' => {
                 chars.next();
-                pos += 1;
 
                 match chars.peek() {
                     Some(&'>') => {
                         chars.next();
-                        pos += 1;
-                        tokens.push(Token::RightShift);
+
+                        if chars.peek() == Some(&'=') {
+                            chars.next();
+                            Token::RightShiftAssign
+                        } else {
+                            Token::RightShift
+                        }
                     }
                     Some(&'=') => {
                         chars.next();
-                        pos += 1;
-                        tokens.push(Token::GreaterThanEqual);
-                    }
-                    _ => {
-                        tokens.push(Token::GreaterThan);
+                        Token::GreaterThanEqual
                     }
+                    _ => Token::GreaterThan,
                 }
             }
             '=' => {
                 chars.next();
-                pos += 1;
 
                 match chars.peek() {
                     Some(&'=') => {
                         chars.next();
-                        pos += 1;
-                        tokens.push(Token::Equal);
-                    }
-                    _ => {
-                        tokens.push(Token::Assign);
+                        Token::Equal
                     }
+                    _ => Token::Assign,
                 }
             }
             '|' => {
                 chars.next();
-                pos += 1;
 
                 match chars.peek() {
                     Some(&'|') => {
                         chars.next();
-                        pos += 1;
-                        tokens.push(Token::LogicalOr);
+                        Token::LogicalOr
                     }
-                    _ => {
-                        tokens.push(Token::BitwiseOr);
+                    Some(&'=') => {
+                        chars.next();
+                        Token::BitwiseOrAssign
                     }
+                    _ => Token::BitwiseOr,
                 }
             }
             '^' => {
-                tokens.push(Token::BitwiseXor);
                 chars.next();
-                pos += 1;
+
+                match chars.peek() {
+                    Some(&'=') => {
+                        chars.next();
+                        Token::BitwiseXorAssign
+                    }
+                    _ => Token::BitwiseXor,
+                }
             }
             '~' => {
-                tokens.push(Token::BitwiseComplement);
                 chars.next();
-                pos += 1;
+                Token::BitwiseComplement
             }
             '!' => {
                 chars.next();
-                pos += 1;
 
                 match chars.peek() {
                     Some(&'=') => {
                         chars.next();
-                        pos += 1;
-                        tokens.push(Token::NotEqual);
-                    }
-                    _ => {
-                        tokens.push(Token::LogicalNegation);
+                        Token::NotEqual
                     }
+                    _ => Token::LogicalNegation,
                 }
             }
             '+' => {
-                tokens.push(Token::Addition);
                 chars.next();
-                pos += 1;
+
+                match chars.peek() {
+                    Some(&'+') => {
+                        chars.next();
+                        Token::Increment
+                    }
+                    Some(&'=') => {
+                        chars.next();
+                        Token::AdditionAssign
+                    }
+                    _ => Token::Addition,
+                }
             }
             '*' => {
-                tokens.push(Token::Multiplication);
                 chars.next();
-                pos += 1;
+
+                match chars.peek() {
+                    Some(&'=') => {
+                        chars.next();
+                        Token::MultiplicationAssign
+                    }
+                    _ => Token::Multiplication,
+                }
             }
             '/' => {
-                tokens.push(Token::Division);
                 chars.next();
-                pos += 1;
+
+                match chars.peek() {
+                    Some(&'=') => {
+                        chars.next();
+                        Token::DivisionAssign
+                    }
+                    // Comments are skipped like whitespace
+                    Some(&'/') => {
+                        while chars.peek().is_some_and(|&ch| ch != '\n') {
+                            chars.next();
+                        }
+                        continue;
+                    }
+                    Some(&'*') => {
+                        chars.next();
+                        skip_block_comment(&mut chars, start)?;
+                        continue;
+                    }
+                    _ => Token::Division,
+                }
             }
             '%' => {
-                tokens.push(Token::Remainder);
                 chars.next();
-                pos += 1;
+
+                match chars.peek() {
+                    Some(&'=') => {
+                        chars.next();
+                        Token::RemainderAssign
+                    }
+                    _ => Token::Remainder,
+                }
+            }
+            '?' => {
+                chars.next();
+                Token::QuestionMark
+            }
+            ':' => {
+                chars.next();
+                Token::Colon
             }
             c if c.is_ascii_alphabetic() => {
                 let text = consume_while(&mut chars, |ch| ch.is_ascii_alphanumeric() || ch == '_');
-                pos += text.len();
 
-                let token = match text.as_str() {
+                match text.as_str() {
                     "int" => Token::IntKeyword,
+                    "char" => Token::CharKeyword,
+                    "struct" => Token::StructKeyword,
+                    "union" => Token::UnionKeyword,
+                    "long" => Token::LongKeyword,
+                    "signed" => Token::SignedKeyword,
+                    "unsigned" => Token::UnsignedKeyword,
+                    "double" => Token::DoubleKeyword,
+                    "void" => Token::VoidKeyword,
+                    "static" => Token::StaticKeyword,
+                    "extern" => Token::ExternKeyword,
                     "return" => Token::ReturnKeyword,
+                    "if" => Token::IfKeyword,
+                    "else" => Token::ElseKeyword,
+                    "do" => Token::DoKeyword,
+                    "while" => Token::WhileKeyword,
+                    "for" => Token::ForKeyword,
+                    "break" => Token::BreakKeyword,
+                    "continue" => Token::ContinueKeyword,
+                    "switch" => Token::SwitchKeyword,
+                    "case" => Token::CaseKeyword,
+                    "default" => Token::DefaultKeyword,
+                    "goto" => Token::GotoKeyword,
                     _ => Token::Identifier(text),
-                };
-                tokens.push(token);
+                }
+            }
+
+            '\'' => {
+                chars.next();
+
+                let bytes = lex_quoted(&mut chars, '\'', start)?;
+                match bytes.as_slice() {
+                    [byte] => Token::CharLiteral(*byte),
+                    _ => return Err(LexError::InvalidCharLiteral(chars.span_from(start))),
+                }
+            }
+            '"' => {
+                chars.next();
+                Token::StringLiteral(lex_quoted(&mut chars, '"', start)?)
+            }
+
+            // Hex and binary literals are always integers: 0x1F, 0b101
+            '0' if matches!(chars.peek_nth(1), Some('x' | 'X' | 'b' | 'B')) => {
+                chars.next();
+                let prefix = chars.next().unwrap();
+                let radix = if prefix.eq_ignore_ascii_case(&'x') { 16 } else { 2 };
+                let digits = consume_while(&mut chars, |ch| ch.is_digit(radix));
+                let suffix =
+                    consume_while(&mut chars, |ch| ch.is_ascii_alphanumeric() || ch == '_');
+                let text = format!("0{}{}{}", prefix, digits, suffix);
+                integer_literal(&digits, radix, &suffix, text, chars.span_from(start))?
             }
 
-            c if c.is_ascii_digit() => {
-                let num_str = consume_while(&mut chars, |ch| ch.is_ascii_digit());
-                pos += num_str.len();
+            // A leading '.' only starts a number when a digit follows, e.g. `.5`
+            c if c.is_ascii_digit()
+                || (c == '.' && chars.peek_nth(1).is_some_and(|ch| ch.is_ascii_digit())) =>
+            {
+                let mut num_str = consume_while(&mut chars, |ch| ch.is_ascii_digit());
+                let mut is_double = false;
 
-                let value: i32 = num_str.parse().unwrap_or(0);
-                tokens.push(Token::IntegerLiteral(value));
+                if chars.peek() == Some(&'.') {
+                    chars.next();
+                    num_str.push('.');
+                    num_str.push_str(&consume_while(&mut chars, |ch| ch.is_ascii_digit()));
+                    is_double = true;
+                }
+
+                if let Some(&e @ ('e' | 'E')) = chars.peek() {
+                    chars.next();
+                    num_str.push(e);
+                    if let Some(&sign @ ('+' | '-')) = chars.peek() {
+                        chars.next();
+                        num_str.push(sign);
+                    }
+                    let exponent = consume_while(&mut chars, |ch| ch.is_ascii_digit());
+                    if exponent.is_empty() {
+                        return Err(LexError::InvalidNumber(num_str, chars.span_from(start)));
+                    }
+                    num_str.push_str(&exponent);
+                    is_double = true;
+                }
+
+                if is_double {
+                    match num_str.parse() {
+                        Ok(value) => Token::DoubleLiteral(value),
+                        Err(_) => {
+                            return Err(LexError::InvalidNumber(num_str, chars.span_from(start)));
+                        }
+                    }
+                } else {
+                    let suffix =
+                        consume_while(&mut chars, |ch| ch.is_ascii_alphanumeric() || ch == '_');
+                    let text = format!("{}{}", num_str, suffix);
+
+                    // A leading 0 makes an integer octal: 017 == 15
+                    let (digits, radix) = match num_str.strip_prefix('0') {
+                        Some(rest) if !rest.is_empty() => (rest, 8),
+                        _ => (num_str.as_str(), 10),
+                    };
+                    integer_literal(digits, radix, &suffix, text, chars.span_from(start))?
+                }
+            }
+
+            '.' => {
+                chars.next();
+                Token::Dot
             }
 
             _ => {
-                return Err(LexError::UnknownToken(c, pos));
+                chars.next();
+                return Err(LexError::UnknownToken(c, chars.span_from(start)));
             }
-        }
+        };
+
+        tokens.push(SpannedToken {
+            token,
+            span: chars.span_from(start),
+        });
     }
 
     Ok(tokens)
 }
 
-fn consume_while<F>(chars: &mut Peekable<Chars>, predicate: F) -> String
+/// Skips the rest of a `/* */` comment whose `/*` is at `start`. Comments
+/// don't nest, so the first `*/` ends it.
+fn skip_block_comment(chars: &mut Cursor, start: Position) -> Result<(), LexError> {
+    let opening = chars.span_from(start);
+    while let Some(c) = chars.next() {
+        if c == '*' && chars.peek() == Some(&'/') {
+            chars.next();
+            return Ok(());
+        }
+    }
+    Err(LexError::UnterminatedComment(opening))
+}
+
+/// Makes the token for an integer literal, typed as the first of the types
+/// C allows for its suffix and base that can hold the value (C17 6.4.4.1).
+/// `text` is the whole literal, for error messages.
+fn integer_literal(
+    digits: &str,
+    radix: u32,
+    suffix: &str,
+    text: String,
+    span: Span,
+) -> Result<Token, LexError> {
+    if digits.is_empty() || !digits.chars().all(|ch| ch.is_digit(radix)) {
+        return Err(LexError::InvalidNumber(text, span));
+    }
+
+    // Suffix letters may come in either order, 10ul or 10LU, but the two
+    // letters of `ll` must match. long long is the same size as long here.
+    let (unsigned, long) = match suffix.to_ascii_lowercase().as_str() {
+        _ if suffix.contains("lL") || suffix.contains("Ll") => {
+            return Err(LexError::InvalidNumber(text, span));
+        }
+        "" => (false, false),
+        "u" => (true, false),
+        "l" | "ll" => (false, true),
+        "ul" | "lu" | "ull" | "llu" => (true, true),
+        _ => return Err(LexError::InvalidNumber(text, span)),
+    };
+
+    let Ok(value) = u64::from_str_radix(digits, radix) else {
+        return Err(LexError::IntegerTooLarge(text, span));
+    };
+
+    // Decimal literals only become unsigned with a `u` suffix
+    let may_be_unsigned = unsigned || radix != 10;
+    let token = if !long && !unsigned && value <= i32::MAX as u64 {
+        Token::IntegerLiteral(value as i64)
+    } else if !long && may_be_unsigned && value <= u32::MAX as u64 {
+        Token::UnsignedIntegerLiteral(value)
+    } else if !unsigned && value <= i64::MAX as u64 {
+        Token::LongLiteral(value as i64)
+    } else if may_be_unsigned {
+        Token::UnsignedLongLiteral(value)
+    } else {
+        return Err(LexError::IntegerTooLarge(text, span));
+    };
+    Ok(token)
+}
+
+/// Reads the contents of a character or string literal up to the closing
+/// `quote`, resolving escape sequences. Other characters are kept as their
+/// UTF-8 bytes.
+fn lex_quoted(chars: &mut Cursor, quote: char, start: Position) -> Result<Vec<u8>, LexError> {
+    let mut bytes = Vec::new();
+
+    loop {
+        let escape_start = chars.position;
+        let c = match chars.next() {
+            Some('\n') | None => return Err(LexError::UnterminatedLiteral(chars.span_from(start))),
+            Some(c) => c,
+        };
+
+        if c == quote {
+            return Ok(bytes);
+        }
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+
+        let Some(c) = chars.next() else {
+            return Err(LexError::UnterminatedLiteral(chars.span_from(start)));
+        };
+
+        let byte = match c {
+            'n' => b'\n',
+            't' => b'\t',
+            'r' => b'\r',
+            'a' => 0x07,
+            'b' => 0x08,
+            'f' => 0x0c,
+            'v' => 0x0b,
+            '\\' | '\'' | '"' | '?' => c as u8,
+
+            // Up to three octal digits, e.g. \0 or \177
+            '0'..='7' => {
+                let mut digits = c.to_string();
+                while digits.len() < 3
+                    && let Some(&d @ '0'..='7') = chars.peek()
+                {
+                    digits.push(d);
+                    chars.next();
+                }
+                u8::try_from(u32::from_str_radix(&digits, 8).unwrap())
+                    .map_err(|_| LexError::InvalidEscape(digits, chars.span_from(escape_start)))?
+            }
+
+            // Any number of hex digits, as long as the value fits a byte
+            'x' => {
+                let digits = consume_while(chars, |ch| ch.is_ascii_hexdigit());
+                let span = chars.span_from(escape_start);
+                u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(|val| u8::try_from(val).ok())
+                    .ok_or_else(|| LexError::InvalidEscape(format!("x{}", digits), span))?
+            }
+
+            _ => {
+                let span = chars.span_from(escape_start);
+                return Err(LexError::InvalidEscape(c.to_string(), span));
+            }
+        };
+        bytes.push(byte);
+    }
+}
+
+fn consume_while<F>(chars: &mut Cursor, predicate: F) -> String
 where
     F: Fn(char) -> bool,
 {
@@ -279,3 +871,175 @@ where
     }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<Token> {
+        let tokens = lex_str(source, "test.c").unwrap_or_else(|e| panic!("{}", e));
+        tokens.into_iter().map(|t| t.token).collect()
+    }
+
+    fn error(source: &str) -> LexError {
+        lex_str(source, "test.c").expect_err("should not lex")
+    }
+
+    #[test]
+    fn integer_radixes() {
+        assert_eq!(
+            tokens("31 0x1F 0X1f 037 0b11111 0B11111 0"),
+            vec![
+                Token::IntegerLiteral(31),
+                Token::IntegerLiteral(31),
+                Token::IntegerLiteral(31),
+                Token::IntegerLiteral(31),
+                Token::IntegerLiteral(31),
+                Token::IntegerLiteral(31),
+                Token::IntegerLiteral(0),
+            ]
+        );
+        // Only integers are octal
+        assert_eq!(tokens("017.5"), vec![Token::DoubleLiteral(17.5)]);
+    }
+
+    #[test]
+    fn unsuffixed_literal_types() {
+        // Decimal literals skip the unsigned types; other bases don't
+        assert_eq!(
+            tokens("2147483647 2147483648 0x7FFFFFFF 0x80000000 0x100000000"),
+            vec![
+                Token::IntegerLiteral(2147483647),
+                Token::LongLiteral(2147483648),
+                Token::IntegerLiteral(0x7FFFFFFF),
+                Token::UnsignedIntegerLiteral(0x80000000),
+                Token::LongLiteral(0x100000000),
+            ]
+        );
+        assert_eq!(
+            tokens("9223372036854775807 0x8000000000000000"),
+            vec![
+                Token::LongLiteral(i64::MAX),
+                Token::UnsignedLongLiteral(0x8000000000000000),
+            ]
+        );
+    }
+
+    #[test]
+    fn suffixed_literal_types() {
+        assert_eq!(
+            tokens("10u 4294967296u 10l 10L 10ll 10LL 0xFFFFFFFFFFFFFFFFl"),
+            vec![
+                Token::UnsignedIntegerLiteral(10),
+                Token::UnsignedLongLiteral(4294967296),
+                Token::LongLiteral(10),
+                Token::LongLiteral(10),
+                Token::LongLiteral(10),
+                Token::LongLiteral(10),
+                Token::UnsignedLongLiteral(u64::MAX),
+            ]
+        );
+        assert_eq!(
+            tokens("10ul 10LU 10uLL 10llu"),
+            vec![Token::UnsignedLongLiteral(10); 4]
+        );
+    }
+
+    #[test]
+    fn invalid_integer_literals() {
+        for source in ["09", "0x", "0b", "0b2", "0x1g", "10lL", "10Ll", "10lul", "10uu", "10z"] {
+            assert!(
+                matches!(error(source), LexError::InvalidNumber(ref text, _) if text == source),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn integer_literals_out_of_range() {
+        for source in [
+            "9223372036854775808",
+            "9223372036854775808l",
+            "18446744073709551616u",
+            "0x10000000000000000",
+        ] {
+            assert!(
+                matches!(error(source), LexError::IntegerTooLarge(ref text, _) if text == source),
+                "{}",
+                source
+            );
+        }
+        assert_eq!(
+            tokens("18446744073709551615u"),
+            vec![Token::UnsignedLongLiteral(u64::MAX)]
+        );
+    }
+
+    fn identifiers(names: &[&str]) -> Vec<Token> {
+        names.iter().map(|name| Token::Identifier(name.to_string())).collect()
+    }
+
+    #[test]
+    fn comments_are_skipped() {
+        assert_eq!(tokens("a // b\nc"), identifiers(&["a", "c"]));
+        assert_eq!(tokens("a /* b\n b */ c"), identifiers(&["a", "c"]));
+        assert_eq!(tokens("a/**/c /***/ d"), identifiers(&["a", "c", "d"]));
+        // `/*/` opens a comment without closing it
+        assert_eq!(tokens("a /*/ b */ c"), identifiers(&["a", "c"]));
+        // Comments don't nest
+        assert_eq!(
+            tokens("a /* /* */ c */"),
+            vec![
+                Token::Identifier("a".to_string()),
+                Token::Identifier("c".to_string()),
+                Token::Multiplication,
+                Token::Division,
+            ]
+        );
+        assert_eq!(
+            tokens("a / c /= d"),
+            vec![
+                Token::Identifier("a".to_string()),
+                Token::Division,
+                Token::Identifier("c".to_string()),
+                Token::DivisionAssign,
+                Token::Identifier("d".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn line_comment_continued_by_backslash() {
+        assert_eq!(tokens("a // b \\\n still b\nc"), identifiers(&["a", "c"]));
+        assert_eq!(tokens("a // b \\\r\n still b\r\nc"), identifiers(&["a", "c"]));
+    }
+
+    #[test]
+    fn unterminated_comment_points_at_opening() {
+        let LexError::UnterminatedComment(span) = error("int x;\n  /* never\n closed") else {
+            panic!("expected an unterminated comment");
+        };
+        assert_eq!(span.start, Position { line: 2, column: 3 });
+        assert_eq!(span.end, Position { line: 2, column: 5 });
+        assert!(matches!(error("/*/"), LexError::UnterminatedComment(_)));
+    }
+
+    #[test]
+    fn backslash_newline_joins_lines() {
+        assert_eq!(tokens("ma\\\nin"), identifiers(&["main"]));
+        assert_eq!(tokens("ma\\\r\nin"), identifiers(&["main"]));
+        assert_eq!(tokens("1\\\n2\\\n3"), vec![Token::IntegerLiteral(123)]);
+        assert_eq!(tokens("+\\\n="), vec![Token::AdditionAssign]);
+        assert_eq!(tokens("/\\\n/ comment\nx"), identifiers(&["x"]));
+        assert_eq!(tokens("\"ab\\\ncd\""), vec![Token::StringLiteral(b"abcd".to_vec())]);
+    }
+
+    #[test]
+    fn spans_after_continuations() {
+        let tokens = lex_str("ma\\\nin x", "test.c").unwrap();
+        assert_eq!(tokens[0].span.start, Position { line: 1, column: 1 });
+        assert_eq!(tokens[0].span.end, Position { line: 2, column: 3 });
+        assert_eq!(tokens[1].span.start, Position { line: 2, column: 4 });
+    }
+}
@@ -0,0 +1,145 @@
+//! A compiler for a subset of C that emits x86-64 assembly in Intel syntax.
+//!
+//! [`compile_file`] and [`compile_str`] run every pass and hand back what
+//! each one produced, so tools can look at the tokens, the checked syntax
+//! tree or the IR as well as the assembly. The passes are public modules
+//! too, for callers that want to drive them one at a time.
+
+pub mod ast;
+pub mod codegen;
+pub mod diagnostics;
+pub mod ir;
+pub mod ir_gen;
+pub mod lexer;
+pub mod parser;
+pub mod semantic;
+pub mod span;
+pub mod typecheck;
+
+use std::fmt;
+use std::path::Path;
+
+use diagnostics::Diagnostic;
+use lexer::{LexError, SpannedToken};
+use parser::ParseError;
+use semantic::SemanticError;
+use span::Located;
+use typecheck::{SymbolTable, TypeError};
+
+/// The output of every pass over one translation unit.
+#[derive(Debug)]
+pub struct Compilation {
+    pub tokens: Vec<SpannedToken>,
+    /// The syntax tree after name resolution and type checking, with
+    /// identifiers renamed to be unique and every expression typed.
+    pub ast: ast::Program,
+    pub symbols: SymbolTable,
+    pub ir: ir::Program,
+    pub assembly: String,
+}
+
+/// Why a translation unit failed to compile, by the pass that rejected it.
+#[derive(Debug)]
+pub enum CompileError {
+    Lex(LexError),
+    Parse(Vec<Located<ParseError>>), // at most parser::MAX_ERRORS
+    Semantic(Located<SemanticError>),
+    Type(Located<TypeError>),
+}
+
+impl CompileError {
+    /// The diagnostics to show the user, one per error.
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        match self {
+            CompileError::Lex(e) => vec![e.into()],
+            CompileError::Parse(errors) => {
+                let stopped = errors.len() >= parser::MAX_ERRORS;
+                let mut diagnostics: Vec<Diagnostic> =
+                    errors.into_iter().map(Diagnostic::from).collect();
+                if stopped && let Some(last) = diagnostics.last_mut() {
+                    let note = format!("stopped after {} syntax errors", parser::MAX_ERRORS);
+                    last.notes.push(Diagnostic::note(note, None));
+                }
+                diagnostics
+            }
+            CompileError::Semantic(e) => vec![e.into()],
+            CompileError::Type(e) => vec![e.into()],
+        }
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompileError::Lex(e) => match e.span() {
+                Some(span) => write!(f, "{}: {}", span, e),
+                None => write!(f, "{}", e),
+            },
+            CompileError::Parse(errors) => {
+                for (i, e) in errors.iter().enumerate() {
+                    if i > 0 {
+                        writeln!(f)?;
+                    }
+                    write!(f, "{}: {}", e.span, e.error)?;
+                }
+                Ok(())
+            }
+            CompileError::Semantic(e) => write!(f, "{}: {}", e.span, e.error),
+            CompileError::Type(e) => write!(f, "{}: {}", e.span, e.error),
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+impl From<LexError> for CompileError {
+    fn from(e: LexError) -> Self {
+        CompileError::Lex(e)
+    }
+}
+
+impl From<Vec<Located<ParseError>>> for CompileError {
+    fn from(errors: Vec<Located<ParseError>>) -> Self {
+        CompileError::Parse(errors)
+    }
+}
+
+impl From<Located<SemanticError>> for CompileError {
+    fn from(e: Located<SemanticError>) -> Self {
+        CompileError::Semantic(e)
+    }
+}
+
+impl From<Located<TypeError>> for CompileError {
+    fn from(e: Located<TypeError>) -> Self {
+        CompileError::Type(e)
+    }
+}
+
+/// Compiles the C source file at `path`.
+pub fn compile_file<P: AsRef<Path>>(path: P) -> Result<Compilation, CompileError> {
+    compile_tokens(lexer::lex(path)?)
+}
+
+/// Compiles C source held in memory. `name` stands in for the file name
+/// in spans and diagnostics.
+pub fn compile_str(source: &str, name: &str) -> Result<Compilation, CompileError> {
+    compile_tokens(lexer::lex_str(source, name)?)
+}
+
+fn compile_tokens(tokens: Vec<SpannedToken>) -> Result<Compilation, CompileError> {
+    let ast = parser::Parser::new(tokens.clone()).parse_program()?;
+    let ast = semantic::analyze(ast)?;
+    let (ast, mut symbols) = typecheck::check(ast)?;
+
+    let ir = ir_gen::generate(&ast, &mut symbols);
+    let assembly = codegen::generate(&ir, &symbols).to_string();
+
+    Ok(Compilation {
+        tokens,
+        ast,
+        symbols,
+        ir,
+        assembly,
+    })
+}
@@ -1,23 +1,61 @@
-mod ast;
-mod codegen;
-mod lexer;
-mod parser;
-mod ir_gen;
-mod ir;
-
 use std::env;
 use std::fs;
+use std::io::{self, Read, Write};
 use std::path::Path;
 use std::process::Command;
 
+use compiler_from_scratch::{compile_file, compile_str};
+use compiler_from_scratch::diagnostics::{Diagnostic, Emitter};
+
+/// The name diagnostics use for source read from standard input.
+const STDIN_NAME: &str = "<stdin>";
+
 fn main() {
     let args: Vec<String> = env::args().collect();
     if args.len() != 2 {
-        eprintln!("Usage: {} <filename.c>", args[0]);
+        eprintln!("Usage: {} <filename.c | ->", args[0]);
+        eprintln!("Pass - to read the program from stdin and print the assembly to stdout.");
         std::process::exit(1);
     }
 
     let input_path = &args[1];
+    let mut emitter = Emitter::stderr();
+
+    let stdin_source = if input_path == "-" {
+        let mut source = String::new();
+        if let Err(e) = io::stdin().read_to_string(&mut source) {
+            let message = format!("failed to read stdin: {}", e);
+            emitter.emit(&Diagnostic::error(None, message, None));
+            std::process::exit(1);
+        }
+        emitter.add_source(STDIN_NAME, source.clone());
+        Some(source)
+    } else {
+        None
+    };
+
+    let mut fail = |diagnostics: Vec<Diagnostic>| -> ! {
+        for diagnostic in &diagnostics {
+            emitter.emit(diagnostic);
+        }
+        if diagnostics.len() > 1 {
+            let message = format!("aborting due to {} previous errors", diagnostics.len());
+            emitter.emit(&Diagnostic::error(None, message, None));
+        }
+        std::process::exit(1);
+    };
+
+    // Only the assembly goes to stdout, so it can be piped straight into `as`
+    if let Some(source) = stdin_source {
+        let compilation =
+            compile_str(&source, STDIN_NAME).unwrap_or_else(|e| fail(e.into_diagnostics()));
+        if let Err(e) = io::stdout().write_all(compilation.assembly.as_bytes()) {
+            let message = format!("failed to write assembly to stdout: {}", e);
+            fail(vec![Diagnostic::error(None, message, None)]);
+        }
+        return;
+    }
+
     let path = Path::new(input_path);
 
     let parent_dir = path.parent().unwrap_or(Path::new("."));
@@ -31,28 +69,14 @@ fn main() {
 
     println!("Compiling {}...", input_path);
 
-    let tokens = match lexer::lex(input_path) {
-        Ok(t) => t,
-        Err(e) => {
-            eprintln!("Lexer Error: {:?}", e);
-            std::process::exit(1);
-        }
-    };
+    let compilation = compile_file(input_path).unwrap_or_else(|e| fail(e.into_diagnostics()));
 
-    let mut parser = parser::Parser::new(tokens);
-    let ast = match parser.parse_program() {
-        Ok(ast) => ast,
-        Err(e) => {
-            eprintln!("Parser Error: {:?}", e);
-            std::process::exit(1);
-        }
-    };
-    let ir = ir_gen::generate(&ast);
-    let assembly = codegen::generate(&ir).to_string();
-
-    if let Err(e) = fs::write(&asm_path, assembly) {
-        eprintln!("Failed to write assembly file: {}", e);
-        std::process::exit(1);
+    if let Err(e) = fs::write(&asm_path, compilation.assembly) {
+        fail(vec![Diagnostic::error(
+            None,
+            format!("failed to write {}: {}", asm_path.display(), e),
+            None,
+        )]);
     }
 
     println!("Generated assembly: {}", asm_path.display());
@@ -70,15 +94,16 @@ fn main() {
             if result.status.success() {
                 println!("Success! Executable created at: ./{}", exe_path.display());
             } else {
-                eprintln!("GCC Error:");
-                eprintln!("{}", String::from_utf8_lossy(&result.stderr));
-                std::process::exit(1);
+                let stderr = String::from_utf8_lossy(&result.stderr);
+                fail(vec![
+                    Diagnostic::error(None, "gcc failed to assemble the output", None)
+                        .with_note(Diagnostic::note(stderr.trim_end(), None)),
+                ]);
             }
         }
-        Err(e) => {
-            eprintln!("Failed to execute GCC. Is it installed?");
-            eprintln!("Error: {}", e);
-            std::process::exit(1);
-        }
+        Err(e) => fail(vec![
+            Diagnostic::error(None, format!("failed to execute gcc: {}", e), None)
+                .with_note(Diagnostic::note("is it installed?", None)),
+        ]),
     }
 }
@@ -1,18 +1,55 @@
+use std::fmt;
 use std::iter::Peekable;
 use std::vec::IntoIter;
 
-use crate::ast::{BinOp, Expression, FunctionDeclaration, Program, Statement, UnOp};
-use crate::lexer::Token;
+use crate::ast::{
+    BinOp, Block, BlockItem, Const, Declaration, Expression, ExpressionKind, ForInit,
+    FunctionDeclaration, FunctionType, Initializer, MemberDeclaration, Program, Statement,
+    StatementKind, StorageClass, StructDeclaration, StructKind, Type, UnOp, VariableDeclaration,
+};
+use crate::lexer::{SpannedToken, Token};
+use crate::span::{Located, Span};
+
+/// Parsing gives up after this many syntax errors.
+pub const MAX_ERRORS: usize = 20;
 
 #[derive(Debug)]
 pub enum ParseError {
     UnexpectedToken { expected: String, found: String },
     UnexpectedEOF,
+    InvalidSpecifiers(String),
+    InvalidDeclarator(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedToken { expected, found } => {
+                write!(f, "expected {}, found {}", expected, found)
+            }
+            ParseError::UnexpectedEOF => write!(f, "unexpected end of file"),
+            ParseError::InvalidSpecifiers(msg) => write!(f, "invalid specifiers: {}", msg),
+            ParseError::InvalidDeclarator(msg) => write!(f, "invalid declarator: {}", msg),
+        }
+    }
+}
+
+impl ParseError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParseError::UnexpectedToken { .. } => "E0101",
+            ParseError::UnexpectedEOF => "E0102",
+            ParseError::InvalidSpecifiers(_) => "E0103",
+            ParseError::InvalidDeclarator(_) => "E0104",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Precedence {
     None,
+    Assignment,
+    Conditional,
     LogicalOr,
     LogicalAnd,
     Equality,
@@ -25,9 +62,80 @@ pub enum Precedence {
     Factor, // * / %
 }
 
+/// The part of a declaration after the specifiers, e.g. `*f(int a)`. The
+/// declared type is built inside out from the specifier type.
+#[derive(Debug)]
+enum Declarator {
+    Identifier(String, Span),
+    Pointer(Box<Declarator>),
+    Array(Box<Declarator>, usize),
+    Function(Vec<(Type, Declarator)>, Box<Declarator>), // params, declarator
+}
+
+/// A declarator without a name, as in the type of a cast.
+#[derive(Debug)]
+enum AbstractDeclarator {
+    Pointer(Box<AbstractDeclarator>),
+    Array(Box<AbstractDeclarator>, usize),
+    Base,
+}
+
 impl Token {
+    fn is_type_specifier(&self) -> bool {
+        matches!(
+            self,
+            Token::IntKeyword
+                | Token::CharKeyword
+                | Token::LongKeyword
+                | Token::SignedKeyword
+                | Token::UnsignedKeyword
+                | Token::DoubleKeyword
+                | Token::StructKeyword
+                | Token::UnionKeyword
+        )
+    }
+
+    fn is_specifier(&self) -> bool {
+        self.is_type_specifier() || matches!(self, Token::StaticKeyword | Token::ExternKeyword)
+    }
+
+    /// Whether the token can start a unary expression.
+    fn begins_factor(&self) -> bool {
+        matches!(
+            self,
+            Token::OpenParen
+                | Token::IntegerLiteral(_)
+                | Token::LongLiteral(_)
+                | Token::UnsignedIntegerLiteral(_)
+                | Token::UnsignedLongLiteral(_)
+                | Token::DoubleLiteral(_)
+                | Token::CharLiteral(_)
+                | Token::StringLiteral(_)
+                | Token::Identifier(_)
+                | Token::Negation
+                | Token::BitwiseComplement
+                | Token::LogicalNegation
+                | Token::Increment
+                | Token::Decrement
+                | Token::Multiplication
+                | Token::BitwiseAnd
+        )
+    }
+
     fn get_precedence(&self) -> Precedence {
         match self {
+            Token::Assign
+            | Token::AdditionAssign
+            | Token::SubtractionAssign
+            | Token::MultiplicationAssign
+            | Token::DivisionAssign
+            | Token::RemainderAssign
+            | Token::BitwiseAndAssign
+            | Token::BitwiseOrAssign
+            | Token::BitwiseXorAssign
+            | Token::LeftShiftAssign
+            | Token::RightShiftAssign => Precedence::Assignment,
+            Token::QuestionMark => Precedence::Conditional,
             Token::LogicalOr => Precedence::LogicalOr,
             Token::LogicalAnd => Precedence::LogicalAnd,
             Token::Equal => Precedence::Equality,
@@ -45,107 +153,870 @@ impl Token {
             _ => Precedence::None,
         }
     }
+
+    /// The operator a compound assignment like `+=` applies.
+    fn compound_assignment_op(&self) -> Option<BinOp> {
+        match self {
+            Token::AdditionAssign => Some(BinOp::Add),
+            Token::SubtractionAssign => Some(BinOp::Subtract),
+            Token::MultiplicationAssign => Some(BinOp::Multiply),
+            Token::DivisionAssign => Some(BinOp::Divide),
+            Token::RemainderAssign => Some(BinOp::Remainder),
+            Token::BitwiseAndAssign => Some(BinOp::BitwiseAnd),
+            Token::BitwiseOrAssign => Some(BinOp::BitwiseOr),
+            Token::BitwiseXorAssign => Some(BinOp::BitwiseXor),
+            Token::LeftShiftAssign => Some(BinOp::LeftShift),
+            Token::RightShiftAssign => Some(BinOp::RightShift),
+            _ => None,
+        }
+    }
 }
 
 pub struct Parser {
-    tokens: Peekable<IntoIter<Token>>,
+    tokens: Peekable<IntoIter<SpannedToken>>,
+    previous: Span,                  // of the last token consumed
+    errors: Vec<Located<ParseError>>, // recovered from so far
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: Vec<SpannedToken>) -> Self {
+        // Errors before the first token is consumed point at its start
+        let previous = match tokens.first() {
+            Some(t) => Span {
+                end: t.span.start,
+                ..t.span.clone()
+            },
+            None => Span::default(),
+        };
         Parser {
             tokens: tokens.into_iter().peekable(),
+            previous,
+            errors: Vec::new(),
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        self.tokens.peek().map(|t| &t.token)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let SpannedToken { token, span } = self.tokens.next()?;
+        self.previous = span;
+        Some(token)
+    }
+
+    /// Consumes the next token if `predicate` accepts it.
+    fn next_if(&mut self, predicate: impl FnOnce(&Token) -> bool) -> Option<Token> {
+        if self.peek().is_some_and(predicate) {
+            self.next()
+        } else {
+            None
+        }
+    }
+
+    /// The span of the next token, or the end of the input.
+    fn peek_span(&mut self) -> Span {
+        match self.tokens.peek() {
+            Some(t) => t.span.clone(),
+            None => self.previous.after(),
+        }
+    }
+
+    /// Locates an error at the last token consumed, which is usually the
+    /// unexpected one.
+    fn error(&self, error: ParseError) -> Located<ParseError> {
+        Located::new(error, &self.previous)
+    }
+
+    fn unexpected_eof(&self) -> Located<ParseError> {
+        Located::new(ParseError::UnexpectedEOF, &self.previous.after())
+    }
+
+    /// Reports the next token as unexpected. It is left unconsumed, so
+    /// recovery can tell whether it ends a statement or block.
+    fn unexpected(&mut self, expected: &str) -> Located<ParseError> {
+        let span = self.peek_span();
+        match self.peek() {
+            Some(token) => {
+                let error = ParseError::UnexpectedToken {
+                    expected: expected.to_string(),
+                    found: token.to_string(),
+                };
+                Located::new(error, &span)
+            }
+            None => self.unexpected_eof(),
+        }
+    }
+
+    fn expect(&mut self, expected_token: Token) -> Result<(), Located<ParseError>> {
+        match self.next_if(|token| *token == expected_token) {
+            Some(_) => Ok(()),
+            None => Err(self.unexpected(&expected_token.to_string())),
+        }
+    }
+
+    /// Records a syntax error and skips to where parsing can resume. The
+    /// error is handed back instead once the input has run out or the
+    /// error limit is reached, which ends parsing.
+    fn recover(&mut self, error: Located<ParseError>) -> Result<(), Located<ParseError>> {
+        if matches!(error.error, ParseError::UnexpectedEOF) || self.errors.len() + 1 >= MAX_ERRORS
+        {
+            return Err(error);
+        }
+        self.errors.push(error);
+        self.synchronize();
+        Ok(())
+    }
+
+    /// Skips past the next `;`, or up to the `}` closing the current block.
+    /// Blocks opened along the way are skipped whole.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        loop {
+            match self.peek() {
+                None => return,
+                Some(Token::CloseBrace) if depth == 0 => return,
+                Some(Token::Semicolon) if depth == 0 => {
+                    self.next();
+                    return;
+                }
+                Some(Token::OpenBrace) => depth += 1,
+                Some(Token::CloseBrace) => {
+                    depth -= 1;
+                    if depth == 0 {
+                        self.next();
+                        return;
+                    }
+                }
+                Some(_) => {}
+            }
+            self.next();
+        }
+    }
+
+    /// Parses the whole translation unit, reporting every syntax error
+    /// found (up to `MAX_ERRORS`) rather than just the first.
+    pub fn parse_program(&mut self) -> Result<Program, Vec<Located<ParseError>>> {
+        let mut declarations = Vec::new();
+
+        while self.peek().is_some() {
+            match self.parse_declaration() {
+                Ok(decl) => declarations.push(decl),
+                Err(error) => {
+                    if let Err(error) = self.recover(error) {
+                        self.errors.push(error);
+                        break;
+                    }
+                    // A stray `}` can't start anything at file scope
+                    if self.peek() == Some(&Token::CloseBrace) {
+                        self.next();
+                    }
+                }
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(Program { declarations })
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
+    }
+
+    fn parse_block(&mut self) -> Result<Block, Located<ParseError>> {
+        self.expect(Token::OpenBrace)?;
+
+        let mut items = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::CloseBrace) => break,
+                Some(_) => match self.parse_block_item() {
+                    Ok(item) => items.push(item),
+                    Err(error) => self.recover(error)?,
+                },
+                None => return Err(self.unexpected_eof()),
+            }
         }
+
+        self.expect(Token::CloseBrace)?;
+
+        Ok(Block { items })
     }
 
-    fn expect(&mut self, expected_token: Token) -> Result<(), ParseError> {
-        match self.tokens.next() {
-            Some(token) if token == expected_token => Ok(()),
-            Some(token) => Err(ParseError::UnexpectedToken {
-                expected: format!("{:?}", expected_token),
-                found: format!("{:?}", token),
-            }),
-            None => Err(ParseError::UnexpectedEOF),
+    fn parse_block_item(&mut self) -> Result<BlockItem, Located<ParseError>> {
+        match self.peek() {
+            Some(t) if t.is_specifier() => Ok(BlockItem::Declaration(self.parse_declaration()?)),
+            _ => Ok(BlockItem::Statement(self.parse_statement()?)),
         }
     }
 
-    pub fn parse_program(&mut self) -> Result<Program, ParseError> {
-        let function = self.parse_function()?;
+    /// Parses the type and storage class specifiers, which may come in any order.
+    fn parse_specifiers(&mut self) -> Result<(Type, Option<StorageClass>), Located<ParseError>> {
+        let start = self.peek_span();
+        let mut type_specifiers = Vec::new();
+        let mut structures = Vec::new();
+        let mut storage_classes = Vec::new();
 
-        if self.tokens.peek().is_some() {
-            return Err(ParseError::UnexpectedToken {
-                expected: "End of File".to_string(),
-                found: format!("{:?}", self.tokens.peek().unwrap()),
+        while let Some(token) = self.peek() {
+            match token {
+                Token::StructKeyword | Token::UnionKeyword => {
+                    let kind = match token {
+                        Token::StructKeyword => StructKind::Struct,
+                        _ => StructKind::Union,
+                    };
+                    self.next();
+                    structures.push(Type::Structure(kind, self.parse_identifier()?));
+                    continue;
+                }
+                t if t.is_type_specifier() => type_specifiers.push(t.clone()),
+                Token::StaticKeyword => storage_classes.push(StorageClass::Static),
+                Token::ExternKeyword => storage_classes.push(StorageClass::Extern),
+                _ => break,
+            }
+            self.next();
+        }
+
+        // Errors cover the specifiers, or point where they were expected
+        let empty =
+            type_specifiers.is_empty() && structures.is_empty() && storage_classes.is_empty();
+        let span = if empty { start } else { start.to(&self.previous) };
+
+        // A structure type can't be combined with any other type specifier
+        let ty = match structures.as_slice() {
+            [] => Self::parse_type(&type_specifiers).map_err(|e| Located::new(e, &span))?,
+            [ty] if type_specifiers.is_empty() => ty.clone(),
+            _ => {
+                let words = structures
+                    .iter()
+                    .map(Type::to_string)
+                    .chain(type_specifiers.iter().map(Self::spell_specifier));
+                let error = ParseError::InvalidSpecifiers(format!(
+                    "invalid type specifier combination `{}`",
+                    words.collect::<Vec<_>>().join(" ")
+                ));
+                return Err(Located::new(error, &span));
+            }
+        };
+
+        match storage_classes.as_slice() {
+            [] => Ok((ty, None)),
+            [storage_class] => Ok((ty, Some(*storage_class))),
+            _ => Err(Located::new(
+                ParseError::InvalidSpecifiers("multiple storage classes".to_string()),
+                &span,
+            )),
+        }
+    }
+
+    /// Specifiers are keywords, which all have a fixed spelling.
+    fn spell_specifier(token: &Token) -> String {
+        token.spelling().unwrap_or_default().to_string()
+    }
+
+    fn parse_type(specifiers: &[Token]) -> Result<Type, ParseError> {
+        if specifiers.is_empty() {
+            return Err(ParseError::InvalidSpecifiers(
+                "missing type specifier".to_string(),
+            ));
+        }
+
+        if specifiers == [Token::DoubleKeyword] {
+            return Ok(Type::Double);
+        }
+
+        // Specifiers may appear in any order, e.g. `long unsigned int`
+        let count = |token: Token| specifiers.iter().filter(|t| **t == token).count();
+        let ints = count(Token::IntKeyword);
+        let longs = count(Token::LongKeyword);
+        let chars = count(Token::CharKeyword);
+        let signs = count(Token::SignedKeyword) + count(Token::UnsignedKeyword);
+
+        if ints > 1
+            || longs > 1
+            || signs > 1
+            || chars > 1
+            || (chars == 1 && ints + longs > 0)
+            || specifiers.contains(&Token::DoubleKeyword)
+        {
+            let words: Vec<String> = specifiers.iter().map(Self::spell_specifier).collect();
+            return Err(ParseError::InvalidSpecifiers(format!(
+                "invalid type specifier combination `{}`",
+                words.join(" ")
+            )));
+        }
+
+        let unsigned = specifiers.contains(&Token::UnsignedKeyword);
+        if chars == 1 {
+            // Plain char is a distinct type from signed char
+            return Ok(match (signs, unsigned) {
+                (0, _) => Type::Char,
+                (_, false) => Type::SChar,
+                (_, true) => Type::UChar,
             });
         }
 
-        Ok(Program { function })
+        match (longs, unsigned) {
+            (0, false) => Ok(Type::Int),
+            (0, true) => Ok(Type::UInt),
+            (_, false) => Ok(Type::Long),
+            (_, true) => Ok(Type::ULong),
+        }
     }
 
-    fn parse_function(&mut self) -> Result<FunctionDeclaration, ParseError> {
-        self.expect(Token::IntKeyword)?;
+    /// Parses a type with no storage class, as in parameters and casts.
+    fn parse_type_name(&mut self) -> Result<Type, Located<ParseError>> {
+        match self.parse_specifiers()? {
+            (ty, None) => Ok(ty),
+            (_, Some(_)) => Err(self.error(ParseError::InvalidSpecifiers(
+                "storage class not allowed here".to_string(),
+            ))),
+        }
+    }
 
-        let name = match self.tokens.next() {
-            Some(Token::Identifier(name)) => name,
-            Some(t) => {
-                return Err(ParseError::UnexpectedToken {
-                    expected: "Identifier".to_string(),
-                    found: format!("{:?}", t),
-                });
+    fn parse_declaration(&mut self) -> Result<Declaration, Located<ParseError>> {
+        let start = self.peek_span();
+        let (base_type, storage_class) = self.parse_specifiers()?;
+        let specifiers = start.to(&self.previous);
+
+        // `struct s;` declares the tag, `struct s { ... };` also defines it
+        if let Type::Structure(kind, tag) = &base_type
+            && matches!(self.peek(), Some(Token::Semicolon | Token::OpenBrace))
+        {
+            if storage_class.is_some() {
+                let error = ParseError::InvalidSpecifiers(format!(
+                    "storage class in declaration of '{}'",
+                    base_type
+                ));
+                return Err(Located::new(error, &specifiers));
             }
-            None => return Err(ParseError::UnexpectedEOF),
+            let members = match self.peek() {
+                Some(Token::OpenBrace) => Some(self.parse_member_list(&base_type)?),
+                _ => None,
+            };
+            self.expect(Token::Semicolon)?;
+
+            return Ok(Declaration::Struct(StructDeclaration {
+                kind: *kind,
+                tag: tag.clone(),
+                members,
+                span: specifiers,
+            }));
+        }
+
+        let (name, span, ty, params) = self.parse_full_declarator(base_type)?;
+
+        if let Type::Function(fun_type) = ty {
+            let body = match self.peek() {
+                Some(Token::Semicolon) => {
+                    self.next();
+                    None
+                }
+                _ => Some(self.parse_block()?),
+            };
+
+            return Ok(Declaration::Function(FunctionDeclaration {
+                name,
+                params,
+                fun_type,
+                body,
+                storage_class,
+                span,
+            }));
+        }
+
+        let init = match self.peek() {
+            Some(Token::Assign) => {
+                self.next();
+                Some(self.parse_initializer()?)
+            }
+            _ => None,
         };
 
-        self.expect(Token::OpenParen)?;
-        self.expect(Token::CloseParen)?;
+        self.expect(Token::Semicolon)?;
+
+        Ok(Declaration::Variable(VariableDeclaration {
+            name,
+            ty,
+            init,
+            storage_class,
+            span,
+        }))
+    }
+
+    /// Parses a declarator and applies it to the specifier type, returning
+    /// the declared name and its span, the type and any parameter names.
+    fn parse_full_declarator(
+        &mut self,
+        base_type: Type,
+    ) -> Result<(String, Span, Type, Vec<String>), Located<ParseError>> {
+        let start = self.peek_span();
+        let declarator = self.parse_declarator()?;
+        Self::process_declarator(declarator, base_type)
+            .map_err(|e| Located::new(e, &start.to(&self.previous)))
+    }
+
+    /// Parses the `{ ... }` of a structure definition.
+    fn parse_member_list(
+        &mut self,
+        ty: &Type,
+    ) -> Result<Vec<MemberDeclaration>, Located<ParseError>> {
         self.expect(Token::OpenBrace)?;
 
-        let body = self.parse_statement()?;
+        let mut members = Vec::new();
+        loop {
+            match self.peek() {
+                Some(Token::CloseBrace) => break,
+                Some(_) => {}
+                None => return Err(self.unexpected_eof()),
+            }
+
+            let base_type = self.parse_type_name()?;
+            let (name, span, ty, _) = self.parse_full_declarator(base_type)?;
+            if let Type::Function(_) = ty {
+                let error =
+                    ParseError::InvalidDeclarator(format!("member '{}' has function type", name));
+                return Err(Located::new(error, &span));
+            }
+            self.expect(Token::Semicolon)?;
 
+            members.push(MemberDeclaration { name, ty, span });
+        }
         self.expect(Token::CloseBrace)?;
 
-        Ok(FunctionDeclaration { name, body })
+        if members.is_empty() {
+            return Err(self.error(ParseError::InvalidDeclarator(format!(
+                "'{}' has no members",
+                ty
+            ))));
+        }
+        Ok(members)
     }
 
-    fn parse_statement(&mut self) -> Result<Statement, ParseError> {
-        self.expect(Token::ReturnKeyword)?;
+    fn parse_initializer(&mut self) -> Result<Initializer, Located<ParseError>> {
+        if self.peek() != Some(&Token::OpenBrace) {
+            return Ok(Initializer::Single(self.parse_expression(&Precedence::None)?));
+        }
+        self.next();
+
+        // A trailing comma is allowed: { 1, 2, }
+        let mut inits = vec![self.parse_initializer()?];
+        while self.peek() == Some(&Token::Comma) {
+            self.next();
+            if self.peek() == Some(&Token::CloseBrace) {
+                break;
+            }
+            inits.push(self.parse_initializer()?);
+        }
 
-        let expr = self.parse_expression(&Precedence::None)?;
+        self.expect(Token::CloseBrace)?;
+        Ok(Initializer::Compound(inits))
+    }
 
-        self.expect(Token::Semicolon)?;
+    fn parse_declarator(&mut self) -> Result<Declarator, Located<ParseError>> {
+        if self.peek() == Some(&Token::Multiplication) {
+            self.next();
+            return Ok(Declarator::Pointer(Box::new(self.parse_declarator()?)));
+        }
 
-        Ok(Statement::Return(expr))
-    }
-
-fn parse_expression(&mut self, min_prec: &Precedence) -> Result<Expression, ParseError> {
-        let mut left = self.parse_factor().unwrap();
-
-        while let Some(Token::Addition)
-        | Some(Token::Negation)
-        | Some(Token::Division)
-        | Some(Token::Multiplication)
-        | Some(Token::Remainder)
-        | Some(Token::BitwiseOr)
-        | Some(Token::BitwiseXor)
-        | Some(Token::BitwiseAnd)
-        | Some(Token::LogicalOr)
-        | Some(Token::LogicalAnd)
-        | Some(Token::LeftShift)
-        | Some(Token::RightShift)
-        | Some(Token::Equal)
-        | Some(Token::NotEqual)
-        | Some(Token::LessThan)
-        | Some(Token::LessThanEqual)
-        | Some(Token::GreaterThan)
-        | Some(Token::GreaterThanEqual) = self.tokens.peek()
-        {
-            let prec = self.tokens.peek().unwrap().get_precedence();
-            if prec <= *min_prec {
-                return Ok(left);
+        let mut declarator = match self.peek() {
+            Some(Token::OpenParen) => {
+                self.next();
+                let inner = self.parse_declarator()?;
+                self.expect(Token::CloseParen)?;
+                inner
             }
+            _ => {
+                let name = self.parse_identifier()?;
+                Declarator::Identifier(name, self.previous.clone())
+            }
+        };
+
+        if self.peek() == Some(&Token::OpenParen) {
+            self.next();
+            let params = self.parse_params()?;
+            self.expect(Token::CloseParen)?;
+            return Ok(Declarator::Function(params, Box::new(declarator)));
+        }
+
+        while self.peek() == Some(&Token::OpenBracket) {
+            declarator = Declarator::Array(Box::new(declarator), self.parse_array_length()?);
+        }
+
+        Ok(declarator)
+    }
+
+    /// Parses `[N]`, where N must be a positive integer constant.
+    fn parse_array_length(&mut self) -> Result<usize, Located<ParseError>> {
+        self.expect(Token::OpenBracket)?;
+        let len = match self.next() {
+            Some(Token::IntegerLiteral(val) | Token::LongLiteral(val)) if val > 0 => val as usize,
+            Some(Token::UnsignedIntegerLiteral(val) | Token::UnsignedLongLiteral(val))
+                if val > 0 =>
+            {
+                val as usize
+            }
+            Some(t) => {
+                return Err(self.error(ParseError::InvalidDeclarator(format!(
+                    "array length must be a positive integer constant, found {}",
+                    t
+                ))));
+            }
+            None => return Err(self.unexpected_eof()),
+        };
+        self.expect(Token::CloseBracket)?;
+        Ok(len)
+    }
+
+    /// Applies a declarator to the specifier type, returning the declared
+    /// name and its span, its type and, for functions, the parameter names.
+    fn process_declarator(
+        declarator: Declarator,
+        base_type: Type,
+    ) -> Result<(String, Span, Type, Vec<String>), ParseError> {
+        match declarator {
+            Declarator::Identifier(name, span) => Ok((name, span, base_type, Vec::new())),
+            Declarator::Pointer(inner) => {
+                Self::process_declarator(*inner, Type::Pointer(Box::new(base_type)))
+            }
+            Declarator::Array(inner, len) => {
+                Self::process_declarator(*inner, Type::Array(Box::new(base_type), len))
+            }
+            Declarator::Function(params, inner) => {
+                let Declarator::Identifier(name, span) = *inner else {
+                    return Err(ParseError::InvalidDeclarator(
+                        "function pointers are not supported".to_string(),
+                    ));
+                };
+                if base_type.is_array() {
+                    return Err(ParseError::InvalidDeclarator(format!(
+                        "function '{}' returns an array",
+                        name
+                    )));
+                }
+
+                let mut param_types = Vec::new();
+                let mut param_names = Vec::new();
+                for (param_type, param) in params {
+                    let (param_name, _, param_type, _) =
+                        Self::process_declarator(param, param_type)?;
+                    // Array parameters are really pointers to the first element
+                    let param_type = match param_type {
+                        Type::Function(_) => {
+                            return Err(ParseError::InvalidDeclarator(format!(
+                                "parameter '{}' has function type",
+                                param_name
+                            )));
+                        }
+                        Type::Array(element, _) => Type::Pointer(element),
+                        ty => ty,
+                    };
+                    param_types.push(param_type);
+                    param_names.push(param_name);
+                }
+
+                let fun_type = FunctionType {
+                    params: param_types,
+                    ret: Box::new(base_type),
+                };
+                Ok((name, span, Type::Function(fun_type), param_names))
+            }
+        }
+    }
+
+    fn parse_abstract_declarator(&mut self) -> Result<AbstractDeclarator, Located<ParseError>> {
+        let mut declarator = match self.peek() {
+            Some(Token::Multiplication) => {
+                self.next();
+                return Ok(AbstractDeclarator::Pointer(Box::new(
+                    self.parse_abstract_declarator()?,
+                )));
+            }
+            Some(Token::OpenParen) => {
+                self.next();
+                let inner = self.parse_abstract_declarator()?;
+                self.expect(Token::CloseParen)?;
+                inner
+            }
+            _ => AbstractDeclarator::Base,
+        };
+
+        while self.peek() == Some(&Token::OpenBracket) {
+            declarator =
+                AbstractDeclarator::Array(Box::new(declarator), self.parse_array_length()?);
+        }
+
+        Ok(declarator)
+    }
+
+    fn process_abstract_declarator(declarator: AbstractDeclarator, base_type: Type) -> Type {
+        match declarator {
+            AbstractDeclarator::Pointer(inner) => {
+                Self::process_abstract_declarator(*inner, Type::Pointer(Box::new(base_type)))
+            }
+            AbstractDeclarator::Array(inner, len) => {
+                Self::process_abstract_declarator(*inner, Type::Array(Box::new(base_type), len))
+            }
+            AbstractDeclarator::Base => base_type,
+        }
+    }
+
+    fn parse_params(&mut self) -> Result<Vec<(Type, Declarator)>, Located<ParseError>> {
+        let mut params = Vec::new();
+
+        match self.peek() {
+            // `()` and `(void)` both declare no parameters
+            Some(Token::CloseParen) => return Ok(params),
+            Some(Token::VoidKeyword) => {
+                self.next();
+                return Ok(params);
+            }
+            _ => {}
+        }
+
+        loop {
+            let ty = self.parse_type_name()?;
+            params.push((ty, self.parse_declarator()?));
+
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.next();
+                }
+                _ => return Ok(params),
+            }
+        }
+    }
+
+    fn parse_arguments(&mut self) -> Result<Vec<Expression>, Located<ParseError>> {
+        let mut args = Vec::new();
+
+        if self.peek() == Some(&Token::CloseParen) {
+            return Ok(args);
+        }
+
+        loop {
+            args.push(self.parse_expression(&Precedence::None)?);
+
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.next();
+                }
+                _ => return Ok(args),
+            }
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, Located<ParseError>> {
+        let start = self.peek_span();
+        let kind = match self.peek() {
+            Some(Token::ReturnKeyword) => {
+                self.next();
+                let expr = self.parse_expression(&Precedence::None)?;
+                self.expect(Token::Semicolon)?;
+                StatementKind::Return(expr)
+            }
+            Some(Token::IfKeyword) => {
+                self.next();
+                self.expect(Token::OpenParen)?;
+                let cond = self.parse_expression(&Precedence::None)?;
+                self.expect(Token::CloseParen)?;
+
+                let then_stmt = self.parse_statement()?;
+
+                // An else always binds to the nearest if
+                let else_stmt = match self.peek() {
+                    Some(Token::ElseKeyword) => {
+                        self.next();
+                        Some(Box::new(self.parse_statement()?))
+                    }
+                    _ => None,
+                };
+
+                StatementKind::If(cond, Box::new(then_stmt), else_stmt)
+            }
+            Some(Token::OpenBrace) => StatementKind::Compound(self.parse_block()?),
+            Some(Token::WhileKeyword) => {
+                self.next();
+                self.expect(Token::OpenParen)?;
+                let cond = self.parse_expression(&Precedence::None)?;
+                self.expect(Token::CloseParen)?;
+                let body = self.parse_statement()?;
+                StatementKind::While(cond, Box::new(body), String::new())
+            }
+            Some(Token::DoKeyword) => {
+                self.next();
+                let body = self.parse_statement()?;
+                self.expect(Token::WhileKeyword)?;
+                self.expect(Token::OpenParen)?;
+                let cond = self.parse_expression(&Precedence::None)?;
+                self.expect(Token::CloseParen)?;
+                self.expect(Token::Semicolon)?;
+                StatementKind::DoWhile(Box::new(body), cond, String::new())
+            }
+            Some(Token::ForKeyword) => {
+                self.next();
+                self.expect(Token::OpenParen)?;
+
+                let init = match self.peek() {
+                    Some(t) if t.is_specifier() => match self.parse_declaration()? {
+                        Declaration::Variable(decl) => ForInit::Declaration(decl),
+                        Declaration::Function(func) => {
+                            let error = ParseError::UnexpectedToken {
+                                expected: "Variable declaration".to_string(),
+                                found: format!("function declaration of {}", func.name),
+                            };
+                            return Err(Located::new(error, &func.span));
+                        }
+                        Declaration::Struct(decl) => {
+                            let error = ParseError::UnexpectedToken {
+                                expected: "Variable declaration".to_string(),
+                                found: format!("declaration of {}", decl.tag),
+                            };
+                            return Err(Located::new(error, &decl.span));
+                        }
+                    },
+                    _ => {
+                        let expr = self.parse_optional_expression(Token::Semicolon)?;
+                        self.expect(Token::Semicolon)?;
+                        ForInit::Expression(expr)
+                    }
+                };
+
+                let cond = self.parse_optional_expression(Token::Semicolon)?;
+                self.expect(Token::Semicolon)?;
+                let post = self.parse_optional_expression(Token::CloseParen)?;
+                self.expect(Token::CloseParen)?;
+
+                let body = self.parse_statement()?;
+                StatementKind::For(Box::new(init), cond, post, Box::new(body), String::new())
+            }
+            Some(Token::SwitchKeyword) => {
+                self.next();
+                self.expect(Token::OpenParen)?;
+                let cond = self.parse_expression(&Precedence::None)?;
+                self.expect(Token::CloseParen)?;
+                let body = self.parse_statement()?;
+                StatementKind::Switch(cond, Box::new(body), String::new(), Vec::new())
+            }
+            Some(Token::CaseKeyword) => {
+                self.next();
+                let value = self.parse_expression(&Precedence::None)?;
+                self.expect(Token::Colon)?;
+                let stmt = self.parse_statement()?;
+                StatementKind::Case(value, Box::new(stmt), String::new())
+            }
+            Some(Token::DefaultKeyword) => {
+                self.next();
+                self.expect(Token::Colon)?;
+                let stmt = self.parse_statement()?;
+                StatementKind::Default(Box::new(stmt), String::new())
+            }
+            Some(Token::BreakKeyword) => {
+                self.next();
+                self.expect(Token::Semicolon)?;
+                StatementKind::Break(String::new())
+            }
+            Some(Token::ContinueKeyword) => {
+                self.next();
+                self.expect(Token::Semicolon)?;
+                StatementKind::Continue(String::new())
+            }
+            Some(Token::GotoKeyword) => {
+                self.next();
+                let label = self.parse_identifier()?;
+                self.expect(Token::Semicolon)?;
+                StatementKind::Goto(label)
+            }
+            Some(Token::Semicolon) => {
+                self.next();
+                StatementKind::Null
+            }
+            Some(token) => {
+                // `label:` reads as an expression consisting of just the
+                // identifier, followed by a colon
+                let is_identifier = matches!(token, Token::Identifier(_));
+                let expr = self.parse_expression(&Precedence::None)?;
+                if let ExpressionKind::Var(label) = &expr.kind
+                    && is_identifier
+                    && self.peek() == Some(&Token::Colon)
+                {
+                    let label = label.clone();
+                    self.next();
+                    let stmt = self.parse_statement()?;
+                    StatementKind::Labeled(label, Box::new(stmt))
+                } else {
+                    self.expect(Token::Semicolon)?;
+                    StatementKind::Expression(expr)
+                }
+            }
+            None => return Err(self.unexpected_eof()),
+        };
+
+        Ok(Statement::new(kind, start.to(&self.previous)))
+    }
+
+    /// Parses an expression unless the next token is `terminator`.
+    fn parse_optional_expression(
+        &mut self,
+        terminator: Token,
+    ) -> Result<Option<Expression>, Located<ParseError>> {
+        if self.peek() == Some(&terminator) {
+            Ok(None)
+        } else {
+            Ok(Some(self.parse_expression(&Precedence::None)?))
+        }
+    }
+
+    fn parse_identifier(&mut self) -> Result<String, Located<ParseError>> {
+        match self.next_if(|token| matches!(token, Token::Identifier(_))) {
+            Some(Token::Identifier(name)) => Ok(name),
+            _ => Err(self.unexpected("identifier")),
+        }
+    }
+
+    fn parse_expression(
+        &mut self,
+        min_prec: &Precedence,
+    ) -> Result<Expression, Located<ParseError>> {
+        let mut left = self.parse_factor()?;
 
-            let op_token = self.tokens.next().unwrap();
-            let right = self.parse_expression(&prec).unwrap();
+        // Tokens that are not binary operators have no precedence, which
+        // ends the expression
+        while let Some(op_token) = self.next_if(|token| token.get_precedence() > *min_prec) {
+            let prec = op_token.get_precedence();
+
+            // Assignment is right associative, so the right hand side may
+            // itself contain another assignment.
+            if op_token == Token::Assign {
+                let right = self.parse_expression(&Precedence::None)?;
+                let span = left.span.to(&right.span);
+                let kind = ExpressionKind::Assignment(Box::new(left), Box::new(right));
+                left = Expression::new(kind, span);
+                continue;
+            }
+            if let Some(op) = op_token.compound_assignment_op() {
+                let right = self.parse_expression(&Precedence::None)?;
+                let span = left.span.to(&right.span);
+                let kind = ExpressionKind::CompoundAssignment(op, Box::new(left), Box::new(right));
+                left = Expression::new(kind, span);
+                continue;
+            }
+
+            // The conditional operator is also right associative; its middle
+            // operand is parsed as if it were parenthesized.
+            if op_token == Token::QuestionMark {
+                let then_expr = self.parse_expression(&Precedence::None)?;
+                self.expect(Token::Colon)?;
+                let else_expr = self.parse_expression(&Precedence::Assignment)?;
+                let span = left.span.to(&else_expr.span);
+                let kind = ExpressionKind::Conditional(
+                    Box::new(left),
+                    Box::new(then_expr),
+                    Box::new(else_expr),
+                );
+                left = Expression::new(kind, span);
+                continue;
+            }
+
+            let right = self.parse_expression(&prec)?;
 
             let bin_op = match op_token {
                 Token::Addition => BinOp::Add,
@@ -169,42 +1040,209 @@ fn parse_expression(&mut self, min_prec: &Precedence) -> Result<Expression, Pars
                 _ => unreachable!(),
             };
 
-            left = Expression::BinaryOp(bin_op, Box::new(left), Box::new(right));
+            let span = left.span.to(&right.span);
+            left = Expression::new(
+                ExpressionKind::BinaryOp(bin_op, Box::new(left), Box::new(right)),
+                span,
+            );
         }
 
         Ok(left)
     }
 
-    fn parse_factor(&mut self) -> Result<Expression, ParseError> {
-        match self.tokens.next() {
+    fn parse_factor(&mut self) -> Result<Expression, Located<ParseError>> {
+        let start = self.peek_span();
+        let kind = match self.next_if(Token::begins_factor) {
             Some(Token::OpenParen) => {
-                let result = self.parse_expression(&Precedence::None);
-                let _ = self.expect(Token::CloseParen);
-                result
+                if self.peek().is_some_and(Token::is_type_specifier) {
+                    let base_type = self.parse_type_name()?;
+                    let declarator = self.parse_abstract_declarator()?;
+                    let ty = Self::process_abstract_declarator(declarator, base_type);
+                    self.expect(Token::CloseParen)?;
+                    let inner = self.parse_factor()?;
+                    let span = start.to(&inner.span);
+                    return Ok(Expression::new(ExpressionKind::Cast(ty, Box::new(inner)), span));
+                }
+
+                let inner = self.parse_expression(&Precedence::None)?;
+                self.expect(Token::CloseParen)?;
+                return self.parse_postfix(inner);
             }
 
-            Some(Token::IntegerLiteral(val)) => Ok(Expression::Constant(val)),
+            // The lexer has already checked that the value fits the type
+            Some(Token::IntegerLiteral(val)) => ExpressionKind::Constant(Const::Int(val as i32)),
 
-            Some(Token::Negation) => Ok(Expression::UnaryOp((
-                UnOp::Negation,
-                Box::new(self.parse_factor().unwrap()),
-            ))),
+            Some(Token::LongLiteral(val)) => ExpressionKind::Constant(Const::Long(val)),
 
-            Some(Token::BitwiseComplement) => Ok(Expression::UnaryOp((
-                UnOp::BitwiseComplement,
-                Box::new(self.parse_factor().unwrap()),
-            ))),
+            Some(Token::UnsignedIntegerLiteral(val)) => {
+                ExpressionKind::Constant(Const::UInt(val as u32))
+            }
 
-            Some(Token::LogicalNegation) => Ok(Expression::UnaryOp((
-                UnOp::LogicalNegation,
-                Box::new(self.parse_factor().unwrap()),
-            ))),
+            Some(Token::UnsignedLongLiteral(val)) => ExpressionKind::Constant(Const::ULong(val)),
+
+            Some(Token::DoubleLiteral(val)) => ExpressionKind::Constant(Const::Double(val)),
+
+            // Character constants have type int; plain char is signed, so '\xff' is -1
+            Some(Token::CharLiteral(byte)) => {
+                ExpressionKind::Constant(Const::Int(byte as i8 as i32))
+            }
+
+            // Adjacent string literals are concatenated: "ab" "cd" is "abcd"
+            Some(Token::StringLiteral(mut bytes)) => {
+                while let Some(Token::StringLiteral(next)) = self.peek() {
+                    bytes.extend_from_slice(next);
+                    self.next();
+                }
+                ExpressionKind::String(bytes)
+            }
 
-            Some(t) => Err(ParseError::UnexpectedToken {
-                expected: "Factor parsing error".to_string(),
-                found: format!("{:?}", t),
-            }),
-            None => Err(ParseError::UnexpectedEOF),
+            Some(Token::Identifier(name)) => {
+                if self.peek() == Some(&Token::OpenParen) {
+                    self.next();
+                    let args = self.parse_arguments()?;
+                    self.expect(Token::CloseParen)?;
+                    ExpressionKind::FunctionCall(name, args)
+                } else {
+                    ExpressionKind::Var(name)
+                }
+            }
+
+            // Prefix operators take their operand's postfix operators with them
+            Some(Token::Negation) => {
+                let inner = Box::new(self.parse_factor()?);
+                let kind = ExpressionKind::UnaryOp((UnOp::Negation, inner));
+                return Ok(Expression::new(kind, start.to(&self.previous)));
+            }
+
+            Some(Token::BitwiseComplement) => {
+                let inner = Box::new(self.parse_factor()?);
+                let kind = ExpressionKind::UnaryOp((UnOp::BitwiseComplement, inner));
+                return Ok(Expression::new(kind, start.to(&self.previous)));
+            }
+
+            Some(Token::LogicalNegation) => {
+                let inner = Box::new(self.parse_factor()?);
+                let kind = ExpressionKind::UnaryOp((UnOp::LogicalNegation, inner));
+                return Ok(Expression::new(kind, start.to(&self.previous)));
+            }
+
+            // ++x is x += 1, and --x is x -= 1
+            Some(token @ (Token::Increment | Token::Decrement)) => {
+                let op = if token == Token::Increment {
+                    BinOp::Add
+                } else {
+                    BinOp::Subtract
+                };
+                let one = Expression::new(ExpressionKind::Constant(Const::Int(1)), start.clone());
+                let kind = ExpressionKind::CompoundAssignment(
+                    op,
+                    Box::new(self.parse_factor()?),
+                    Box::new(one),
+                );
+                return Ok(Expression::new(kind, start.to(&self.previous)));
+            }
+
+            Some(Token::Multiplication) => {
+                let kind = ExpressionKind::Dereference(Box::new(self.parse_factor()?));
+                return Ok(Expression::new(kind, start.to(&self.previous)));
+            }
+
+            Some(Token::BitwiseAnd) => {
+                let kind = ExpressionKind::AddressOf(Box::new(self.parse_factor()?));
+                return Ok(Expression::new(kind, start.to(&self.previous)));
+            }
+
+            _ => return Err(self.unexpected("expression")),
+        };
+
+        let primary = Expression::new(kind, start.to(&self.previous));
+        self.parse_postfix(primary)
+    }
+
+    /// Applies any postfix operators following a primary expression. They
+    /// bind tighter than the prefix ones, so `*a[1]` is `*(a[1])`.
+    fn parse_postfix(&mut self, mut expr: Expression) -> Result<Expression, Located<ParseError>> {
+        loop {
+            let start = expr.span.clone();
+            let kind = match self.peek() {
+                Some(Token::OpenBracket) => {
+                    self.next();
+                    let index = self.parse_expression(&Precedence::None)?;
+                    self.expect(Token::CloseBracket)?;
+                    ExpressionKind::Subscript(Box::new(expr), Box::new(index))
+                }
+                Some(Token::Dot) => {
+                    self.next();
+                    ExpressionKind::Member(Box::new(expr), self.parse_identifier()?)
+                }
+                Some(Token::Arrow) => {
+                    self.next();
+                    ExpressionKind::PointerMember(Box::new(expr), self.parse_identifier()?)
+                }
+                Some(Token::Increment) => {
+                    self.next();
+                    ExpressionKind::PostfixIncrement(Box::new(expr))
+                }
+                Some(Token::Decrement) => {
+                    self.next();
+                    ExpressionKind::PostfixDecrement(Box::new(expr))
+                }
+                _ => return Ok(expr),
+            };
+            expr = Expression::new(kind, start.to(&self.previous));
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::lex_str;
+
+    fn errors(source: &str) -> Vec<Located<ParseError>> {
+        let tokens = lex_str(source, "test.c").unwrap_or_else(|e| panic!("{}", e));
+        Parser::new(tokens).parse_program().expect_err("should not parse")
+    }
+
+    fn lines(errors: &[Located<ParseError>]) -> Vec<u32> {
+        errors.iter().map(|e| e.span.start.line).collect()
+    }
+
+    #[test]
+    fn reports_errors_in_every_function() {
+        let errors = errors(
+            "int f(void) { return 1 }\n\
+             int g(void) { int = 2; return 0; }\n\
+             int h(void) { return 0; }\n\
+             int main(void) { return 2 + ; }\n",
+        );
+        assert_eq!(lines(&errors), vec![1, 2, 4]);
+    }
+
+    #[test]
+    fn resumes_after_block_with_error_in_header() {
+        let errors = errors(
+            "int main(void) {\n\
+             if (1 { return 1; }\n\
+             return 0;\n\
+             }\n\
+             int g(void) { return 1 +; }\n",
+        );
+        assert_eq!(lines(&errors), vec![2, 5]);
+        assert_eq!(errors[0].error.to_string(), "expected `)`, found `{`");
+    }
+
+    #[test]
+    fn stops_at_error_limit() {
+        let errors = errors(&"int x = ;\n".repeat(MAX_ERRORS + 10));
+        assert_eq!(errors.len(), MAX_ERRORS);
+        assert_eq!(errors.last().unwrap().span.start.line, MAX_ERRORS as u32);
+    }
+
+    #[test]
+    fn end_of_file_ends_parsing() {
+        let errors = errors("int f(void) { return 1 }\nint main(void) {\nreturn 0;\n");
+        assert_eq!(lines(&errors), vec![1, 3]);
+        assert!(matches!(errors[1].error, ParseError::UnexpectedEOF));
+    }
+}
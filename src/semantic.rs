@@ -0,0 +1,921 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{
+    Block, BlockItem, Declaration, Expression, ExpressionKind, ForInit, FunctionDeclaration,
+    FunctionType, Initializer, MemberDeclaration, Program, Statement, StatementKind, StorageClass,
+    StructDeclaration, StructKind, Type, VariableDeclaration,
+};
+use crate::span::{Located, Span};
+
+#[derive(Debug)]
+pub enum SemanticError {
+    UndeclaredVariable(String),
+    UndeclaredFunction(String),
+    DuplicateDeclaration(String, Span), // the previous declaration
+    NestedFunctionDefinition(String),
+    StaticBlockScopeFunction(String),
+    StorageClassInForInit,
+    InvalidLvalue,
+    AddressOfRvalue,
+    BreakOutsideLoop,
+    ContinueOutsideLoop,
+    CaseOutsideSwitch,
+    DefaultOutsideSwitch,
+    UndefinedLabel(String),
+    DuplicateLabel(String, Span), // the previous definition
+    ConflictingTag(String),
+    TagRedefinition(Type),
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SemanticError::UndeclaredVariable(name) => {
+                write!(f, "use of undeclared variable '{}'", name)
+            }
+            SemanticError::UndeclaredFunction(name) => {
+                write!(f, "call to undeclared function '{}'", name)
+            }
+            SemanticError::DuplicateDeclaration(name, _) => {
+                write!(f, "duplicate declaration of '{}'", name)
+            }
+            SemanticError::NestedFunctionDefinition(name) => {
+                write!(f, "function '{}' is defined inside another function", name)
+            }
+            SemanticError::StaticBlockScopeFunction(name) => {
+                write!(f, "function '{}' declared static at block scope", name)
+            }
+            SemanticError::StorageClassInForInit => {
+                write!(f, "storage class specifier in 'for' loop initializer")
+            }
+            SemanticError::InvalidLvalue => {
+                write!(f, "invalid lvalue in assignment or increment")
+            }
+            SemanticError::AddressOfRvalue => write!(f, "cannot take the address of an rvalue"),
+            SemanticError::BreakOutsideLoop => {
+                write!(f, "'break' statement not in loop or switch")
+            }
+            SemanticError::ContinueOutsideLoop => {
+                write!(f, "'continue' statement not in loop")
+            }
+            SemanticError::CaseOutsideSwitch => write!(f, "'case' label not in switch"),
+            SemanticError::DefaultOutsideSwitch => write!(f, "'default' label not in switch"),
+            SemanticError::UndefinedLabel(label) => {
+                write!(f, "label '{}' used but not defined", label)
+            }
+            SemanticError::DuplicateLabel(label, _) => write!(f, "duplicate label '{}'", label),
+            SemanticError::ConflictingTag(tag) => {
+                write!(f, "'{}' declared as a different kind of tag", tag)
+            }
+            SemanticError::TagRedefinition(ty) => write!(f, "redefinition of '{}'", ty),
+        }
+    }
+}
+
+impl SemanticError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SemanticError::UndeclaredVariable(_) => "E0201",
+            SemanticError::UndeclaredFunction(_) => "E0202",
+            SemanticError::DuplicateDeclaration(..) => "E0203",
+            SemanticError::NestedFunctionDefinition(_) => "E0204",
+            SemanticError::StaticBlockScopeFunction(_) => "E0205",
+            SemanticError::StorageClassInForInit => "E0206",
+            SemanticError::InvalidLvalue => "E0207",
+            SemanticError::AddressOfRvalue => "E0208",
+            SemanticError::BreakOutsideLoop => "E0209",
+            SemanticError::ContinueOutsideLoop => "E0210",
+            SemanticError::CaseOutsideSwitch => "E0211",
+            SemanticError::DefaultOutsideSwitch => "E0212",
+            SemanticError::UndefinedLabel(_) => "E0213",
+            SemanticError::DuplicateLabel(..) => "E0214",
+            SemanticError::ConflictingTag(_) => "E0215",
+            SemanticError::TagRedefinition(_) => "E0216",
+        }
+    }
+}
+
+/// Expressions that designate an object, i.e. may be assigned to or have
+/// their address taken. A member is an lvalue only if its structure is.
+fn is_lvalue(expr: &Expression) -> bool {
+    match &expr.kind {
+        ExpressionKind::Member(inner, _) => is_lvalue(inner),
+        kind => matches!(
+            kind,
+            ExpressionKind::Var(_)
+                | ExpressionKind::String(_)
+                | ExpressionKind::Dereference(_)
+                | ExpressionKind::Subscript(..)
+                | ExpressionKind::PointerMember(..)
+        ),
+    }
+}
+
+struct ScopeEntry {
+    unique_name: String,
+    has_linkage: bool, // functions keep their name so every declaration refers to the same one
+    span: Span,        // where it was declared
+}
+
+struct TagEntry {
+    unique_tag: String,
+    kind: StructKind,
+    defined: bool,
+}
+
+/// Ordinary identifiers and structure tags live in separate namespaces.
+#[derive(Default)]
+struct Scope {
+    identifiers: HashMap<String, ScopeEntry>,
+    tags: HashMap<String, TagEntry>,
+}
+
+struct Resolver {
+    scopes: Vec<Scope>, // innermost last
+    counter: usize,
+}
+
+impl Resolver {
+    fn new() -> Self {
+        Self {
+            scopes: Vec::new(),
+            counter: 0,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&ScopeEntry> {
+        self.scopes.iter().rev().find_map(|scope| scope.identifiers.get(name))
+    }
+
+    fn current_scope(&mut self) -> &mut HashMap<String, ScopeEntry> {
+        &mut self.scopes.last_mut().expect("declaration outside of any scope").identifiers
+    }
+
+    fn current_tags(&mut self) -> &mut HashMap<String, TagEntry> {
+        &mut self.scopes.last_mut().expect("declaration outside of any scope").tags
+    }
+
+    /// Declares a tag in the current scope, or returns the unique name it
+    /// already has there.
+    fn declare_tag(
+        &mut self,
+        kind: StructKind,
+        tag: &str,
+        span: &Span,
+    ) -> Result<String, Located<SemanticError>> {
+        if let Some(entry) = self.current_tags().get(tag) {
+            if entry.kind != kind {
+                return Err(Located::new(SemanticError::ConflictingTag(tag.to_string()), span));
+            }
+            return Ok(entry.unique_tag.clone());
+        }
+
+        let unique_tag = self.make_unique_name(tag);
+        self.current_tags().insert(
+            tag.to_string(),
+            TagEntry {
+                unique_tag: unique_tag.clone(),
+                kind,
+                defined: false,
+            },
+        );
+        Ok(unique_tag)
+    }
+
+    fn resolve_struct_declaration(
+        &mut self,
+        decl: StructDeclaration,
+    ) -> Result<StructDeclaration, Located<SemanticError>> {
+        let tag = self.declare_tag(decl.kind, &decl.tag, &decl.span)?;
+
+        let members = match decl.members {
+            Some(members) => {
+                let entry = self.current_tags().get_mut(&decl.tag).unwrap();
+                if entry.defined {
+                    let error = SemanticError::TagRedefinition(Type::Structure(decl.kind, tag));
+                    return Err(Located::new(error, &decl.span));
+                }
+                entry.defined = true;
+
+                let mut resolved = Vec::new();
+                for member in members {
+                    resolved.push(MemberDeclaration {
+                        ty: self.resolve_type(member.ty, &member.span)?,
+                        ..member
+                    });
+                }
+                Some(resolved)
+            }
+            None => None,
+        };
+
+        Ok(StructDeclaration {
+            tag,
+            members,
+            ..decl
+        })
+    }
+
+    /// Replaces every tag in a type with its unique name. Using a tag with
+    /// no visible declaration declares it in the current scope. Errors are
+    /// reported at `span`, where the type was written.
+    fn resolve_type(&mut self, ty: Type, span: &Span) -> Result<Type, Located<SemanticError>> {
+        match ty {
+            Type::Structure(kind, tag) => {
+                let visible = self.scopes.iter().rev().find_map(|scope| scope.tags.get(&tag));
+                match visible {
+                    Some(entry) if entry.kind != kind => {
+                        Err(Located::new(SemanticError::ConflictingTag(tag), span))
+                    }
+                    Some(entry) => Ok(Type::Structure(kind, entry.unique_tag.clone())),
+                    None => Ok(Type::Structure(kind, self.declare_tag(kind, &tag, span)?)),
+                }
+            }
+            Type::Pointer(inner) => Ok(Type::Pointer(Box::new(self.resolve_type(*inner, span)?))),
+            Type::Array(inner, len) => {
+                Ok(Type::Array(Box::new(self.resolve_type(*inner, span)?), len))
+            }
+            Type::Function(fun_type) => {
+                let mut params = Vec::new();
+                for param in fun_type.params {
+                    params.push(self.resolve_type(param, span)?);
+                }
+                Ok(Type::Function(FunctionType {
+                    params,
+                    ret: Box::new(self.resolve_type(*fun_type.ret, span)?),
+                }))
+            }
+            ty => Ok(ty),
+        }
+    }
+
+    fn make_unique_name(&mut self, name: &str) -> String {
+        let unique = format!("{}.{}", name, self.counter);
+        self.counter += 1;
+        unique
+    }
+
+    /// Declares a new local variable (or parameter) in the current scope.
+    fn declare_variable(
+        &mut self,
+        name: String,
+        span: &Span,
+    ) -> Result<String, Located<SemanticError>> {
+        // Shadowing an outer scope is fine, redeclaring in the same one is not
+        if let Some(prev) = self.current_scope().get(&name) {
+            let error = SemanticError::DuplicateDeclaration(name, prev.span.clone());
+            return Err(Located::new(error, span));
+        }
+
+        let unique_name = self.make_unique_name(&name);
+        self.current_scope().insert(
+            name,
+            ScopeEntry {
+                unique_name: unique_name.clone(),
+                has_linkage: false,
+                span: span.clone(),
+            },
+        );
+
+        Ok(unique_name)
+    }
+
+    fn resolve_file_scope_variable_declaration(
+        &mut self,
+        decl: VariableDeclaration,
+    ) -> Result<VariableDeclaration, Located<SemanticError>> {
+        let ty = self.resolve_type(decl.ty, &decl.span)?;
+
+        // File scope variables always have linkage and keep their name
+        self.current_scope().insert(
+            decl.name.clone(),
+            ScopeEntry {
+                unique_name: decl.name.clone(),
+                has_linkage: true,
+                span: decl.span.clone(),
+            },
+        );
+
+        let init = self.resolve_optional_initializer(decl.init)?;
+
+        Ok(VariableDeclaration { ty, init, ..decl })
+    }
+
+    fn resolve_local_variable_declaration(
+        &mut self,
+        decl: VariableDeclaration,
+    ) -> Result<VariableDeclaration, Located<SemanticError>> {
+        let ty = self.resolve_type(decl.ty, &decl.span)?;
+
+        if decl.storage_class == Some(StorageClass::Extern) {
+            // An extern declaration refers to the file scope variable, so it
+            // only conflicts with a non-linked declaration in the same scope.
+            if let Some(prev) = self.current_scope().get(&decl.name)
+                && !prev.has_linkage
+            {
+                let error = SemanticError::DuplicateDeclaration(decl.name, prev.span.clone());
+                return Err(Located::new(error, &decl.span));
+            }
+
+            self.current_scope().insert(
+                decl.name.clone(),
+                ScopeEntry {
+                    unique_name: decl.name.clone(),
+                    has_linkage: true,
+                    span: decl.span.clone(),
+                },
+            );
+
+            let init = self.resolve_optional_initializer(decl.init)?;
+            return Ok(VariableDeclaration { ty, init, ..decl });
+        }
+
+        let name = self.declare_variable(decl.name, &decl.span)?;
+
+        // The variable is already in scope inside its own initializer
+        let init = self.resolve_optional_initializer(decl.init)?;
+
+        Ok(VariableDeclaration {
+            name,
+            ty,
+            init,
+            ..decl
+        })
+    }
+
+    fn resolve_function_declaration(
+        &mut self,
+        func: FunctionDeclaration,
+    ) -> Result<FunctionDeclaration, Located<SemanticError>> {
+        if let Some(prev) = self.current_scope().get(&func.name)
+            && !prev.has_linkage
+        {
+            let error = SemanticError::DuplicateDeclaration(func.name, prev.span.clone());
+            return Err(Located::new(error, &func.span));
+        }
+
+        self.current_scope().insert(
+            func.name.clone(),
+            ScopeEntry {
+                unique_name: func.name.clone(),
+                has_linkage: true,
+                span: func.span.clone(),
+            },
+        );
+
+        let fun_type = self.resolve_type(Type::Function(func.fun_type), &func.span)?;
+        let Type::Function(fun_type) = fun_type else {
+            unreachable!()
+        };
+
+        // Parameters share a scope with the outermost block of the body
+        self.scopes.push(Scope::default());
+
+        let mut params = Vec::new();
+        for param in func.params {
+            params.push(self.declare_variable(param, &func.span)?);
+        }
+
+        let body = match func.body {
+            Some(body) => Some(self.resolve_block_items(body)?),
+            None => None,
+        };
+
+        self.scopes.pop();
+
+        Ok(FunctionDeclaration {
+            params,
+            fun_type,
+            body,
+            ..func
+        })
+    }
+
+    fn resolve_statement(&mut self, stmt: Statement) -> Result<Statement, Located<SemanticError>> {
+        let kind = match stmt.kind {
+            StatementKind::Return(expr) => StatementKind::Return(self.resolve_expression(expr)?),
+            StatementKind::Expression(expr) => {
+                StatementKind::Expression(self.resolve_expression(expr)?)
+            }
+            StatementKind::If(cond, then_stmt, else_stmt) => {
+                let cond = self.resolve_expression(cond)?;
+                let then_stmt = self.resolve_statement(*then_stmt)?;
+                let else_stmt = match else_stmt {
+                    Some(stmt) => Some(Box::new(self.resolve_statement(*stmt)?)),
+                    None => None,
+                };
+                StatementKind::If(cond, Box::new(then_stmt), else_stmt)
+            }
+            StatementKind::Compound(block) => StatementKind::Compound(self.resolve_block(block)?),
+            StatementKind::While(cond, body, label) => {
+                let cond = self.resolve_expression(cond)?;
+                let body = self.resolve_statement(*body)?;
+                StatementKind::While(cond, Box::new(body), label)
+            }
+            StatementKind::DoWhile(body, cond, label) => {
+                let body = self.resolve_statement(*body)?;
+                let cond = self.resolve_expression(cond)?;
+                StatementKind::DoWhile(Box::new(body), cond, label)
+            }
+            StatementKind::For(init, cond, post, body, label) => {
+                // A declaration in the init clause is scoped to the loop
+                self.scopes.push(Scope::default());
+
+                let init = match *init {
+                    ForInit::Declaration(decl) => {
+                        if decl.storage_class.is_some() {
+                            let error = SemanticError::StorageClassInForInit;
+                            return Err(Located::new(error, &decl.span));
+                        }
+                        ForInit::Declaration(self.resolve_local_variable_declaration(decl)?)
+                    }
+                    ForInit::Expression(expr) => {
+                        ForInit::Expression(self.resolve_optional_expression(expr)?)
+                    }
+                };
+                let cond = self.resolve_optional_expression(cond)?;
+                let post = self.resolve_optional_expression(post)?;
+                let body = self.resolve_statement(*body)?;
+
+                self.scopes.pop();
+
+                StatementKind::For(Box::new(init), cond, post, Box::new(body), label)
+            }
+            StatementKind::Switch(cond, body, label, cases) => {
+                let cond = self.resolve_expression(cond)?;
+                let body = self.resolve_statement(*body)?;
+                StatementKind::Switch(cond, Box::new(body), label, cases)
+            }
+            StatementKind::Case(value, stmt, label) => {
+                let value = self.resolve_expression(value)?;
+                let stmt = self.resolve_statement(*stmt)?;
+                StatementKind::Case(value, Box::new(stmt), label)
+            }
+            StatementKind::Default(stmt, label) => {
+                StatementKind::Default(Box::new(self.resolve_statement(*stmt)?), label)
+            }
+            // Labels live in their own namespace, checked by `validate_labels`
+            StatementKind::Labeled(label, stmt) => {
+                StatementKind::Labeled(label, Box::new(self.resolve_statement(*stmt)?))
+            }
+            StatementKind::Goto(label) => StatementKind::Goto(label),
+            StatementKind::Break(label) => StatementKind::Break(label),
+            StatementKind::Continue(label) => StatementKind::Continue(label),
+            StatementKind::Null => StatementKind::Null,
+        };
+
+        Ok(Statement::new(kind, stmt.span))
+    }
+
+    fn resolve_optional_expression(
+        &mut self,
+        expr: Option<Expression>,
+    ) -> Result<Option<Expression>, Located<SemanticError>> {
+        match expr {
+            Some(expr) => Ok(Some(self.resolve_expression(expr)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn resolve_optional_initializer(
+        &mut self,
+        init: Option<Initializer>,
+    ) -> Result<Option<Initializer>, Located<SemanticError>> {
+        match init {
+            Some(init) => Ok(Some(self.resolve_initializer(init)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn resolve_initializer(
+        &mut self,
+        init: Initializer,
+    ) -> Result<Initializer, Located<SemanticError>> {
+        match init {
+            Initializer::Single(expr) => Ok(Initializer::Single(self.resolve_expression(expr)?)),
+            Initializer::Compound(inits) => {
+                let mut resolved = Vec::new();
+                for init in inits {
+                    resolved.push(self.resolve_initializer(init)?);
+                }
+                Ok(Initializer::Compound(resolved))
+            }
+        }
+    }
+
+    fn resolve_expression(
+        &mut self,
+        expr: Expression,
+    ) -> Result<Expression, Located<SemanticError>> {
+        let kind = match expr.kind {
+            ExpressionKind::Constant(c) => ExpressionKind::Constant(c),
+            ExpressionKind::String(bytes) => ExpressionKind::String(bytes),
+
+            ExpressionKind::Var(name) => match self.lookup(&name) {
+                Some(entry) => ExpressionKind::Var(entry.unique_name.clone()),
+                None => {
+                    return Err(Located::new(SemanticError::UndeclaredVariable(name), &expr.span));
+                }
+            },
+
+            ExpressionKind::Cast(ty, inner) => ExpressionKind::Cast(
+                self.resolve_type(ty, &expr.span)?,
+                Box::new(self.resolve_expression(*inner)?),
+            ),
+
+            ExpressionKind::FunctionCall(name, args) => {
+                let name = match self.lookup(&name) {
+                    Some(entry) => entry.unique_name.clone(),
+                    None => {
+                        let error = SemanticError::UndeclaredFunction(name);
+                        return Err(Located::new(error, &expr.span));
+                    }
+                };
+
+                let mut resolved_args = Vec::new();
+                for arg in args {
+                    resolved_args.push(self.resolve_expression(arg)?);
+                }
+
+                ExpressionKind::FunctionCall(name, resolved_args)
+            }
+
+            ExpressionKind::Assignment(lvalue, value) => {
+                if !is_lvalue(&lvalue) {
+                    return Err(Located::new(SemanticError::InvalidLvalue, &lvalue.span));
+                }
+
+                ExpressionKind::Assignment(
+                    Box::new(self.resolve_expression(*lvalue)?),
+                    Box::new(self.resolve_expression(*value)?),
+                )
+            }
+
+            ExpressionKind::CompoundAssignment(op, lvalue, value) => {
+                if !is_lvalue(&lvalue) {
+                    return Err(Located::new(SemanticError::InvalidLvalue, &lvalue.span));
+                }
+
+                ExpressionKind::CompoundAssignment(
+                    op,
+                    Box::new(self.resolve_expression(*lvalue)?),
+                    Box::new(self.resolve_expression(*value)?),
+                )
+            }
+
+            ExpressionKind::PostfixIncrement(inner) => {
+                if !is_lvalue(&inner) {
+                    return Err(Located::new(SemanticError::InvalidLvalue, &inner.span));
+                }
+                ExpressionKind::PostfixIncrement(Box::new(self.resolve_expression(*inner)?))
+            }
+            ExpressionKind::PostfixDecrement(inner) => {
+                if !is_lvalue(&inner) {
+                    return Err(Located::new(SemanticError::InvalidLvalue, &inner.span));
+                }
+                ExpressionKind::PostfixDecrement(Box::new(self.resolve_expression(*inner)?))
+            }
+
+            ExpressionKind::Conditional(cond, then_expr, else_expr) => ExpressionKind::Conditional(
+                Box::new(self.resolve_expression(*cond)?),
+                Box::new(self.resolve_expression(*then_expr)?),
+                Box::new(self.resolve_expression(*else_expr)?),
+            ),
+
+            ExpressionKind::UnaryOp((op, inner)) => {
+                ExpressionKind::UnaryOp((op, Box::new(self.resolve_expression(*inner)?)))
+            }
+
+            ExpressionKind::BinaryOp(op, left, right) => ExpressionKind::BinaryOp(
+                op,
+                Box::new(self.resolve_expression(*left)?),
+                Box::new(self.resolve_expression(*right)?),
+            ),
+
+            ExpressionKind::Dereference(inner) => {
+                ExpressionKind::Dereference(Box::new(self.resolve_expression(*inner)?))
+            }
+
+            ExpressionKind::AddressOf(inner) => {
+                if !is_lvalue(&inner) {
+                    return Err(Located::new(SemanticError::AddressOfRvalue, &inner.span));
+                }
+                ExpressionKind::AddressOf(Box::new(self.resolve_expression(*inner)?))
+            }
+
+            ExpressionKind::Subscript(array, index) => ExpressionKind::Subscript(
+                Box::new(self.resolve_expression(*array)?),
+                Box::new(self.resolve_expression(*index)?),
+            ),
+
+            // Member names are looked up by the type checker
+            ExpressionKind::Member(inner, member) => {
+                ExpressionKind::Member(Box::new(self.resolve_expression(*inner)?), member)
+            }
+            ExpressionKind::PointerMember(inner, member) => {
+                ExpressionKind::PointerMember(Box::new(self.resolve_expression(*inner)?), member)
+            }
+        };
+
+        Ok(Expression::new(kind, expr.span))
+    }
+
+    fn resolve_block(&mut self, block: Block) -> Result<Block, Located<SemanticError>> {
+        self.scopes.push(Scope::default());
+        let block = self.resolve_block_items(block)?;
+        self.scopes.pop();
+
+        Ok(block)
+    }
+
+    /// Resolves the items of a block in the current scope.
+    fn resolve_block_items(&mut self, block: Block) -> Result<Block, Located<SemanticError>> {
+        let mut items = Vec::new();
+        for item in block.items {
+            let item = match item {
+                BlockItem::Declaration(Declaration::Variable(decl)) => BlockItem::Declaration(
+                    Declaration::Variable(self.resolve_local_variable_declaration(decl)?),
+                ),
+                BlockItem::Declaration(Declaration::Function(func)) => {
+                    if func.body.is_some() {
+                        let error = SemanticError::NestedFunctionDefinition(func.name);
+                        return Err(Located::new(error, &func.span));
+                    }
+                    if func.storage_class == Some(StorageClass::Static) {
+                        let error = SemanticError::StaticBlockScopeFunction(func.name);
+                        return Err(Located::new(error, &func.span));
+                    }
+                    BlockItem::Declaration(Declaration::Function(
+                        self.resolve_function_declaration(func)?,
+                    ))
+                }
+                BlockItem::Declaration(Declaration::Struct(decl)) => BlockItem::Declaration(
+                    Declaration::Struct(self.resolve_struct_declaration(decl)?),
+                ),
+                BlockItem::Statement(stmt) => BlockItem::Statement(self.resolve_statement(stmt)?),
+            };
+            items.push(item);
+        }
+
+        Ok(Block { items })
+    }
+}
+
+/// A statement that `break` can jump out of.
+enum Breakable {
+    Loop(String),
+    Switch(String),
+}
+
+impl Breakable {
+    fn label(&self) -> &str {
+        match self {
+            Breakable::Loop(label) | Breakable::Switch(label) => label,
+        }
+    }
+}
+
+/// Attaches every `break` to its innermost enclosing loop or switch, every
+/// `continue` to its innermost loop and every `case`/`default` to its
+/// innermost switch.
+struct LoopLabeler {
+    enclosing: Vec<Breakable>, // innermost last
+    counter: usize,
+}
+
+impl LoopLabeler {
+    fn new() -> Self {
+        Self {
+            enclosing: Vec::new(),
+            counter: 0,
+        }
+    }
+
+    fn make_label(&mut self, prefix: &str) -> String {
+        let label = format!("{}.{}", prefix, self.counter);
+        self.counter += 1;
+        label
+    }
+
+    fn label_loop_body(
+        &mut self,
+        body: Statement,
+    ) -> Result<(Box<Statement>, String), Located<SemanticError>> {
+        let label = self.make_label("loop");
+
+        self.enclosing.push(Breakable::Loop(label.clone()));
+        let body = self.label_statement(body)?;
+        self.enclosing.pop();
+
+        Ok((Box::new(body), label))
+    }
+
+    fn in_switch(&self) -> bool {
+        self.enclosing.iter().any(|b| matches!(b, Breakable::Switch(_)))
+    }
+
+    fn label_statement(&mut self, stmt: Statement) -> Result<Statement, Located<SemanticError>> {
+        let Statement { kind, span } = stmt;
+        let kind = match kind {
+            StatementKind::If(cond, then_stmt, else_stmt) => {
+                let then_stmt = self.label_statement(*then_stmt)?;
+                let else_stmt = match else_stmt {
+                    Some(stmt) => Some(Box::new(self.label_statement(*stmt)?)),
+                    None => None,
+                };
+                StatementKind::If(cond, Box::new(then_stmt), else_stmt)
+            }
+            StatementKind::Compound(block) => StatementKind::Compound(self.label_block(block)?),
+            StatementKind::While(cond, body, _) => {
+                let (body, label) = self.label_loop_body(*body)?;
+                StatementKind::While(cond, body, label)
+            }
+            StatementKind::DoWhile(body, cond, _) => {
+                let (body, label) = self.label_loop_body(*body)?;
+                StatementKind::DoWhile(body, cond, label)
+            }
+            StatementKind::For(init, cond, post, body, _) => {
+                let (body, label) = self.label_loop_body(*body)?;
+                StatementKind::For(init, cond, post, body, label)
+            }
+            StatementKind::Switch(cond, body, _, cases) => {
+                let label = self.make_label("switch");
+
+                self.enclosing.push(Breakable::Switch(label.clone()));
+                let body = self.label_statement(*body)?;
+                self.enclosing.pop();
+
+                StatementKind::Switch(cond, Box::new(body), label, cases)
+            }
+            StatementKind::Case(value, stmt, _) => {
+                if !self.in_switch() {
+                    return Err(Located::new(SemanticError::CaseOutsideSwitch, &span));
+                }
+                let label = self.make_label("case");
+                let stmt = self.label_statement(*stmt)?;
+                StatementKind::Case(value, Box::new(stmt), label)
+            }
+            StatementKind::Default(stmt, _) => {
+                if !self.in_switch() {
+                    return Err(Located::new(SemanticError::DefaultOutsideSwitch, &span));
+                }
+                let label = self.make_label("default");
+                let stmt = self.label_statement(*stmt)?;
+                StatementKind::Default(Box::new(stmt), label)
+            }
+            StatementKind::Labeled(label, stmt) => {
+                StatementKind::Labeled(label, Box::new(self.label_statement(*stmt)?))
+            }
+            StatementKind::Break(_) => match self.enclosing.last() {
+                Some(breakable) => StatementKind::Break(breakable.label().to_string()),
+                None => return Err(Located::new(SemanticError::BreakOutsideLoop, &span)),
+            },
+            StatementKind::Continue(_) => {
+                let innermost_loop = self.enclosing.iter().rev().find_map(|b| match b {
+                    Breakable::Loop(label) => Some(label),
+                    Breakable::Switch(_) => None,
+                });
+                match innermost_loop {
+                    Some(label) => StatementKind::Continue(label.clone()),
+                    None => {
+                        let error = SemanticError::ContinueOutsideLoop;
+                        return Err(Located::new(error, &span));
+                    }
+                }
+            }
+            kind @ (StatementKind::Return(_)
+            | StatementKind::Expression(_)
+            | StatementKind::Goto(_)
+            | StatementKind::Null) => kind,
+        };
+
+        Ok(Statement::new(kind, span))
+    }
+
+    fn label_block(&mut self, block: Block) -> Result<Block, Located<SemanticError>> {
+        let mut items = Vec::new();
+        for item in block.items {
+            let item = match item {
+                BlockItem::Statement(stmt) => BlockItem::Statement(self.label_statement(stmt)?),
+                BlockItem::Declaration(decl) => BlockItem::Declaration(decl),
+            };
+            items.push(item);
+        }
+        Ok(Block { items })
+    }
+
+    fn label_function(
+        &mut self,
+        func: FunctionDeclaration,
+    ) -> Result<FunctionDeclaration, Located<SemanticError>> {
+        let body = match func.body {
+            Some(body) => Some(self.label_block(body)?),
+            None => None,
+        };
+
+        Ok(FunctionDeclaration { body, ..func })
+    }
+}
+
+/// Records the labels defined in a statement and the ones its gotos target.
+fn collect_labels<'a>(
+    stmt: &'a Statement,
+    defined: &mut HashMap<&'a str, &'a Span>,
+    targets: &mut Vec<(&'a str, &'a Span)>,
+) -> Result<(), Located<SemanticError>> {
+    match &stmt.kind {
+        StatementKind::Labeled(label, inner) => {
+            if let Some(previous) = defined.insert(label, &stmt.span) {
+                let error = SemanticError::DuplicateLabel(label.clone(), previous.clone());
+                return Err(Located::new(error, &stmt.span));
+            }
+            collect_labels(inner, defined, targets)
+        }
+        StatementKind::Goto(label) => {
+            targets.push((label, &stmt.span));
+            Ok(())
+        }
+        StatementKind::If(_, then_stmt, else_stmt) => {
+            collect_labels(then_stmt, defined, targets)?;
+            match else_stmt {
+                Some(stmt) => collect_labels(stmt, defined, targets),
+                None => Ok(()),
+            }
+        }
+        StatementKind::Compound(block) => collect_block_labels(block, defined, targets),
+        StatementKind::While(_, body, _)
+        | StatementKind::DoWhile(body, _, _)
+        | StatementKind::For(_, _, _, body, _)
+        | StatementKind::Switch(_, body, _, _)
+        | StatementKind::Case(_, body, _)
+        | StatementKind::Default(body, _) => collect_labels(body, defined, targets),
+        StatementKind::Return(_)
+        | StatementKind::Expression(_)
+        | StatementKind::Break(_)
+        | StatementKind::Continue(_)
+        | StatementKind::Null => Ok(()),
+    }
+}
+
+fn collect_block_labels<'a>(
+    block: &'a Block,
+    defined: &mut HashMap<&'a str, &'a Span>,
+    targets: &mut Vec<(&'a str, &'a Span)>,
+) -> Result<(), Located<SemanticError>> {
+    for item in &block.items {
+        if let BlockItem::Statement(stmt) = item {
+            collect_labels(stmt, defined, targets)?;
+        }
+    }
+    Ok(())
+}
+
+/// Labels are scoped to the whole function body: each may be defined once,
+/// and every goto must target one of them.
+fn validate_labels(func: &FunctionDeclaration) -> Result<(), Located<SemanticError>> {
+    let Some(body) = &func.body else {
+        return Ok(());
+    };
+
+    let mut defined = HashMap::new();
+    let mut targets = Vec::new();
+    collect_block_labels(body, &mut defined, &mut targets)?;
+
+    match targets.into_iter().find(|(label, _)| !defined.contains_key(label)) {
+        Some((label, span)) => {
+            Err(Located::new(SemanticError::UndefinedLabel(label.to_string()), span))
+        }
+        None => Ok(()),
+    }
+}
+
+/// Resolves every variable to a uniquely renamed declaration, checks the
+/// labels of each function, then labels loops so `break` and `continue` know
+/// where to jump.
+pub fn analyze(program: Program) -> Result<Program, Located<SemanticError>> {
+    let mut resolver = Resolver::new();
+    resolver.scopes.push(Scope::default()); // file scope
+
+    let mut labeler = LoopLabeler::new();
+
+    let mut declarations = Vec::new();
+    for decl in program.declarations {
+        let decl = match decl {
+            Declaration::Function(func) => {
+                let func = resolver.resolve_function_declaration(func)?;
+                validate_labels(&func)?;
+                Declaration::Function(labeler.label_function(func)?)
+            }
+            Declaration::Variable(decl) => {
+                Declaration::Variable(resolver.resolve_file_scope_variable_declaration(decl)?)
+            }
+            Declaration::Struct(decl) => {
+                Declaration::Struct(resolver.resolve_struct_declaration(decl)?)
+            }
+        };
+        declarations.push(decl);
+    }
+
+    Ok(Program { declarations })
+}
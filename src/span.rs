@@ -0,0 +1,63 @@
+use std::fmt;
+use std::rc::Rc;
+
+/// A line and column in a source file, both counted from 1. Columns count
+/// characters, not bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Position {
+    pub line: u32,
+    pub column: u32,
+}
+
+/// The stretch of source a token or syntax tree node was read from. The
+/// end is exclusive, so a single character at column 5 ends at column 6.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Span {
+    pub file: Rc<str>,
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(&self, other: &Span) -> Span {
+        Span {
+            file: self.file.clone(),
+            start: self.start,
+            end: other.end,
+        }
+    }
+
+    /// The empty span just past the end of this one.
+    pub fn after(&self) -> Span {
+        Span {
+            file: self.file.clone(),
+            start: self.end,
+            end: self.end,
+        }
+    }
+}
+
+/// Formats the start of the span as `file:line:col`, which editors
+/// recognize as a jump target.
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.start.line, self.start.column)
+    }
+}
+
+/// An error found at a particular place in the source.
+#[derive(Debug)]
+pub struct Located<E> {
+    pub error: E,
+    pub span: Span,
+}
+
+impl<E> Located<E> {
+    pub fn new(error: E, span: &Span) -> Self {
+        Self {
+            error,
+            span: span.clone(),
+        }
+    }
+}
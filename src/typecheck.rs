@@ -0,0 +1,1890 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{
+    BinOp, Block, BlockItem, Const, Declaration, Expression, ExpressionKind, ForInit,
+    FunctionDeclaration, Initializer, Program, Statement, StatementKind, StorageClass,
+    StructDeclaration, StructKind, SwitchCase, Type, UnOp, VariableDeclaration,
+};
+use crate::span::{Located, Span};
+
+#[derive(Debug)]
+pub enum TypeError {
+    IncompatibleDeclarations(String),
+    FunctionRedefinition(String),
+    WrongArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    CallToVariable(String),
+    FunctionUsedAsVariable(String),
+    ConflictingLinkage(String),
+    ConflictingDefinitions(String),
+    NonConstantInitializer(String),
+    ExternWithInitializer(String),
+    InvalidDoubleOperand(&'static str), // operator
+    InvalidPointerOperand(&'static str), // operator
+    DereferenceNonPointer(Type),
+    IncompatibleTypes { from: Type, to: Type },
+    IncompatibleOperands(Type, Type),
+    InvalidCast { from: Type, to: Type },
+    InvalidSubscript(Type, Type), // array, index
+    ArrayAssignment,
+    InvalidInitializer(String),
+    TooManyInitializers(String),
+    TypeTooLarge(Type),
+    FrameTooLarge,
+    StringTooLong(String),
+    IncompleteType(Type),
+    DuplicateMember(String),
+    NotAStructure(Type),
+    UnknownMember(Type, String),
+    NonScalar(Type),
+    InvalidSwitch(Type),
+    NonConstantCase,
+    DuplicateCase(Const),
+    DuplicateDefault,
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TypeError::IncompatibleDeclarations(name) => {
+                write!(f, "conflicting types for '{}'", name)
+            }
+            TypeError::FunctionRedefinition(name) => {
+                write!(f, "redefinition of function '{}'", name)
+            }
+            TypeError::WrongArgumentCount {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "function '{}' expects {} argument(s) but {} were given",
+                name, expected, found
+            ),
+            TypeError::CallToVariable(name) => {
+                write!(f, "called object '{}' is not a function", name)
+            }
+            TypeError::FunctionUsedAsVariable(name) => {
+                write!(f, "function '{}' used as a variable", name)
+            }
+            TypeError::ConflictingLinkage(name) => {
+                write!(f, "conflicting linkage for '{}'", name)
+            }
+            TypeError::ConflictingDefinitions(name) => {
+                write!(f, "conflicting definitions of '{}'", name)
+            }
+            TypeError::NonConstantInitializer(name) => {
+                write!(f, "initializer of static variable '{}' is not constant", name)
+            }
+            TypeError::ExternWithInitializer(name) => {
+                write!(f, "extern variable '{}' has an initializer", name)
+            }
+            TypeError::InvalidDoubleOperand(op) => {
+                write!(f, "invalid operand of type 'double' to '{}'", op)
+            }
+            TypeError::InvalidPointerOperand(op) => {
+                write!(f, "invalid operand of pointer type to '{}'", op)
+            }
+            TypeError::DereferenceNonPointer(ty) => {
+                write!(f, "cannot dereference non-pointer type '{}'", ty)
+            }
+            TypeError::IncompatibleTypes { from, to } => {
+                write!(f, "cannot convert '{}' to '{}'", from, to)
+            }
+            TypeError::IncompatibleOperands(a, b) => {
+                write!(f, "incompatible operand types '{}' and '{}'", a, b)
+            }
+            TypeError::InvalidCast { from, to } => {
+                write!(f, "invalid cast from '{}' to '{}'", from, to)
+            }
+            TypeError::InvalidSubscript(array, index) => {
+                write!(f, "cannot subscript '{}' with '{}'", array, index)
+            }
+            TypeError::ArrayAssignment => write!(f, "arrays are not assignable"),
+            TypeError::InvalidInitializer(name) => {
+                write!(f, "invalid initializer for '{}'", name)
+            }
+            TypeError::TooManyInitializers(name) => {
+                write!(f, "too many elements in initializer for '{}'", name)
+            }
+            TypeError::TypeTooLarge(ty) => write!(f, "type '{}' is too large", ty),
+            TypeError::FrameTooLarge => write!(
+                f,
+                "function needs more than {} bytes of stack",
+                MAX_OBJECT_SIZE
+            ),
+            TypeError::StringTooLong(name) => {
+                write!(f, "initializer string for '{}' is too long", name)
+            }
+            TypeError::IncompleteType(ty) => write!(f, "use of incomplete type '{}'", ty),
+            TypeError::DuplicateMember(name) => write!(f, "duplicate member '{}'", name),
+            TypeError::NotAStructure(ty) => {
+                write!(f, "member access into '{}', which is not a structure or union", ty)
+            }
+            TypeError::UnknownMember(ty, name) => {
+                write!(f, "'{}' has no member named '{}'", ty, name)
+            }
+            TypeError::NonScalar(ty) => {
+                write!(f, "'{}' used where a scalar value is required", ty)
+            }
+            TypeError::InvalidSwitch(ty) => {
+                write!(f, "switch on '{}', which is not an integer type", ty)
+            }
+            TypeError::NonConstantCase => {
+                write!(f, "case label is not an integer constant expression")
+            }
+            TypeError::DuplicateCase(value) => write!(f, "duplicate case value {}", value),
+            TypeError::DuplicateDefault => write!(f, "multiple default labels in one switch"),
+        }
+    }
+}
+
+impl TypeError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TypeError::IncompatibleDeclarations(_) => "E0301",
+            TypeError::FunctionRedefinition(_) => "E0302",
+            TypeError::WrongArgumentCount { .. } => "E0303",
+            TypeError::CallToVariable(_) => "E0304",
+            TypeError::FunctionUsedAsVariable(_) => "E0305",
+            TypeError::ConflictingLinkage(_) => "E0306",
+            TypeError::ConflictingDefinitions(_) => "E0307",
+            TypeError::NonConstantInitializer(_) => "E0308",
+            TypeError::ExternWithInitializer(_) => "E0309",
+            TypeError::InvalidDoubleOperand(_) => "E0310",
+            TypeError::InvalidPointerOperand(_) => "E0311",
+            TypeError::DereferenceNonPointer(_) => "E0312",
+            TypeError::IncompatibleTypes { .. } => "E0313",
+            TypeError::IncompatibleOperands(..) => "E0314",
+            TypeError::InvalidCast { .. } => "E0315",
+            TypeError::InvalidSubscript(..) => "E0316",
+            TypeError::ArrayAssignment => "E0317",
+            TypeError::InvalidInitializer(_) => "E0318",
+            TypeError::TooManyInitializers(_) => "E0319",
+            TypeError::StringTooLong(_) => "E0320",
+            TypeError::IncompleteType(_) => "E0321",
+            TypeError::DuplicateMember(_) => "E0322",
+            TypeError::NotAStructure(_) => "E0323",
+            TypeError::UnknownMember(..) => "E0324",
+            TypeError::NonScalar(_) => "E0325",
+            TypeError::InvalidSwitch(_) => "E0326",
+            TypeError::NonConstantCase => "E0327",
+            TypeError::DuplicateCase(_) => "E0328",
+            TypeError::DuplicateDefault => "E0329",
+            TypeError::TypeTooLarge(_) => "E0330",
+            TypeError::FrameTooLarge => "E0331",
+        }
+    }
+}
+
+/// A value in the initializer of a static variable, already converted to its type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StaticInit {
+    Char(i8),
+    UChar(u8),
+    Int(i32),
+    Long(i64),
+    UInt(u32),
+    ULong(u64),
+    Double(f64),
+    Zero(usize),           // bytes
+    String(Vec<u8>, bool), // bytes, null terminated
+    Pointer(String),       // address of a string constant
+}
+
+impl StaticInit {
+    pub fn from_const(c: Const, ty: &Type) -> Self {
+        if let Type::Double = ty {
+            let value = match c {
+                Const::Int(v) => v as f64,
+                Const::Long(v) => v as f64,
+                Const::UInt(v) => v as f64,
+                Const::ULong(v) => v as f64,
+                Const::Double(v) => v,
+                Const::Char(v) => v as f64,
+                Const::UChar(v) => v as f64,
+            };
+            return StaticInit::Double(value);
+        }
+
+        // Integer conversions wrap like a runtime cast; doubles truncate
+        // toward zero.
+        let value = match c {
+            Const::Int(v) => v as i64,
+            Const::Long(v) => v,
+            Const::UInt(v) => v as i64,
+            Const::ULong(v) => v as i64,
+            Const::Double(v) if ty.is_signed() => v as i64,
+            Const::Double(v) => v as u64 as i64,
+            Const::Char(v) => v as i64,
+            Const::UChar(v) => v as i64,
+        };
+
+        match ty {
+            Type::Char | Type::SChar => StaticInit::Char(value as i8),
+            Type::UChar => StaticInit::UChar(value as u8),
+            Type::Int => StaticInit::Int(value as i32),
+            Type::Long => StaticInit::Long(value),
+            Type::UInt => StaticInit::UInt(value as u32),
+            Type::ULong | Type::Pointer(_) => StaticInit::ULong(value as u64),
+            Type::Double => unreachable!(),
+            Type::Array(..) | Type::Structure(..) => {
+                unreachable!("aggregates are initialized element by element")
+            }
+            Type::Function(_) => unreachable!("functions have no initializers"),
+        }
+    }
+
+    /// Number of bytes the value occupies.
+    pub fn size(&self) -> usize {
+        match self {
+            StaticInit::Char(_) | StaticInit::UChar(_) => 1,
+            StaticInit::Int(_) | StaticInit::UInt(_) => 4,
+            StaticInit::Long(_)
+            | StaticInit::ULong(_)
+            | StaticInit::Double(_)
+            | StaticInit::Pointer(_) => 8,
+            StaticInit::Zero(bytes) => *bytes,
+            StaticInit::String(bytes, null_terminated) => bytes.len() + *null_terminated as usize,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            // -0.0 is not all zero bits, so it can't live in .bss
+            StaticInit::Double(v) => v.to_bits() == 0,
+            _ => matches!(
+                self,
+                StaticInit::Char(0)
+                    | StaticInit::UChar(0)
+                    | StaticInit::Int(0)
+                    | StaticInit::Long(0)
+                    | StaticInit::UInt(0)
+                    | StaticInit::ULong(0)
+                    | StaticInit::Zero(_)
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InitialValue {
+    Tentative, // `int x;` at file scope, zero unless defined elsewhere
+    Initial(Vec<StaticInit>),
+    NoInitializer, // extern declaration
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IdentifierAttrs {
+    Function { defined: bool, global: bool },
+    Static { init: InitialValue, global: bool },
+    Constant(StaticInit), // read-only data such as string literals
+    Local,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub ty: Type,
+    pub attrs: IdentifierAttrs,
+}
+
+/// A structure member and its byte offset; every union member is at 0.
+#[derive(Debug, Clone)]
+pub struct Member {
+    pub name: String,
+    pub ty: Type,
+    pub offset: usize,
+}
+
+/// The layout of a complete structure or union type.
+#[derive(Debug, Clone)]
+pub struct StructEntry {
+    pub size: usize,
+    pub alignment: usize,
+    pub members: Vec<Member>,
+}
+
+impl StructEntry {
+    pub fn member(&self, name: &str) -> Option<&Member> {
+        self.members.iter().find(|member| member.name == name)
+    }
+}
+
+/// Every function and (uniquely renamed) variable in the program, along with
+/// the layout of every structure type, keyed by its unique tag.
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    symbols: HashMap<String, Symbol>,
+    structures: HashMap<String, StructEntry>,
+    string_count: usize,
+}
+
+impl SymbolTable {
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.get(name)
+    }
+
+    fn insert(&mut self, name: String, symbol: Symbol) {
+        self.symbols.insert(name, symbol);
+    }
+
+    /// Registers a local variable, e.g. a temporary created during IR generation.
+    pub fn add_local(&mut self, name: String, ty: Type) {
+        self.insert(
+            name,
+            Symbol {
+                ty,
+                attrs: IdentifierAttrs::Local,
+            },
+        );
+    }
+
+    /// The layout of a structure type, or None while it is incomplete.
+    pub fn structure(&self, tag: &str) -> Option<&StructEntry> {
+        self.structures.get(tag)
+    }
+
+    /// Returns false for structures that are declared but not (yet) defined,
+    /// and arrays of them.
+    pub fn is_complete(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Structure(_, tag) => self.structures.contains_key(tag),
+            Type::Array(element, _) => self.is_complete(element),
+            _ => true,
+        }
+    }
+
+    /// Size in bytes of a value of any complete type.
+    pub fn size_of(&self, ty: &Type) -> usize {
+        match ty {
+            Type::Structure(_, tag) => self.structures[tag].size,
+            Type::Array(element, len) => self.size_of(element) * len,
+            _ => ty.size(),
+        }
+    }
+
+    /// Size in bytes, or None if the type is incomplete or larger than
+    /// `MAX_OBJECT_SIZE`.
+    pub fn checked_size_of(&self, ty: &Type) -> Option<usize> {
+        let size = match ty {
+            Type::Structure(_, tag) => self.structures.get(tag)?.size,
+            Type::Array(element, len) => self.checked_size_of(element)?.checked_mul(*len)?,
+            _ => ty.size(),
+        };
+        (size <= MAX_OBJECT_SIZE).then_some(size)
+    }
+
+    pub fn alignment_of(&self, ty: &Type) -> usize {
+        match ty {
+            Type::Structure(_, tag) => self.structures[tag].alignment,
+            Type::Array(element, _) => self.alignment_of(element),
+            _ => ty.size(),
+        }
+    }
+
+    /// Stores a string literal as a constant `char` array and returns its name.
+    pub fn add_string_constant(&mut self, bytes: Vec<u8>) -> String {
+        let name = format!("string.{}", self.string_count);
+        self.string_count += 1;
+        self.insert(
+            name.clone(),
+            Symbol {
+                ty: Type::Array(Box::new(Type::Char), bytes.len() + 1),
+                attrs: IdentifierAttrs::Constant(StaticInit::String(bytes, true)),
+            },
+        );
+        name
+    }
+
+    /// The type of a variable; panics on unknown names since every variable
+    /// is recorded during type checking or IR generation.
+    pub fn type_of(&self, name: &str) -> &Type {
+        match self.get(name) {
+            Some(symbol) => &symbol.ty,
+            None => panic!("no symbol table entry for '{}'", name),
+        }
+    }
+
+    /// Returns true if `name` is a function with a body in this translation unit.
+    pub fn is_defined_function(&self, name: &str) -> bool {
+        matches!(
+            self.get(name).map(|symbol| &symbol.attrs),
+            Some(IdentifierAttrs::Function { defined: true, .. })
+        )
+    }
+
+    /// Returns true if `name` lives in static storage rather than on the stack.
+    pub fn is_static(&self, name: &str) -> bool {
+        matches!(
+            self.get(name).map(|symbol| &symbol.attrs),
+            Some(IdentifierAttrs::Static { .. })
+        )
+    }
+
+    /// Returns true if `name` is read-only data emitted under a local label.
+    pub fn is_constant(&self, name: &str) -> bool {
+        matches!(
+            self.get(name).map(|symbol| &symbol.attrs),
+            Some(IdentifierAttrs::Constant(_))
+        )
+    }
+
+    /// Returns true if `name` is visible to other translation units.
+    pub fn is_global(&self, name: &str) -> bool {
+        matches!(
+            self.get(name).map(|symbol| &symbol.attrs),
+            Some(IdentifierAttrs::Function { global: true, .. })
+                | Some(IdentifierAttrs::Static { global: true, .. })
+        )
+    }
+
+    /// All symbols sorted by name, so output is deterministic.
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Symbol)> {
+        let mut symbols: Vec<_> = self.symbols.iter().collect();
+        symbols.sort_by(|a, b| a.0.cmp(b.0));
+        symbols.into_iter()
+    }
+}
+
+/// Strips the suffix added by identifier resolution (`x.3` -> `x`).
+fn source_name(unique_name: &str) -> String {
+    match unique_name.split_once('.') {
+        Some((name, _)) => name.to_string(),
+        None => unique_name.to_string(),
+    }
+}
+
+/// Integer promotion: character types are widened to int before any
+/// arithmetic.
+fn promote(ty: &Type) -> Type {
+    if ty.is_character() { Type::Int } else { ty.clone() }
+}
+
+/// The type both operands of an arithmetic operation are converted to, per
+/// the usual arithmetic conversions: double wins, then the wider type, and
+/// between types of equal size the unsigned one.
+fn common_type(a: &Type, b: &Type) -> Type {
+    let (a, b) = (&promote(a), &promote(b));
+    if a == b {
+        a.clone()
+    } else if *a == Type::Double || *b == Type::Double {
+        Type::Double
+    } else if a.size() == b.size() {
+        if a.is_signed() { b.clone() } else { a.clone() }
+    } else if a.size() > b.size() {
+        a.clone()
+    } else {
+        b.clone()
+    }
+}
+
+fn const_type(c: &Const) -> Type {
+    match c {
+        Const::Int(_) => Type::Int,
+        Const::Long(_) => Type::Long,
+        Const::UInt(_) => Type::UInt,
+        Const::ULong(_) => Type::ULong,
+        Const::Double(_) => Type::Double,
+        Const::Char(_) => Type::Char,
+        Const::UChar(_) => Type::UChar,
+    }
+}
+
+/// Generated code addresses memory with signed 32-bit offsets, so no
+/// object, and no function's stack frame, may be larger than this.
+pub const MAX_OBJECT_SIZE: usize = i32::MAX as usize;
+
+fn round_up(value: usize, alignment: usize) -> usize {
+    value.div_ceil(alignment) * alignment
+}
+
+/// An integer constant zero, which converts implicitly to any pointer type.
+fn is_null_pointer_constant(c: &Const) -> bool {
+    matches!(
+        c,
+        Const::Int(0) | Const::Long(0) | Const::UInt(0) | Const::ULong(0)
+    )
+}
+
+fn is_null_pointer(expr: &Expression) -> bool {
+    matches!(&expr.kind, ExpressionKind::Constant(c) if is_null_pointer_constant(c))
+}
+
+/// The type two operands are converted to when at least one is a pointer:
+/// they must have the same type, or one must be a null pointer constant.
+fn common_pointer_type(a: &Expression, b: &Expression) -> Result<Type, Located<TypeError>> {
+    if a.ty() == b.ty() || is_null_pointer(b) {
+        Ok(a.ty().clone())
+    } else if is_null_pointer(a) {
+        Ok(b.ty().clone())
+    } else {
+        let error = TypeError::IncompatibleOperands(a.ty().clone(), b.ty().clone());
+        Err(Located::new(error, &a.span.to(&b.span)))
+    }
+}
+
+/// Types the operands of `+` or `-` when at least one is a pointer. The
+/// integer operand is widened to long; subtracting two pointers of the same
+/// type gives the number of elements between them.
+fn pointer_arithmetic(
+    op: &BinOp,
+    left: Expression,
+    right: Expression,
+) -> Result<(Expression, Expression, Type), Located<TypeError>> {
+    let (l, r) = (left.ty(), right.ty());
+    match op {
+        BinOp::Add | BinOp::Subtract if l.is_pointer() && r.is_integer() => {
+            let ty = l.clone();
+            Ok((left, convert_to(right, &Type::Long), ty))
+        }
+        BinOp::Add if l.is_integer() && r.is_pointer() => {
+            let ty = r.clone();
+            Ok((convert_to(left, &Type::Long), right, ty))
+        }
+        BinOp::Subtract if l.is_pointer() && l == r => Ok((left, right, Type::Long)),
+        _ => {
+            let error = TypeError::IncompatibleOperands(l.clone(), r.clone());
+            Err(Located::new(error, &left.span.to(&right.span)))
+        }
+    }
+}
+
+/// Rejects operators that are not defined on the operand types.
+fn check_operand_types(op: &BinOp, left: &Type, right: &Type) -> Result<(), TypeError> {
+    // Only arithmetic and comparisons are defined on doubles
+    if *left == Type::Double || *right == Type::Double {
+        let symbol = match op {
+            BinOp::Remainder => Some("%"),
+            BinOp::BitwiseAnd => Some("&"),
+            BinOp::BitwiseOr => Some("|"),
+            BinOp::BitwiseXor => Some("^"),
+            BinOp::LeftShift => Some("<<"),
+            BinOp::RightShift => Some(">>"),
+            _ => None,
+        };
+        if let Some(symbol) = symbol {
+            return Err(TypeError::InvalidDoubleOperand(symbol));
+        }
+    }
+
+    // ... and pointers can only be compared, tested, offset by an
+    // integer or subtracted from each other
+    if left.is_pointer() || right.is_pointer() {
+        let symbol = match op {
+            BinOp::Multiply => Some("*"),
+            BinOp::Divide => Some("/"),
+            BinOp::Remainder => Some("%"),
+            BinOp::BitwiseAnd => Some("&"),
+            BinOp::BitwiseOr => Some("|"),
+            BinOp::BitwiseXor => Some("^"),
+            BinOp::LeftShift => Some("<<"),
+            BinOp::RightShift => Some(">>"),
+            _ => None,
+        };
+        if let Some(symbol) = symbol {
+            return Err(TypeError::InvalidPointerOperand(symbol));
+        }
+    }
+
+    Ok(())
+}
+
+/// Converts `expr` to `ty` as assignment does: arithmetic types convert
+/// freely, but pointers only accept the same type or a null pointer constant.
+fn convert_by_assignment(expr: Expression, ty: &Type) -> Result<Expression, Located<TypeError>> {
+    if expr.ty() == ty
+        || (expr.ty().is_arithmetic() && ty.is_arithmetic())
+        || (ty.is_pointer() && is_null_pointer(&expr))
+    {
+        Ok(convert_to(expr, ty))
+    } else {
+        let error = TypeError::IncompatibleTypes {
+            from: expr.ty().clone(),
+            to: ty.clone(),
+        };
+        Err(Located::new(error, &expr.span))
+    }
+}
+
+/// Converts a constant to another arithmetic type, wrapping or truncating
+/// like a runtime cast would.
+fn convert_const(c: Const, ty: &Type) -> Const {
+    match StaticInit::from_const(c, ty) {
+        StaticInit::Char(v) => Const::Char(v),
+        StaticInit::UChar(v) => Const::UChar(v),
+        StaticInit::Int(v) => Const::Int(v),
+        StaticInit::Long(v) => Const::Long(v),
+        StaticInit::UInt(v) => Const::UInt(v),
+        StaticInit::ULong(v) => Const::ULong(v),
+        StaticInit::Double(v) => Const::Double(v),
+        _ => unreachable!("only converted to arithmetic types"),
+    }
+}
+
+/// The value of an arithmetic constant expression: constants combined with
+/// casts and the unary, binary and conditional operators. Static
+/// initializers are folded before they are type checked, so operands are
+/// converted here rather than relying on the casts the type checker inserts.
+fn constant_value(expr: &Expression) -> Option<Const> {
+    match &expr.kind {
+        ExpressionKind::Constant(c) => Some(*c),
+        ExpressionKind::Cast(ty, inner) if ty.is_arithmetic() => {
+            Some(convert_const(constant_value(inner)?, ty))
+        }
+        ExpressionKind::UnaryOp((op, inner)) => {
+            let value = constant_value(inner)?;
+            let value = convert_const(value, &promote(&const_type(&value)));
+            match (op, value) {
+                (UnOp::Negation, Const::Int(v)) => Some(Const::Int(v.wrapping_neg())),
+                (UnOp::Negation, Const::Long(v)) => Some(Const::Long(v.wrapping_neg())),
+                (UnOp::Negation, Const::UInt(v)) => Some(Const::UInt(v.wrapping_neg())),
+                (UnOp::Negation, Const::ULong(v)) => Some(Const::ULong(v.wrapping_neg())),
+                (UnOp::Negation, Const::Double(v)) => Some(Const::Double(-v)),
+                (UnOp::BitwiseComplement, Const::Int(v)) => Some(Const::Int(!v)),
+                (UnOp::BitwiseComplement, Const::Long(v)) => Some(Const::Long(!v)),
+                (UnOp::BitwiseComplement, Const::UInt(v)) => Some(Const::UInt(!v)),
+                (UnOp::BitwiseComplement, Const::ULong(v)) => Some(Const::ULong(!v)),
+                (UnOp::LogicalNegation, value) => Some(Const::Int(is_zero(&value) as i32)),
+                _ => None,
+            }
+        }
+        ExpressionKind::BinaryOp(op, left, right) => {
+            fold_binary(op, constant_value(left)?, constant_value(right)?)
+        }
+        ExpressionKind::Conditional(cond, then, otherwise) => {
+            let cond = constant_value(cond)?;
+            let (then, otherwise) = (constant_value(then)?, constant_value(otherwise)?);
+            let ty = common_type(&const_type(&then), &const_type(&otherwise));
+            let value = if is_zero(&cond) { otherwise } else { then };
+            Some(convert_const(value, &ty))
+        }
+        _ => None,
+    }
+}
+
+fn is_zero(c: &Const) -> bool {
+    match c {
+        Const::Double(v) => *v == 0.0,
+        c => integer_value(*c) == Some(0),
+    }
+}
+
+/// An integer constant widened so that values of every integer type fit.
+fn integer_value(c: Const) -> Option<i128> {
+    match c {
+        Const::Char(v) => Some(v as i128),
+        Const::UChar(v) => Some(v as i128),
+        Const::Int(v) => Some(v as i128),
+        Const::Long(v) => Some(v as i128),
+        Const::UInt(v) => Some(v as i128),
+        Const::ULong(v) => Some(v as i128),
+        Const::Double(_) => None,
+    }
+}
+
+/// Folds a binary operator applied to two constants, or returns None if
+/// the result is undefined, as for division by zero or shifting by the
+/// width of the type or more. Integer results wrap to their type.
+fn fold_binary(op: &BinOp, left: Const, right: Const) -> Option<Const> {
+    let bool_const = |b: bool| Some(Const::Int(b as i32));
+    // The low 64 bits of an i128 result are the wrapped value of any type
+    let wrap = |value: i128, ty: &Type| Some(convert_const(Const::ULong(value as u64), ty));
+
+    match op {
+        BinOp::LogicalAnd => return bool_const(!is_zero(&left) && !is_zero(&right)),
+        BinOp::LogicalOr => return bool_const(!is_zero(&left) || !is_zero(&right)),
+        // The result has the promoted type of the left operand alone
+        BinOp::LeftShift | BinOp::RightShift => {
+            let ty = promote(&const_type(&left));
+            let value = integer_value(left)?;
+            let amount = integer_value(right)?;
+            if amount < 0 || amount >= ty.size() as i128 * 8 {
+                return None;
+            }
+            let value = match op {
+                BinOp::LeftShift => value << amount,
+                _ => value >> amount,
+            };
+            return wrap(value, &ty);
+        }
+        _ => {}
+    }
+
+    let ty = common_type(&const_type(&left), &const_type(&right));
+    if ty == Type::Double {
+        let (Const::Double(l), Const::Double(r)) =
+            (convert_const(left, &ty), convert_const(right, &ty))
+        else {
+            unreachable!("converted to double");
+        };
+        let value = match op {
+            BinOp::Add => l + r,
+            BinOp::Subtract => l - r,
+            BinOp::Multiply => l * r,
+            BinOp::Divide => l / r,
+            BinOp::LessThan => return bool_const(l < r),
+            BinOp::GreaterThan => return bool_const(l > r),
+            BinOp::LessThanEqual => return bool_const(l <= r),
+            BinOp::GreaterThanEqual => return bool_const(l >= r),
+            BinOp::Equal => return bool_const(l == r),
+            BinOp::NotEqual => return bool_const(l != r),
+            _ => return None,
+        };
+        return Some(Const::Double(value));
+    }
+
+    let l = integer_value(convert_const(left, &ty))?;
+    let r = integer_value(convert_const(right, &ty))?;
+    let value = match op {
+        BinOp::Add => l + r,
+        BinOp::Subtract => l - r,
+        BinOp::Multiply => l.wrapping_mul(r),
+        BinOp::Divide => l.checked_div(r)?,
+        BinOp::Remainder => l.checked_rem(r)?,
+        BinOp::BitwiseAnd => l & r,
+        BinOp::BitwiseOr => l | r,
+        BinOp::BitwiseXor => l ^ r,
+        BinOp::LessThan => return bool_const(l < r),
+        BinOp::GreaterThan => return bool_const(l > r),
+        BinOp::LessThanEqual => return bool_const(l <= r),
+        BinOp::GreaterThanEqual => return bool_const(l >= r),
+        BinOp::Equal => return bool_const(l == r),
+        BinOp::NotEqual => return bool_const(l != r),
+        BinOp::LogicalAnd | BinOp::LogicalOr | BinOp::LeftShift | BinOp::RightShift => {
+            unreachable!("folded above")
+        }
+    };
+    wrap(value, &ty)
+}
+
+/// The value of an integer constant expression, such as a case label.
+fn integer_constant(expr: &Expression) -> Option<Const> {
+    match constant_value(expr)? {
+        Const::Double(_) => None,
+        c => Some(c),
+    }
+}
+
+fn zero_initializer(ty: &Type, symbols: &SymbolTable, span: &Span) -> Initializer {
+    let zero = match ty {
+        Type::Char | Type::SChar => Const::Char(0),
+        Type::UChar => Const::UChar(0),
+        Type::Int => Const::Int(0),
+        Type::Long => Const::Long(0),
+        Type::UInt => Const::UInt(0),
+        Type::ULong | Type::Pointer(_) => Const::ULong(0),
+        Type::Double => Const::Double(0.0),
+        Type::Array(element, len) => {
+            let zeros = (0..*len).map(|_| zero_initializer(element, symbols, span)).collect();
+            return Initializer::Compound(zeros);
+        }
+        // Only the first member of a union is initialized
+        Type::Structure(kind, tag) => {
+            let members = &symbols.structure(tag).unwrap().members;
+            let count = match kind {
+                StructKind::Struct => members.len(),
+                StructKind::Union => 1,
+            };
+            let zeros = members[..count].iter().map(|m| zero_initializer(&m.ty, symbols, span));
+            return Initializer::Compound(zeros.collect());
+        }
+        Type::Function(_) => unreachable!("functions have no initializers"),
+    };
+    let zero = Expression::typed(ExpressionKind::Constant(zero), ty.clone(), span.clone());
+    Initializer::Single(zero)
+}
+
+/// Wraps `expr` in a cast unless it already has type `ty`.
+fn convert_to(expr: Expression, ty: &Type) -> Expression {
+    if expr.ty() == ty {
+        return expr;
+    }
+    let span = expr.span.clone();
+    Expression::typed(ExpressionKind::Cast(ty.clone(), Box::new(expr)), ty.clone(), span)
+}
+
+struct TypeChecker {
+    symbols: SymbolTable,
+    return_type: Option<Type>, // of the function being checked
+    switches: Vec<(Type, Vec<SwitchCase>)>, // enclosing switches, innermost last
+    frame_size: usize, // stack the function being checked needs so far, at most
+}
+
+impl TypeChecker {
+    fn new() -> Self {
+        Self {
+            symbols: SymbolTable::default(),
+            return_type: None,
+            switches: Vec::new(),
+            frame_size: 0,
+        }
+    }
+
+    /// Anything that needs the size of a type checks it here first, so
+    /// `size_of` never sees an incomplete or oversized type.
+    fn require_complete(&self, ty: &Type, span: &Span) -> Result<(), Located<TypeError>> {
+        if !self.symbols.is_complete(ty) {
+            Err(Located::new(TypeError::IncompleteType(ty.clone()), span))
+        } else if self.symbols.checked_size_of(ty).is_none() {
+            Err(Located::new(TypeError::TypeTooLarge(ty.clone()), span))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Counts a stack slot of `size` bytes against the frame of the
+    /// function being checked. Aligning the slot pads it by at most 15.
+    fn reserve_stack(&mut self, size: usize, span: &Span) -> Result<(), Located<TypeError>> {
+        self.frame_size = self.frame_size.saturating_add(size + 15);
+        if self.frame_size > MAX_OBJECT_SIZE {
+            return Err(Located::new(TypeError::FrameTooLarge, span));
+        }
+        Ok(())
+    }
+
+    /// Lays out a structure definition: each member at the next offset
+    /// suitably aligned for it (all at 0 in a union), with the size rounded
+    /// up to the strictest alignment so arrays of it stay aligned.
+    fn check_struct_declaration(
+        &mut self,
+        decl: &StructDeclaration,
+    ) -> Result<(), Located<TypeError>> {
+        let Some(member_decls) = &decl.members else {
+            return Ok(());
+        };
+
+        let mut members: Vec<Member> = Vec::new();
+        let mut size = 0;
+        let mut alignment = 1;
+        for member in member_decls {
+            if members.iter().any(|m| m.name == member.name) {
+                let error = TypeError::DuplicateMember(member.name.clone());
+                return Err(Located::new(error, &member.span));
+            }
+            self.require_complete(&member.ty, &member.span)?;
+
+            let member_alignment = self.symbols.alignment_of(&member.ty);
+            let offset = match decl.kind {
+                StructKind::Struct => round_up(size, member_alignment),
+                StructKind::Union => 0,
+            };
+            let end = offset
+                .checked_add(self.symbols.size_of(&member.ty))
+                .filter(|&end| end <= MAX_OBJECT_SIZE);
+            let Some(end) = end else {
+                let error = TypeError::TypeTooLarge(Type::Structure(decl.kind, decl.tag.clone()));
+                return Err(Located::new(error, &decl.span));
+            };
+            size = size.max(end);
+            alignment = alignment.max(member_alignment);
+
+            members.push(Member {
+                name: member.name.clone(),
+                ty: member.ty.clone(),
+                offset,
+            });
+        }
+
+        let entry = StructEntry {
+            size: round_up(size, alignment),
+            alignment,
+            members,
+        };
+        self.symbols.structures.insert(decl.tag.clone(), entry);
+        Ok(())
+    }
+
+    /// The layout of the structure an access like `s.member` refers to.
+    fn structure_of(&self, ty: &Type, span: &Span) -> Result<&StructEntry, Located<TypeError>> {
+        match ty {
+            Type::Structure(_, tag) => match self.symbols.structure(tag) {
+                Some(entry) => Ok(entry),
+                None => Err(Located::new(TypeError::IncompleteType(ty.clone()), span)),
+            },
+            _ => Err(Located::new(TypeError::NotAStructure(ty.clone()), span)),
+        }
+    }
+
+    /// Checks an expression that is tested against zero.
+    fn check_scalar(&mut self, expr: Expression) -> Result<Expression, Located<TypeError>> {
+        let expr = self.check_expression(expr)?;
+        if !expr.ty().is_scalar() {
+            return Err(Located::new(TypeError::NonScalar(expr.ty().clone()), &expr.span));
+        }
+        Ok(expr)
+    }
+
+    /// Checks the operand of a compound assignment or increment, which
+    /// must be a scalar object. Pointers must point to a complete type.
+    fn check_modifiable(&mut self, expr: Expression) -> Result<Expression, Located<TypeError>> {
+        let expr = self.check_expression_no_decay(expr)?;
+        match expr.ty() {
+            Type::Array(..) => return Err(Located::new(TypeError::ArrayAssignment, &expr.span)),
+            Type::Pointer(referenced) => self.require_complete(referenced, &expr.span)?,
+            ty if !ty.is_scalar() => {
+                return Err(Located::new(TypeError::NonScalar(ty.clone()), &expr.span));
+            }
+            _ => {}
+        }
+        Ok(expr)
+    }
+
+    fn check_function_declaration(
+        &mut self,
+        func: FunctionDeclaration,
+    ) -> Result<FunctionDeclaration, Located<TypeError>> {
+        let fun_type = Type::Function(func.fun_type.clone());
+        let has_body = func.body.is_some();
+        let mut already_defined = false;
+        let mut global = func.storage_class != Some(StorageClass::Static);
+
+        if let Some(prev) = self.symbols.get(&func.name) {
+            if prev.ty != fun_type {
+                let error = TypeError::IncompatibleDeclarations(func.name.clone());
+                return Err(Located::new(error, &func.span));
+            }
+            if let IdentifierAttrs::Function {
+                defined,
+                global: prev_global,
+            } = prev.attrs
+            {
+                if defined && has_body {
+                    let error = TypeError::FunctionRedefinition(func.name.clone());
+                    return Err(Located::new(error, &func.span));
+                }
+                // A static declaration can't follow a non-static one, but an
+                // unqualified one may follow a static one and inherits it.
+                if prev_global && func.storage_class == Some(StorageClass::Static) {
+                    let error = TypeError::ConflictingLinkage(func.name.clone());
+                    return Err(Located::new(error, &func.span));
+                }
+                already_defined = defined;
+                global = prev_global;
+            }
+        }
+
+        self.symbols.insert(
+            func.name.clone(),
+            Symbol {
+                ty: fun_type,
+                attrs: IdentifierAttrs::Function {
+                    defined: already_defined || has_body,
+                    global,
+                },
+            },
+        );
+
+        let body = match func.body {
+            Some(body) => {
+                self.require_complete(&func.fun_type.ret, &func.span)?;
+                for ty in &func.fun_type.params {
+                    self.require_complete(ty, &func.span)?;
+                }
+                self.frame_size = 0;
+                // Falling off the end returns a structure from a temporary
+                if let Type::Structure(..) = *func.fun_type.ret {
+                    self.reserve_stack(self.symbols.size_of(&func.fun_type.ret), &func.span)?;
+                }
+                for (param, ty) in func.params.iter().zip(&func.fun_type.params) {
+                    self.reserve_stack(self.symbols.size_of(ty), &func.span)?;
+                    self.symbols.add_local(param.clone(), ty.clone());
+                }
+
+                self.return_type = Some((*func.fun_type.ret).clone());
+                let body = self.check_block(body)?;
+                self.return_type = None;
+
+                Some(body)
+            }
+            None => None,
+        };
+
+        Ok(FunctionDeclaration { body, ..func })
+    }
+
+    /// Static storage initializers must be constant expressions; they are
+    /// flattened into the values laid out in memory.
+    fn static_initial_value(
+        &mut self,
+        decl: &VariableDeclaration,
+    ) -> Result<Option<Vec<StaticInit>>, Located<TypeError>> {
+        match &decl.init {
+            Some(init) => {
+                let mut values = Vec::new();
+                self.flatten_static_init(init, &decl.ty, &decl.name, &decl.span, &mut values)?;
+                Ok(Some(values))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn flatten_static_init(
+        &mut self,
+        init: &Initializer,
+        ty: &Type,
+        name: &str,
+        span: &Span,
+        values: &mut Vec<StaticInit>,
+    ) -> Result<(), Located<TypeError>> {
+        match (init, ty) {
+            (Initializer::Compound(inits), Type::Array(element, len)) => {
+                if inits.len() > *len {
+                    let error = TypeError::TooManyInitializers(source_name(name));
+                    return Err(Located::new(error, span));
+                }
+                for init in inits {
+                    self.flatten_static_init(init, element, name, span, values)?;
+                }
+                if inits.len() < *len {
+                    let element_size = self.symbols.size_of(element);
+                    values.push(StaticInit::Zero((len - inits.len()) * element_size));
+                }
+                Ok(())
+            }
+            // Padding between members and after the last one is zeroed
+            (Initializer::Compound(inits), Type::Structure(kind, tag)) => {
+                let entry = self.symbols.structure(tag).unwrap().clone();
+                if inits.len() > entry.members.len()
+                    || (*kind == StructKind::Union && inits.len() > 1)
+                {
+                    let error = TypeError::TooManyInitializers(source_name(name));
+                    return Err(Located::new(error, span));
+                }
+                let mut offset = 0;
+                for (init, member) in inits.iter().zip(&entry.members) {
+                    if member.offset > offset {
+                        values.push(StaticInit::Zero(member.offset - offset));
+                    }
+                    self.flatten_static_init(init, &member.ty, name, span, values)?;
+                    offset = member.offset + self.symbols.size_of(&member.ty);
+                }
+                if entry.size > offset {
+                    values.push(StaticInit::Zero(entry.size - offset));
+                }
+                Ok(())
+            }
+            // The null terminator is dropped when the array has no room for it
+            (
+                Initializer::Single(Expression {
+                    kind: ExpressionKind::String(bytes),
+                    ..
+                }),
+                Type::Array(element, len),
+            ) if element.is_character() => {
+                if bytes.len() > *len {
+                    let error = TypeError::StringTooLong(source_name(name));
+                    return Err(Located::new(error, span));
+                }
+                values.push(StaticInit::String(bytes.clone(), bytes.len() < *len));
+                if bytes.len() + 1 < *len {
+                    values.push(StaticInit::Zero(len - bytes.len() - 1));
+                }
+                Ok(())
+            }
+            (Initializer::Single(expr), ty) if ty.is_scalar() => match &expr.kind {
+                // Anywhere else a string stands for a pointer to a constant copy
+                ExpressionKind::String(bytes) => {
+                    let string_type = Type::Pointer(Box::new(Type::Char));
+                    if *ty != string_type {
+                        let error = TypeError::IncompatibleTypes {
+                            from: string_type,
+                            to: ty.clone(),
+                        };
+                        return Err(Located::new(error, &expr.span));
+                    }
+                    let constant = self.symbols.add_string_constant(bytes.clone());
+                    values.push(StaticInit::Pointer(constant));
+                    Ok(())
+                }
+                _ => match constant_value(expr) {
+                    Some(c) if ty.is_pointer() && !is_null_pointer_constant(&c) => {
+                        let error = TypeError::IncompatibleTypes {
+                            from: const_type(&c),
+                            to: ty.clone(),
+                        };
+                        Err(Located::new(error, &expr.span))
+                    }
+                    Some(c) => {
+                        values.push(StaticInit::from_const(c, ty));
+                        Ok(())
+                    }
+                    None => {
+                        let error = TypeError::NonConstantInitializer(source_name(name));
+                        Err(Located::new(error, &expr.span))
+                    }
+                },
+            },
+            _ => Err(Located::new(TypeError::InvalidInitializer(source_name(name)), span)),
+        }
+    }
+
+    fn check_file_scope_variable_declaration(
+        &mut self,
+        decl: VariableDeclaration,
+    ) -> Result<VariableDeclaration, Located<TypeError>> {
+        if decl.storage_class != Some(StorageClass::Extern) {
+            self.require_complete(&decl.ty, &decl.span)?;
+        }
+
+        let mut init = match self.static_initial_value(&decl)? {
+            Some(value) => InitialValue::Initial(value),
+            None if decl.storage_class == Some(StorageClass::Extern) => {
+                InitialValue::NoInitializer
+            }
+            None => InitialValue::Tentative,
+        };
+        let mut global = decl.storage_class != Some(StorageClass::Static);
+
+        if let Some(prev) = self.symbols.get(&decl.name) {
+            let IdentifierAttrs::Static {
+                init: prev_init,
+                global: prev_global,
+            } = &prev.attrs
+            else {
+                let error = TypeError::IncompatibleDeclarations(decl.name.clone());
+                return Err(Located::new(error, &decl.span));
+            };
+
+            if prev.ty != decl.ty {
+                let error = TypeError::IncompatibleDeclarations(decl.name.clone());
+                return Err(Located::new(error, &decl.span));
+            }
+
+            if decl.storage_class == Some(StorageClass::Extern) {
+                global = *prev_global;
+            } else if *prev_global != global {
+                let error = TypeError::ConflictingLinkage(decl.name.clone());
+                return Err(Located::new(error, &decl.span));
+            }
+
+            match (prev_init, &init) {
+                (InitialValue::Initial(_), InitialValue::Initial(_)) => {
+                    let error = TypeError::ConflictingDefinitions(decl.name.clone());
+                    return Err(Located::new(error, &decl.span));
+                }
+                (InitialValue::Initial(_), _) => init = prev_init.clone(),
+                (InitialValue::Tentative, InitialValue::NoInitializer) => {
+                    init = InitialValue::Tentative
+                }
+                _ => {}
+            }
+        }
+
+        self.symbols.insert(
+            decl.name.clone(),
+            Symbol {
+                ty: decl.ty.clone(),
+                attrs: IdentifierAttrs::Static { init, global },
+            },
+        );
+
+        Ok(decl)
+    }
+
+    fn check_local_variable_declaration(
+        &mut self,
+        decl: VariableDeclaration,
+    ) -> Result<VariableDeclaration, Located<TypeError>> {
+        if decl.storage_class != Some(StorageClass::Extern) {
+            self.require_complete(&decl.ty, &decl.span)?;
+        }
+
+        match decl.storage_class {
+            Some(StorageClass::Extern) => {
+                if decl.init.is_some() {
+                    let error = TypeError::ExternWithInitializer(decl.name.clone());
+                    return Err(Located::new(error, &decl.span));
+                }
+                match self.symbols.get(&decl.name) {
+                    Some(prev) if prev.ty != decl.ty => {
+                        let error = TypeError::IncompatibleDeclarations(decl.name.clone());
+                        return Err(Located::new(error, &decl.span));
+                    }
+                    Some(_) => {}
+                    None => self.symbols.insert(
+                        decl.name.clone(),
+                        Symbol {
+                            ty: decl.ty.clone(),
+                            attrs: IdentifierAttrs::Static {
+                                init: InitialValue::NoInitializer,
+                                global: true,
+                            },
+                        },
+                    ),
+                }
+                Ok(decl)
+            }
+            Some(StorageClass::Static) => {
+                // Initialized once before the program starts, zero by default
+                let init = self.static_initial_value(&decl)?
+                    .unwrap_or_else(|| vec![StaticInit::Zero(self.symbols.size_of(&decl.ty))]);
+                self.symbols.insert(
+                    decl.name.clone(),
+                    Symbol {
+                        ty: decl.ty.clone(),
+                        attrs: IdentifierAttrs::Static {
+                            init: InitialValue::Initial(init),
+                            global: false,
+                        },
+                    },
+                );
+                Ok(decl)
+            }
+            None => {
+                self.reserve_stack(self.symbols.size_of(&decl.ty), &decl.span)?;
+                self.symbols.add_local(decl.name.clone(), decl.ty.clone());
+                let init = match decl.init {
+                    Some(init) => {
+                        Some(self.check_initializer(init, &decl.ty, &decl.name, &decl.span)?)
+                    }
+                    None => None,
+                };
+                Ok(VariableDeclaration { init, ..decl })
+            }
+        }
+    }
+
+    /// Checks an automatic variable's initializer, padding array
+    /// initializers with zeros for the elements they leave out.
+    fn check_initializer(
+        &mut self,
+        init: Initializer,
+        ty: &Type,
+        name: &str,
+        span: &Span,
+    ) -> Result<Initializer, Located<TypeError>> {
+        match (init, ty) {
+            (Initializer::Compound(inits), Type::Array(element, len)) => {
+                if inits.len() > *len {
+                    let error = TypeError::TooManyInitializers(source_name(name));
+                    return Err(Located::new(error, span));
+                }
+                let mut checked = Vec::new();
+                for init in inits {
+                    checked.push(self.check_initializer(init, element, name, span)?);
+                }
+                while checked.len() < *len {
+                    checked.push(zero_initializer(element, &self.symbols, span));
+                }
+                Ok(Initializer::Compound(checked))
+            }
+            (Initializer::Compound(inits), Type::Structure(kind, tag)) => {
+                let members = self.symbols.structure(tag).unwrap().members.clone();
+                if inits.len() > members.len() || (*kind == StructKind::Union && inits.len() > 1)
+                {
+                    let error = TypeError::TooManyInitializers(source_name(name));
+                    return Err(Located::new(error, span));
+                }
+                let mut checked = Vec::new();
+                for (init, member) in inits.into_iter().zip(&members) {
+                    checked.push(self.check_initializer(init, &member.ty, name, span)?);
+                }
+                if *kind == StructKind::Struct {
+                    for member in &members[checked.len()..] {
+                        checked.push(zero_initializer(&member.ty, &self.symbols, span));
+                    }
+                }
+                Ok(Initializer::Compound(checked))
+            }
+            // A string literal fills a character array; the rest is zeroed
+            // when the initializer is emitted
+            (
+                Initializer::Single(Expression {
+                    kind: ExpressionKind::String(bytes),
+                    span,
+                    ..
+                }),
+                Type::Array(element, len),
+            ) if element.is_character() => {
+                if bytes.len() > *len {
+                    let error = TypeError::StringTooLong(source_name(name));
+                    return Err(Located::new(error, &span));
+                }
+                let string = Expression::typed(ExpressionKind::String(bytes), ty.clone(), span);
+                Ok(Initializer::Single(string))
+            }
+            (Initializer::Single(expr), ty) if !ty.is_array() => {
+                let expr = convert_by_assignment(self.check_expression(expr)?, ty)?;
+                Ok(Initializer::Single(expr))
+            }
+            _ => Err(Located::new(TypeError::InvalidInitializer(source_name(name)), span)),
+        }
+    }
+
+    fn check_block(&mut self, block: Block) -> Result<Block, Located<TypeError>> {
+        let mut items = Vec::new();
+
+        for item in block.items {
+            let item = match item {
+                BlockItem::Declaration(Declaration::Variable(decl)) => BlockItem::Declaration(
+                    Declaration::Variable(self.check_local_variable_declaration(decl)?),
+                ),
+                BlockItem::Declaration(Declaration::Function(func)) => BlockItem::Declaration(
+                    Declaration::Function(self.check_function_declaration(func)?),
+                ),
+                BlockItem::Declaration(Declaration::Struct(decl)) => {
+                    self.check_struct_declaration(&decl)?;
+                    BlockItem::Declaration(Declaration::Struct(decl))
+                }
+                BlockItem::Statement(stmt) => BlockItem::Statement(self.check_statement(stmt)?),
+            };
+            items.push(item);
+        }
+
+        Ok(Block { items })
+    }
+
+    fn check_optional_expression(
+        &mut self,
+        expr: Option<Expression>,
+    ) -> Result<Option<Expression>, Located<TypeError>> {
+        match expr {
+            Some(expr) => Ok(Some(self.check_expression(expr)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn check_statement(&mut self, stmt: Statement) -> Result<Statement, Located<TypeError>> {
+        let Statement { kind, span } = stmt;
+        let kind = match kind {
+            StatementKind::Return(expr) => {
+                let ret = self
+                    .return_type
+                    .clone()
+                    .expect("return statement outside of a function");
+                StatementKind::Return(convert_by_assignment(self.check_expression(expr)?, &ret)?)
+            }
+            StatementKind::Expression(expr) => {
+                StatementKind::Expression(self.check_expression(expr)?)
+            }
+            StatementKind::If(cond, then_stmt, else_stmt) => {
+                let cond = self.check_scalar(cond)?;
+                let then_stmt = self.check_statement(*then_stmt)?;
+                let else_stmt = match else_stmt {
+                    Some(stmt) => Some(Box::new(self.check_statement(*stmt)?)),
+                    None => None,
+                };
+                StatementKind::If(cond, Box::new(then_stmt), else_stmt)
+            }
+            StatementKind::Compound(block) => StatementKind::Compound(self.check_block(block)?),
+            StatementKind::While(cond, body, label) => {
+                let cond = self.check_scalar(cond)?;
+                let body = self.check_statement(*body)?;
+                StatementKind::While(cond, Box::new(body), label)
+            }
+            StatementKind::DoWhile(body, cond, label) => {
+                let body = self.check_statement(*body)?;
+                let cond = self.check_scalar(cond)?;
+                StatementKind::DoWhile(Box::new(body), cond, label)
+            }
+            StatementKind::For(init, cond, post, body, label) => {
+                let init = match *init {
+                    ForInit::Declaration(decl) => {
+                        ForInit::Declaration(self.check_local_variable_declaration(decl)?)
+                    }
+                    ForInit::Expression(expr) => {
+                        ForInit::Expression(self.check_optional_expression(expr)?)
+                    }
+                };
+                let cond = match cond {
+                    Some(cond) => Some(self.check_scalar(cond)?),
+                    None => None,
+                };
+                let post = self.check_optional_expression(post)?;
+                let body = self.check_statement(*body)?;
+                StatementKind::For(Box::new(init), cond, post, Box::new(body), label)
+            }
+            // Cases are collected as the body is checked
+            StatementKind::Switch(cond, body, label, _) => {
+                let cond = self.check_expression(cond)?;
+                if !cond.ty().is_integer() {
+                    let error = TypeError::InvalidSwitch(cond.ty().clone());
+                    return Err(Located::new(error, &cond.span));
+                }
+                let ty = promote(cond.ty());
+                let cond = convert_to(cond, &ty);
+
+                self.switches.push((ty, Vec::new()));
+                let body = self.check_statement(*body)?;
+                let (_, cases) = self.switches.pop().unwrap();
+
+                StatementKind::Switch(cond, Box::new(body), label, cases)
+            }
+            StatementKind::Case(value, stmt, label) => {
+                let value = self.check_expression(value)?;
+                let span = value.span.clone();
+                let Some(value) = integer_constant(&value) else {
+                    return Err(Located::new(TypeError::NonConstantCase, &span));
+                };
+
+                let (ty, cases) = self
+                    .switches
+                    .last_mut()
+                    .expect("case labels are checked during semantic analysis");
+                let value = convert_const(value, ty);
+                if cases.iter().any(|case| case.value == Some(value)) {
+                    return Err(Located::new(TypeError::DuplicateCase(value), &span));
+                }
+                cases.push(SwitchCase {
+                    value: Some(value),
+                    label: label.clone(),
+                });
+                let value = Expression::typed(ExpressionKind::Constant(value), ty.clone(), span);
+
+                let stmt = self.check_statement(*stmt)?;
+                StatementKind::Case(value, Box::new(stmt), label)
+            }
+            StatementKind::Default(stmt, label) => {
+                let (_, cases) = self
+                    .switches
+                    .last_mut()
+                    .expect("default labels are checked during semantic analysis");
+                if cases.iter().any(|case| case.value.is_none()) {
+                    return Err(Located::new(TypeError::DuplicateDefault, &span));
+                }
+                cases.push(SwitchCase {
+                    value: None,
+                    label: label.clone(),
+                });
+
+                let stmt = self.check_statement(*stmt)?;
+                StatementKind::Default(Box::new(stmt), label)
+            }
+            StatementKind::Labeled(label, stmt) => {
+                StatementKind::Labeled(label, Box::new(self.check_statement(*stmt)?))
+            }
+            kind @ (StatementKind::Break(_)
+            | StatementKind::Continue(_)
+            | StatementKind::Goto(_)
+            | StatementKind::Null) => kind,
+        };
+
+        Ok(Statement::new(kind, span))
+    }
+
+    /// Checks an expression used as a value, where an array stands for a
+    /// pointer to its first element.
+    fn check_expression(&mut self, expr: Expression) -> Result<Expression, Located<TypeError>> {
+        let expr = self.check_expression_no_decay(expr)?;
+        match expr.ty() {
+            Type::Array(element, _) => {
+                let ty = Type::Pointer(element.clone());
+                let span = expr.span.clone();
+                Ok(Expression::typed(ExpressionKind::AddressOf(Box::new(expr)), ty, span))
+            }
+            _ => Ok(expr),
+        }
+    }
+
+    /// Evaluating an expression may take temporaries: one for a structure
+    /// value (a variable is its own storage), and at most two scalars.
+    fn check_expression_no_decay(
+        &mut self,
+        expr: Expression,
+    ) -> Result<Expression, Located<TypeError>> {
+        let expr = self.check_expression_kind(expr)?;
+        match (&expr.kind, expr.ty()) {
+            (ExpressionKind::Var(_), _) => {}
+            (_, ty @ Type::Structure(..)) => {
+                self.reserve_stack(self.symbols.size_of(ty), &expr.span)?;
+            }
+            _ => self.reserve_stack(2 * 8, &expr.span)?,
+        }
+        Ok(expr)
+    }
+
+    fn check_expression_kind(
+        &mut self,
+        expr: Expression,
+    ) -> Result<Expression, Located<TypeError>> {
+        let span = expr.span;
+        match expr.kind {
+            ExpressionKind::Constant(c) => {
+                Ok(Expression::typed(ExpressionKind::Constant(c), const_type(&c), span))
+            }
+
+            // The array includes the null terminator
+            ExpressionKind::String(bytes) => {
+                let ty = Type::Array(Box::new(Type::Char), bytes.len() + 1);
+                Ok(Expression::typed(ExpressionKind::String(bytes), ty, span))
+            }
+
+            ExpressionKind::Var(name) => {
+                let ty = self.symbols.type_of(&name).clone();
+                if let Type::Function(_) = ty {
+                    return Err(Located::new(TypeError::FunctionUsedAsVariable(name), &span));
+                }
+                Ok(Expression::typed(ExpressionKind::Var(name), ty, span))
+            }
+
+            ExpressionKind::Cast(ty, inner) => {
+                let inner = self.check_expression(*inner)?;
+                // Pointers and doubles can't be converted into each other,
+                // and only scalars can be cast to scalars
+                if (ty.is_pointer() && *inner.ty() == Type::Double)
+                    || (ty == Type::Double && inner.ty().is_pointer())
+                    || !ty.is_scalar()
+                    || !inner.ty().is_scalar()
+                {
+                    let error = TypeError::InvalidCast {
+                        from: inner.ty().clone(),
+                        to: ty,
+                    };
+                    return Err(Located::new(error, &span));
+                }
+                Ok(Expression::typed(
+                    ExpressionKind::Cast(ty.clone(), Box::new(inner)),
+                    ty,
+                    span,
+                ))
+            }
+
+            ExpressionKind::FunctionCall(name, args) => {
+                let fun_type = match self.symbols.type_of(&name) {
+                    Type::Function(fun_type) => fun_type.clone(),
+                    _ => {
+                        let error = TypeError::CallToVariable(source_name(&name));
+                        return Err(Located::new(error, &span));
+                    }
+                };
+
+                self.require_complete(&fun_type.ret, &span)?;
+                if fun_type.params.len() != args.len() {
+                    let error = TypeError::WrongArgumentCount {
+                        name,
+                        expected: fun_type.params.len(),
+                        found: args.len(),
+                    };
+                    return Err(Located::new(error, &span));
+                }
+
+                // Arguments are converted to the parameter types as if by assignment
+                let mut checked_args = Vec::new();
+                for (arg, param_type) in args.into_iter().zip(&fun_type.params) {
+                    let arg = self.check_expression(arg)?;
+                    checked_args.push(convert_by_assignment(arg, param_type)?);
+                }
+
+                Ok(Expression::typed(
+                    ExpressionKind::FunctionCall(name, checked_args),
+                    *fun_type.ret,
+                    span,
+                ))
+            }
+
+            ExpressionKind::Assignment(lvalue, value) => {
+                let lvalue = self.check_expression_no_decay(*lvalue)?;
+                if lvalue.ty().is_array() {
+                    return Err(Located::new(TypeError::ArrayAssignment, &lvalue.span));
+                }
+                let ty = lvalue.ty().clone();
+                let value = convert_by_assignment(self.check_expression(*value)?, &ty)?;
+
+                Ok(Expression::typed(
+                    ExpressionKind::Assignment(Box::new(lvalue), Box::new(value)),
+                    ty,
+                    span,
+                ))
+            }
+
+            // The value is converted to the type `lvalue op value` would be
+            // computed in: an offset of type long for a pointer, the promoted
+            // type of the lvalue for a shift, and the common type otherwise.
+            // The result is converted back to the type of the lvalue.
+            ExpressionKind::CompoundAssignment(op, lvalue, value) => {
+                let lvalue = self.check_modifiable(*lvalue)?;
+                let value = self.check_scalar(*value)?;
+                check_operand_types(&op, lvalue.ty(), value.ty())
+                    .map_err(|e| Located::new(e, &span))?;
+
+                let ty = lvalue.ty().clone();
+                let operation_type = if ty.is_pointer() {
+                    if !value.ty().is_integer() {
+                        let error = TypeError::IncompatibleOperands(ty, value.ty().clone());
+                        return Err(Located::new(error, &span));
+                    }
+                    Type::Long
+                } else if value.ty().is_pointer() {
+                    let error = TypeError::IncompatibleTypes {
+                        from: value.ty().clone(),
+                        to: ty,
+                    };
+                    return Err(Located::new(error, &span));
+                } else if matches!(op, BinOp::LeftShift | BinOp::RightShift) {
+                    promote(&ty)
+                } else {
+                    common_type(&ty, value.ty())
+                };
+                let value = convert_to(value, &operation_type);
+
+                Ok(Expression::typed(
+                    ExpressionKind::CompoundAssignment(op, Box::new(lvalue), Box::new(value)),
+                    ty,
+                    span,
+                ))
+            }
+
+            ExpressionKind::PostfixIncrement(inner) => {
+                let inner = self.check_modifiable(*inner)?;
+                let ty = inner.ty().clone();
+                let kind = ExpressionKind::PostfixIncrement(Box::new(inner));
+                Ok(Expression::typed(kind, ty, span))
+            }
+            ExpressionKind::PostfixDecrement(inner) => {
+                let inner = self.check_modifiable(*inner)?;
+                let ty = inner.ty().clone();
+                let kind = ExpressionKind::PostfixDecrement(Box::new(inner));
+                Ok(Expression::typed(kind, ty, span))
+            }
+
+            ExpressionKind::Conditional(cond, then_expr, else_expr) => {
+                let cond = self.check_scalar(*cond)?;
+                let then_expr = self.check_expression(*then_expr)?;
+                let else_expr = self.check_expression(*else_expr)?;
+
+                let ty = if then_expr.ty().is_pointer() || else_expr.ty().is_pointer() {
+                    common_pointer_type(&then_expr, &else_expr)?
+                } else if then_expr.ty().is_structure() || else_expr.ty().is_structure() {
+                    if then_expr.ty() != else_expr.ty() {
+                        let error = TypeError::IncompatibleOperands(
+                            then_expr.ty().clone(),
+                            else_expr.ty().clone(),
+                        );
+                        return Err(Located::new(error, &span));
+                    }
+                    then_expr.ty().clone()
+                } else {
+                    common_type(then_expr.ty(), else_expr.ty())
+                };
+                let then_expr = convert_to(then_expr, &ty);
+                let else_expr = convert_to(else_expr, &ty);
+
+                Ok(Expression::typed(
+                    ExpressionKind::Conditional(
+                        Box::new(cond),
+                        Box::new(then_expr),
+                        Box::new(else_expr),
+                    ),
+                    ty,
+                    span,
+                ))
+            }
+
+            ExpressionKind::UnaryOp((op, inner)) => {
+                let inner = self.check_scalar(*inner)?;
+                if op == UnOp::BitwiseComplement && *inner.ty() == Type::Double {
+                    return Err(Located::new(TypeError::InvalidDoubleOperand("~"), &span));
+                }
+                if inner.ty().is_pointer() {
+                    match op {
+                        UnOp::Negation => {
+                            return Err(Located::new(TypeError::InvalidPointerOperand("-"), &span));
+                        }
+                        UnOp::BitwiseComplement => {
+                            return Err(Located::new(TypeError::InvalidPointerOperand("~"), &span));
+                        }
+                        UnOp::LogicalNegation => {}
+                    }
+                }
+                let (inner, ty) = match op {
+                    UnOp::LogicalNegation => (inner, Type::Int),
+                    UnOp::Negation | UnOp::BitwiseComplement => {
+                        let ty = promote(inner.ty());
+                        (convert_to(inner, &ty), ty)
+                    }
+                };
+                Ok(Expression::typed(
+                    ExpressionKind::UnaryOp((op, Box::new(inner))),
+                    ty,
+                    span,
+                ))
+            }
+
+            ExpressionKind::BinaryOp(op, left, right) => {
+                let left = self.check_scalar(*left)?;
+                let right = self.check_scalar(*right)?;
+                check_operand_types(&op, left.ty(), right.ty())
+                    .map_err(|e| Located::new(e, &span))?;
+
+                let (left, right, ty) = match op {
+                    BinOp::Add | BinOp::Subtract
+                        if left.ty().is_pointer() || right.ty().is_pointer() =>
+                    {
+                        for ty in [left.ty(), right.ty()] {
+                            if let Type::Pointer(referenced) = ty {
+                                self.require_complete(referenced, &span)?;
+                            }
+                        }
+                        pointer_arithmetic(&op, left, right)?
+                    }
+
+                    // Operands are only tested against zero
+                    BinOp::LogicalAnd | BinOp::LogicalOr => (left, right, Type::Int),
+
+                    // The result has the (promoted) type of the left operand; the
+                    // shift count is converted too so both operands share a size.
+                    BinOp::LeftShift | BinOp::RightShift => {
+                        let ty = promote(left.ty());
+                        (convert_to(left, &ty), convert_to(right, &ty), ty)
+                    }
+
+                    BinOp::Equal | BinOp::NotEqual => {
+                        let common = if left.ty().is_pointer() || right.ty().is_pointer() {
+                            common_pointer_type(&left, &right)?
+                        } else {
+                            common_type(left.ty(), right.ty())
+                        };
+                        (convert_to(left, &common), convert_to(right, &common), Type::Int)
+                    }
+
+                    // Only pointers of the same type can be ordered
+                    BinOp::LessThan
+                    | BinOp::LessThanEqual
+                    | BinOp::GreaterThan
+                    | BinOp::GreaterThanEqual => {
+                        if (left.ty().is_pointer() || right.ty().is_pointer())
+                            && left.ty() != right.ty()
+                        {
+                            let error = TypeError::IncompatibleOperands(
+                                left.ty().clone(),
+                                right.ty().clone(),
+                            );
+                            return Err(Located::new(error, &span));
+                        }
+                        let common = common_type(left.ty(), right.ty());
+                        (convert_to(left, &common), convert_to(right, &common), Type::Int)
+                    }
+
+                    BinOp::Add
+                    | BinOp::Subtract
+                    | BinOp::Multiply
+                    | BinOp::Divide
+                    | BinOp::Remainder
+                    | BinOp::BitwiseAnd
+                    | BinOp::BitwiseOr
+                    | BinOp::BitwiseXor => {
+                        let common = common_type(left.ty(), right.ty());
+                        (
+                            convert_to(left, &common),
+                            convert_to(right, &common),
+                            common,
+                        )
+                    }
+                };
+
+                Ok(Expression::typed(
+                    ExpressionKind::BinaryOp(op, Box::new(left), Box::new(right)),
+                    ty,
+                    span,
+                ))
+            }
+
+            ExpressionKind::Dereference(inner) => {
+                let inner = self.check_expression(*inner)?;
+                let ty = match inner.ty() {
+                    Type::Pointer(referenced) => (**referenced).clone(),
+                    ty => {
+                        let error = TypeError::DereferenceNonPointer(ty.clone());
+                        return Err(Located::new(error, &inner.span));
+                    }
+                };
+                self.require_complete(&ty, &span)?;
+                let kind = ExpressionKind::Dereference(Box::new(inner));
+                Ok(Expression::typed(kind, ty, span))
+            }
+
+            ExpressionKind::AddressOf(inner) => {
+                let inner = self.check_expression_no_decay(*inner)?;
+                let ty = Type::Pointer(Box::new(inner.ty().clone()));
+                Ok(Expression::typed(ExpressionKind::AddressOf(Box::new(inner)), ty, span))
+            }
+
+            // Either operand may be the pointer: a[1] and 1[a] are the same
+            ExpressionKind::Subscript(array, index) => {
+                let array = self.check_expression(*array)?;
+                let index = self.check_expression(*index)?;
+
+                let (array, index) = if array.ty().is_pointer() && index.ty().is_integer() {
+                    (array, convert_to(index, &Type::Long))
+                } else if array.ty().is_integer() && index.ty().is_pointer() {
+                    (convert_to(array, &Type::Long), index)
+                } else {
+                    let error = TypeError::InvalidSubscript(array.ty().clone(), index.ty().clone());
+                    return Err(Located::new(error, &span));
+                };
+
+                let ty = match (array.ty(), index.ty()) {
+                    (Type::Pointer(element), _) | (_, Type::Pointer(element)) => {
+                        (**element).clone()
+                    }
+                    _ => unreachable!(),
+                };
+                self.require_complete(&ty, &span)?;
+                Ok(Expression::typed(
+                    ExpressionKind::Subscript(Box::new(array), Box::new(index)),
+                    ty,
+                    span,
+                ))
+            }
+
+            ExpressionKind::Member(inner, name) => {
+                let inner = self.check_expression(*inner)?;
+                let ty = match self.structure_of(inner.ty(), &span)?.member(&name) {
+                    Some(member) => member.ty.clone(),
+                    None => {
+                        let error = TypeError::UnknownMember(inner.ty().clone(), name);
+                        return Err(Located::new(error, &span));
+                    }
+                };
+                let kind = ExpressionKind::Member(Box::new(inner), name);
+                Ok(Expression::typed(kind, ty, span))
+            }
+
+            ExpressionKind::PointerMember(inner, name) => {
+                let inner = self.check_expression(*inner)?;
+                let Type::Pointer(referenced) = inner.ty() else {
+                    let error = TypeError::DereferenceNonPointer(inner.ty().clone());
+                    return Err(Located::new(error, &inner.span));
+                };
+                let ty = match self.structure_of(referenced, &span)?.member(&name) {
+                    Some(member) => member.ty.clone(),
+                    None => {
+                        let error = TypeError::UnknownMember((**referenced).clone(), name);
+                        return Err(Located::new(error, &span));
+                    }
+                };
+                let kind = ExpressionKind::PointerMember(Box::new(inner), name);
+                Ok(Expression::typed(kind, ty, span))
+            }
+        }
+    }
+}
+
+/// Type checks the program, making every implicit conversion an explicit
+/// cast, and returns it with the symbol table built along the way.
+pub fn check(program: Program) -> Result<(Program, SymbolTable), Located<TypeError>> {
+    let mut checker = TypeChecker::new();
+    let mut declarations = Vec::new();
+
+    for decl in program.declarations {
+        let decl = match decl {
+            Declaration::Function(func) => {
+                Declaration::Function(checker.check_function_declaration(func)?)
+            }
+            Declaration::Variable(decl) => {
+                Declaration::Variable(checker.check_file_scope_variable_declaration(decl)?)
+            }
+            Declaration::Struct(decl) => {
+                checker.check_struct_declaration(&decl)?;
+                Declaration::Struct(decl)
+            }
+        };
+        declarations.push(decl);
+    }
+
+    Ok((Program { declarations }, checker.symbols))
+}
@@ -0,0 +1,56 @@
+//! Tests of the library API: what a compilation hands back for each pass.
+
+use std::fs;
+
+use compiler_from_scratch::ast::Const;
+use compiler_from_scratch::ir::{Instruction, TopLevel, Val};
+use compiler_from_scratch::lexer::Token;
+use compiler_from_scratch::{CompileError, compile_file, compile_str};
+
+#[test]
+fn compilation_keeps_every_pass_output() {
+    let path = std::env::temp_dir().join(format!("api-test-{}.c", std::process::id()));
+    fs::write(&path, "int main(void) { return 2; }\n").unwrap();
+    let compilation = compile_file(&path).unwrap_or_else(|e| panic!("{}", e));
+
+    let tokens: Vec<&Token> = compilation.tokens.iter().map(|t| &t.token).collect();
+    assert_eq!(
+        tokens,
+        [
+            &Token::IntKeyword,
+            &Token::Identifier("main".to_string()),
+            &Token::OpenParen,
+            &Token::VoidKeyword,
+            &Token::CloseParen,
+            &Token::OpenBrace,
+            &Token::ReturnKeyword,
+            &Token::IntegerLiteral(2),
+            &Token::Semicolon,
+            &Token::CloseBrace,
+        ]
+    );
+    assert_eq!(compilation.tokens[7].span.to_string(), format!("{}:1:25", path.display()));
+
+    assert_eq!(compilation.ast.declarations.len(), 1);
+
+    let [TopLevel::Function(main)] = &compilation.ir.top_level[..] else {
+        panic!("expected just main, got {:?}", compilation.ir.top_level);
+    };
+    assert_eq!(main.name, "main");
+    assert!(main.global);
+    assert!(main.params.is_empty());
+    // Followed by the return every function falls back on
+    assert!(matches!(
+        main.instructions[0],
+        Instruction::Return(Val::Constant(Const::Int(2)))
+    ));
+
+    assert!(compilation.assembly.contains(".globl \"main\""));
+}
+
+#[test]
+fn errors_name_the_source() {
+    let error = compile_str("int main(void) {\n  return x;\n}\n", "<stdin>").unwrap_err();
+    assert!(matches!(error, CompileError::Semantic(_)));
+    assert_eq!(error.to_string(), "<stdin>:2:10: use of undeclared variable 'x'");
+}
@@ -0,0 +1,44 @@
+//! Tests of the command line when the program comes from stdin (`-`).
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn compile_stdin(source: &str) -> Output {
+    let mut compiler = Command::new(env!("CARGO_BIN_EXE_compiler-from-scratch"))
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    compiler.stdin.take().unwrap().write_all(source.as_bytes()).unwrap();
+    compiler.wait_with_output().unwrap()
+}
+
+#[test]
+fn stdin_compiles_to_stdout() {
+    let output = compile_stdin("int main(void) { return 3; }\n");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // Nothing but the assembly is printed, so it assembles as is
+    let dir = std::env::temp_dir().join(format!("cli-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let asm_path = dir.join("stdin.s");
+    let exe_path = dir.join("stdin");
+    fs::write(&asm_path, &output.stdout).unwrap();
+    let gcc = Command::new("gcc").arg(&asm_path).arg("-o").arg(&exe_path).output().unwrap();
+    assert!(gcc.status.success(), "{}", String::from_utf8_lossy(&gcc.stderr));
+    assert_eq!(Command::new(&exe_path).status().unwrap().code(), Some(3));
+}
+
+#[test]
+fn stdin_errors_quote_the_source() {
+    let output = compile_stdin("int main(void) {\n  return x;\n}\n");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--> <stdin>:2:10"), "{}", stderr);
+    assert!(stderr.contains("2 |   return x;"), "{}", stderr);
+}
@@ -0,0 +1,59 @@
+//! Compiles each C program in `tests/programs` with the compiler, which
+//! links it with gcc, and runs it. A program signals success by exiting
+//! with status 0; any other status says which of its checks failed.
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+fn run(name: &str) {
+    // The compiler writes the assembly and executable next to its input
+    let dir = std::env::temp_dir().join(format!("compiler-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join(name);
+    let original = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs").join(name);
+    fs::copy(original, &source).unwrap();
+
+    let compiler = Command::new(env!("CARGO_BIN_EXE_compiler-from-scratch"))
+        .arg(&source)
+        .output()
+        .unwrap();
+    assert!(compiler.status.success(), "{}", String::from_utf8_lossy(&compiler.stderr));
+
+    let status = Command::new(dir.join(name.trim_end_matches(".c"))).status().unwrap();
+    assert_eq!(status.code(), Some(0), "{} failed", name);
+}
+
+/// Symbols may share names with registers and assembler operators.
+#[test]
+fn register_names() {
+    run("register_names.c");
+}
+
+/// Static initializers may negate, complement and cast constants.
+#[test]
+fn negative_globals() {
+    run("negative_globals.c");
+}
+
+/// Structures are passed and returned by value as System V classifies
+/// them: in integer registers, SSE registers, a mix of both, or in memory,
+/// including arguments that run out of registers and go on the stack.
+#[test]
+fn struct_abi() {
+    run("struct_abi.c");
+}
+
+/// Dense cases become a jump table and sparse ones a binary search, for
+/// values around and between the cases, every width of controlling
+/// expression, and cases that fall through.
+#[test]
+fn switch() {
+    run("switch.c");
+}
+
+/// Case labels and static initializers may use any operator on constants.
+#[test]
+fn constant_expressions() {
+    run("constant_expressions.c");
+}
@@ -0,0 +1,45 @@
+int sum = 1 + 2 * 3;
+long shifted = 1L << 40;
+unsigned int wrapped = 0u - 1;
+int int_min = -2147483647 - 1;
+double half = 1 / 2.0;
+int truth = !0 && (2 > 1 || 0);
+long picked = 1 ? 10 : 20L;
+
+int classify(int x) {
+    switch (x) {
+    case 1 + 1:
+        return 1;
+    case 'a' + 1:
+        return 2;
+    case 1 << 3:
+        return 3;
+    case -2147483647 - 1:
+        return 4;
+    case (7 % 4) * (10 / 3) | 16:
+        return 5;
+    case 2 == 2 ? 100 : 200:
+        return 6;
+    default:
+        return 0;
+    }
+}
+
+int main(void) {
+    if (sum != 7) return 1;
+    if (shifted != 1099511627776) return 2;
+    if (wrapped != 4294967295u) return 3;
+    if (int_min >= 0 || int_min + 2147483647 != -1) return 4;
+    if (half != 0.5) return 5;
+    if (truth != 1) return 6;
+    if (picked != 10) return 7;
+
+    if (classify(2) != 1) return 8;
+    if (classify('b') != 2) return 9;
+    if (classify(8) != 3) return 10;
+    if (classify(-2147483647 - 1) != 4) return 11;
+    if (classify(25) != 5) return 12;
+    if (classify(100) != 6) return 13;
+    if (classify(3) != 0) return 14;
+    return 0;
+}
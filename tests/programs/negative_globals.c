@@ -0,0 +1,31 @@
+int g = -1;
+static int z = ~-4;
+long l = -2147483649;
+long m = -(long)2;
+unsigned int u = -1;
+unsigned long ul = ~0u;
+double d = -1.5;
+double e = (double)-4;
+int *null = -0;
+long longs[3] = {-1, ~0, -(long)2};
+double doubles[2] = {-0.5, (double)-4};
+char c = -3;
+unsigned char uc = -(char)1;
+
+int main(void) {
+    static int s = -5;
+
+    if (g != -1) return 1;
+    if (z != 3) return 2;
+    if (s != -5) return 3;
+    if (l != -2147483649) return 4;
+    if (m != -2) return 5;
+    if (u != 4294967295u) return 6;
+    if (ul != 4294967295u) return 7;
+    if (d != -1.5 || e != -4.0) return 8;
+    if (null) return 9;
+    if (longs[0] != -1 || longs[1] != -1 || longs[2] != -2) return 10;
+    if (doubles[0] != -0.5 || doubles[1] != -4.0) return 11;
+    if (c != -3 || uc != 255) return 12;
+    return 0;
+}
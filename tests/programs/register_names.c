@@ -0,0 +1,53 @@
+int rax(int a) {
+    return a + 1;
+}
+
+int sp(int and) {
+    return and * 2;
+}
+
+int gt(int a, int b) {
+    return a > b;
+}
+
+int offset(void) {
+    return 7;
+}
+
+int and(int ptr, int rip) {
+    return ptr && rip;
+}
+
+int rsp = 3;
+static int rbp;
+long xmm0 = 5;
+double st = 1.5;
+int al[3] = {1, 2, 3};
+
+struct pair {
+    int a;
+    int b;
+};
+struct pair rcx = {1, 2};
+
+int main(void) {
+    static int eax = 4;
+
+    if (rax(1) != 2) return 1;
+    if (sp(3) != 6) return 2;
+    if (gt(2, 1) != 1 || gt(1, 2) != 0) return 3;
+    if (offset() != 7) return 4;
+    if (and(1, 1) != 1 || and(1, 0) != 0) return 5;
+
+    rbp = rsp + eax;
+    if (rbp != 7) return 6;
+    xmm0 = xmm0 * rbp;
+    if (xmm0 != 35) return 7;
+    if (st * 2 != 3.0) return 8;
+    int *rdi = &rsp;
+    if (*rdi != 3) return 9;
+    al[2] = al[0] + al[1];
+    if (al[2] != 3) return 10;
+    if (rcx.a != 1 || rcx.b != 2) return 11;
+    return 0;
+}
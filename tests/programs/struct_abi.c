@@ -0,0 +1,105 @@
+struct three {
+    char c[3];
+};
+
+struct five {
+    char c[5];
+};
+
+struct twelve {
+    int i[3];
+};
+
+struct twenty {
+    int i[5];
+};
+
+struct double_int {
+    double d;
+    int i;
+};
+
+struct char_double {
+    char c;
+    double d;
+};
+
+struct three next_three(struct three s) {
+    struct three result = {{s.c[0] + 1, s.c[1] + 1, s.c[2] + 1}};
+    return result;
+}
+
+struct five next_five(struct five s) {
+    struct five result = {{s.c[0] + 1, s.c[1] + 1, s.c[2] + 1, s.c[3] + 1, s.c[4] + 1}};
+    return result;
+}
+
+struct twelve next_twelve(struct twelve s) {
+    struct twelve result = {{s.i[0] + 1, s.i[1] + 1, s.i[2] + 1}};
+    return result;
+}
+
+struct twenty next_twenty(int step, struct twenty s) {
+    struct twenty result;
+    for (int i = 0; i < 5; i = i + 1)
+        result.i[i] = s.i[i] + step;
+    return result;
+}
+
+struct double_int swap_double_int(struct double_int s) {
+    struct double_int result = {s.i, s.d};
+    return result;
+}
+
+struct char_double swap_char_double(struct char_double s) {
+    struct char_double result = {s.d, s.c};
+    return result;
+}
+
+double sum_mixed(struct double_int a, struct double_int b, struct double_int c,
+                 struct double_int d, struct double_int e, struct double_int f,
+                 struct double_int g, struct char_double h, struct double_int i,
+                 struct double_int j) {
+    return a.d + a.i + b.d + b.i + c.d + c.i + d.d + d.i + e.d + e.i + f.d + f.i
+        + g.d + g.i + h.c + h.d + i.d + i.i + j.d + j.i;
+}
+
+long split(long a, long b, long c, long d, long e, struct twelve t, long last) {
+    return a + b + c + d + e + t.i[0] + t.i[1] + t.i[2] + last;
+}
+
+int main(void) {
+    struct three three = {{1, 2, 3}};
+    struct five five = {{1, 2, 3, 4, 5}};
+    struct twelve twelve = {{10, 20, 30}};
+    struct twenty twenty = {{1, 2, 3, 4, 5}};
+    struct double_int double_int = {1.5, 2};
+    struct char_double char_double = {'a', 0.25};
+
+    three = next_three(three);
+    if (three.c[0] != 2 || three.c[1] != 3 || three.c[2] != 4) return 1;
+
+    five = next_five(five);
+    for (int i = 0; i < 5; i = i + 1)
+        if (five.c[i] != i + 2) return 2;
+
+    twelve = next_twelve(twelve);
+    if (twelve.i[0] != 11 || twelve.i[1] != 21 || twelve.i[2] != 31) return 3;
+
+    twenty = next_twenty(10, twenty);
+    for (int i = 0; i < 5; i = i + 1)
+        if (twenty.i[i] != i + 11) return 4;
+
+    double_int = swap_double_int(double_int);
+    if (double_int.d != 2.0 || double_int.i != 1) return 5;
+
+    char_double = swap_char_double(char_double);
+    if (char_double.c != 0 || char_double.d != 97.0) return 6;
+
+    struct double_int m = {0.5, 1};
+    struct char_double h = {3, 0.25};
+    if (sum_mixed(m, m, m, m, m, m, m, h, m, m) != 16.75) return 7;
+
+    if (split(1, 2, 3, 4, 5, twelve, 100) != 178) return 8;
+    return 0;
+}
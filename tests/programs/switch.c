@@ -0,0 +1,101 @@
+int dense(int x) {
+    switch (x) {
+    case 3:
+        return 30;
+    case 4:
+        return 40;
+    case 5:
+        return 50;
+    case 7:
+        return 70;
+    case 8:
+        return 80;
+    default:
+        return -1;
+    }
+}
+
+int sparse(long x) {
+    switch (x) {
+    case -9000000000L:
+        return 1;
+    case -5:
+        return 2;
+    case 0:
+        return 3;
+    case 1000:
+        return 4;
+    case 70000:
+        return 5;
+    case 9000000000L:
+        return 6;
+    }
+    return 0;
+}
+
+int near_max(unsigned int x) {
+    switch (x) {
+    case 4294967295u:
+        return 1;
+    case 4294967294u:
+        return 2;
+    case 4294967293u:
+        return 3;
+    case 0u:
+        return 4;
+    default:
+        return 0;
+    }
+}
+
+int letter(char c) {
+    switch (c) {
+    case 'a':
+        return 1;
+    case 'b':
+        return 2;
+    case -1:
+        return 3;
+    default:
+        return 0;
+    }
+}
+
+int count_down(int x) {
+    int n = 0;
+    switch (x) {
+    case 3:
+        n = n + 1;
+    case 2:
+        n = n + 1;
+    default:
+        n = n + 10;
+    case 1:
+        n = n + 1;
+        break;
+    case 0:
+        n = 100;
+    }
+    return n;
+}
+
+int main(void) {
+    if (dense(3) != 30 || dense(5) != 50 || dense(8) != 80) return 1;
+    if (dense(6) != -1) return 2;
+    if (dense(2) != -1 || dense(-2147483647 - 1) != -1) return 3;
+    if (dense(9) != -1 || dense(2147483647) != -1) return 4;
+
+    if (sparse(-9000000000L) != 1 || sparse(-5) != 2 || sparse(0) != 3) return 5;
+    if (sparse(1000) != 4 || sparse(70000) != 5 || sparse(9000000000L) != 6) return 6;
+    if (sparse(1) != 0 || sparse(-6) != 0 || sparse(9000000001L) != 0) return 7;
+
+    if (near_max(4294967295u) != 1 || near_max(-2) != 2 || near_max(4294967293u) != 3) return 8;
+    if (near_max(0) != 4 || near_max(4294967292u) != 0 || near_max(1) != 0) return 9;
+
+    if (letter('a') != 1 || letter('b') != 2 || letter(-1) != 3) return 10;
+    if (letter('c') != 0 || letter(255) != 3) return 11;
+
+    if (count_down(3) != 13 || count_down(2) != 12 || count_down(1) != 1) return 12;
+    if (count_down(0) != 100 || count_down(7) != 11) return 13;
+    return 0;
+}